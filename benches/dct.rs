@@ -0,0 +1,35 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use jpeg_visualizer::dct::DctAlgorithm;
+use strum::IntoEnumIterator;
+
+fn test_block() -> [[i16; 8]; 8] {
+    let mut block = [[0_i16; 8]; 8];
+    for (y, row) in block.iter_mut().enumerate() {
+        for (x, value) in row.iter_mut().enumerate() {
+            *value = ((x * 23 + y * 11 + x * y * 7) % 256) as i16;
+        }
+    }
+    block
+}
+
+fn dct_benchmark(c: &mut Criterion) {
+    let spatial = test_block();
+    let freq = DctAlgorithm::Reference
+        .transform()
+        .spatial_to_freq(&spatial);
+
+    let mut group = c.benchmark_group("dct");
+    for dct_algorithm in DctAlgorithm::iter() {
+        let transform = dct_algorithm.transform();
+        group.bench_function(format!("{} forward", dct_algorithm.name()), |b| {
+            b.iter(|| transform.spatial_to_freq(black_box(&spatial)))
+        });
+        group.bench_function(format!("{} inverse", dct_algorithm.name()), |b| {
+            b.iter(|| transform.freq_to_spatial(black_box(&freq)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, dct_benchmark);
+criterion_main!(benches);
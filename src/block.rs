@@ -1,38 +1,48 @@
-use crate::{dct, quant};
-use seed::prelude::js_sys::Math::sqrt;
+use crate::dct::Transform;
+use crate::image::Plane;
+use crate::quant::{self, Quantizer};
 
+#[derive(Clone, Copy)]
 pub struct Block(pub [[i16; 8]; 8]);
 
+#[derive(Clone)]
 pub struct BlockMatrix {
     pub blocks: Vec<Block>,
     pub width: usize,
     pub height: usize,
 }
 
-pub fn split_to_block_matrix(data: &[u8], height_width_ratio: f64) -> BlockMatrix {
-    let block_count: usize = data.len() / (8 * 8);
-    let block_count_vert: usize = sqrt(block_count as f64 * height_width_ratio) as usize;
-    let block_count_horiz: usize = (block_count_vert as f64 / height_width_ratio) as usize;
-    let mut blocks: Vec<Block> = Vec::with_capacity(block_count_vert * block_count_horiz);
-
-    for v in 0..block_count_vert {
-        for u in 0..block_count_horiz {
-            blocks.push(get_block(u, v, 8 * block_count_horiz, &data));
+impl BlockMatrix {
+    /// Splits a plane whose sides are multiples of 8 into blocks
+    pub fn from_plane(plane: &Plane) -> BlockMatrix {
+        let width = plane.width / 8;
+        let height = plane.height / 8;
+        let mut blocks: Vec<Block> = Vec::with_capacity(width * height);
+        for v in 0..height {
+            for u in 0..width {
+                blocks.push(get_block(u, v, plane.width, &plane.data));
+            }
+        }
+        BlockMatrix {
+            blocks,
+            width,
+            height,
         }
     }
-    BlockMatrix {
-        blocks,
-        height: block_count_vert,
-        width: block_count_horiz,
+
+    pub fn to_plane(&self) -> Plane {
+        Plane::new(self.flatten(), self.width * 8, self.height * 8)
     }
-}
 
-impl BlockMatrix {
-    pub fn apply_quantization(&self, quantization: &[[u8; 8]; 8]) -> BlockMatrix {
+    pub fn apply_quantization(
+        &self,
+        quantization: &[[u8; 8]; 8],
+        transform: &dyn Transform,
+    ) -> BlockMatrix {
         let mut quantized_blocks: Vec<Block> = Vec::with_capacity(self.width * self.height);
         for v in 0..self.height {
             for u in 0..self.width {
-                let mut spatial = dct::spatial_to_freq(&self.blocks[u + v * self.width].0);
+                let mut spatial = transform.spatial_to_freq(&self.blocks[u + v * self.width].0);
                 quant::apply_quantization(&mut spatial, quantization);
                 quantized_blocks.push(Block(spatial));
             }
@@ -44,6 +54,41 @@ impl BlockMatrix {
         }
     }
 
+    /// Transforms every block and quantizes it the way the quantizer chooses
+    pub fn quantize(
+        &self,
+        quantization: &[[u8; 8]; 8],
+        transform: &dyn Transform,
+        quantizer: &Quantizer,
+    ) -> BlockMatrix {
+        BlockMatrix {
+            blocks: self
+                .blocks
+                .iter()
+                .map(|block| {
+                    Block(quantizer.quantize(&transform.spatial_to_freq(&block.0), quantization))
+                })
+                .collect(),
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Returns `width` x `height` blocks starting at the block (`start_u`, `start_v`)
+    pub fn crop(&self, start_u: usize, start_v: usize, width: usize, height: usize) -> BlockMatrix {
+        let mut blocks: Vec<Block> = Vec::with_capacity(width * height);
+        for v in start_v..start_v + height {
+            for u in start_u..start_u + width {
+                blocks.push(self.blocks[u + v * self.width]);
+            }
+        }
+        BlockMatrix {
+            blocks,
+            width,
+            height,
+        }
+    }
+
     pub fn flatten(&self) -> Vec<u8> {
         let mut result: Vec<u8> = vec![0; self.width * self.height * 8 * 8];
         for y in 0..self.height {
@@ -59,13 +104,17 @@ impl BlockMatrix {
         result
     }
 
-    pub fn undo_quantization(&self, quantization: &[[u8; 8]; 8]) -> BlockMatrix {
+    pub fn undo_quantization(
+        &self,
+        quantization: &[[u8; 8]; 8],
+        transform: &dyn Transform,
+    ) -> BlockMatrix {
         let mut result: Vec<Block> = Vec::with_capacity(self.width * self.height);
         for v in 0..self.height {
             for u in 0..self.width {
                 let mut freq = self.blocks[u + v * self.width].0;
                 quant::undo_quantization(&mut freq, quantization);
-                let spatial = dct::freq_to_spatial(&freq);
+                let spatial = transform.freq_to_spatial(&freq);
                 result.push(Block(spatial));
             }
         }
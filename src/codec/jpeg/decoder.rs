@@ -0,0 +1,1037 @@
+use super::constants::{self, ZIG_ZAG};
+use super::huffman::{HuffmanLookup, HuffmanTable};
+use crate::block::{Block, BlockMatrix};
+use std::cmp;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    NotJpeg,
+    UnexpectedEnd,
+    Unsupported(&'static str),
+    Malformed(&'static str),
+}
+
+pub struct DecodedComponent {
+    pub id: u8,
+    pub horiz_sampling: u8,
+    pub vert_sampling: u8,
+    pub quant_table: [[u8; 8]; 8],
+    /// Quantized coefficients in natural order, the block grid is padded to whole MCUs
+    pub coefficients: BlockMatrix,
+}
+
+/// Parameters of a single scan, a progressive file refines the coefficients over many scans
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScanInfo {
+    /// Indices into `DecodedJpeg::components`
+    pub components: Vec<usize>,
+    /// Zig-zag band of the coefficients coded in the scan
+    pub spectral_start: u8,
+    pub spectral_end: u8,
+    /// Ah and Al - the previous and the current bit position of the successive approximation,
+    /// Ah is 0 for the first scan of a band
+    pub successive_high: u8,
+    pub successive_low: u8,
+}
+
+impl ScanInfo {
+    pub fn is_refinement(&self) -> bool {
+        self.successive_high != 0
+    }
+}
+
+pub struct DecodedJpeg {
+    pub width: u16,
+    pub height: u16,
+    pub progressive: bool,
+    pub components: Vec<DecodedComponent>,
+    /// Scans in the order they were decoded
+    pub scans: Vec<ScanInfo>,
+}
+
+impl DecodedJpeg {
+    pub fn max_horiz_sampling(&self) -> u8 {
+        self.components
+            .iter()
+            .map(|component| component.horiz_sampling)
+            .max()
+            .unwrap_or(1)
+    }
+
+    pub fn max_vert_sampling(&self) -> u8 {
+        self.components
+            .iter()
+            .map(|component| component.vert_sampling)
+            .max()
+            .unwrap_or(1)
+    }
+}
+
+struct FrameComponent {
+    id: u8,
+    horiz_sampling: usize,
+    vert_sampling: usize,
+    quant_table_id: usize,
+    /// Number of blocks that cover the component without the MCU padding
+    blocks_horiz: usize,
+    blocks_vert: usize,
+    /// Number of blocks with the MCU padding, used as the row stride of `coefficients`
+    padded_blocks_horiz: usize,
+    padded_blocks_vert: usize,
+    /// Coefficients of every block stored in zig-zag order
+    coefficients: Vec<[i16; 64]>,
+}
+
+struct Frame {
+    width: u16,
+    height: u16,
+    progressive: bool,
+    mcus_horiz: usize,
+    mcus_vert: usize,
+    components: Vec<FrameComponent>,
+}
+
+struct ScanComponent {
+    index: usize,
+    dc_table: usize,
+    ac_table: usize,
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    current: u8,
+    bit_count: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8], position: usize) -> BitReader<'a> {
+        BitReader {
+            data,
+            position,
+            current: 0,
+            bit_count: 0,
+        }
+    }
+
+    /// Returns the next byte of the entropy coded segment with the stuffed zeros removed,
+    /// once a marker is reached only zeros are returned
+    fn next_byte(&mut self) -> u8 {
+        match self.data.get(self.position) {
+            Some(0xFF) if self.data.get(self.position + 1) == Some(&0x00) => {
+                self.position += 2;
+                0xFF
+            }
+            Some(0xFF) => 0,
+            Some(byte) => {
+                self.position += 1;
+                *byte
+            }
+            None => 0,
+        }
+    }
+
+    fn read_bit(&mut self) -> u16 {
+        if self.bit_count == 0 {
+            self.current = self.next_byte();
+            self.bit_count = 8;
+        }
+        self.bit_count -= 1;
+        ((self.current >> self.bit_count) & 1) as u16
+    }
+
+    fn read_bits(&mut self, length: u8) -> u16 {
+        let mut result: u16 = 0;
+        for _ in 0..length {
+            result = (result << 1) | self.read_bit();
+        }
+        result
+    }
+
+    /// Reads `size` additional bits and converts them to the signed amplitude (the EXTEND procedure)
+    fn receive_extend(&mut self, size: u8) -> i16 {
+        if size == 0 {
+            return 0;
+        }
+        let value = self.read_bits(size) as i32;
+        if value < 1 << (size - 1) {
+            (value - (1 << size) + 1) as i16
+        } else {
+            value as i16
+        }
+    }
+
+    fn decode_huffman(&mut self, lookup: &HuffmanLookup) -> Result<u8, DecodeError> {
+        let mut code: i32 = 0;
+        for length in 1..=16 {
+            code = (code << 1) | self.read_bit() as i32;
+            if let Some(symbol) = lookup.symbol(code, length) {
+                return Ok(symbol);
+            }
+        }
+        Err(DecodeError::Malformed("invalid Huffman code"))
+    }
+
+    /// Drops the remaining bits and skips the RSTn marker
+    fn restart(&mut self) {
+        self.bit_count = 0;
+        while self.position + 1 < self.data.len() {
+            let is_restart_marker = self.data[self.position] == 0xFF
+                && (constants::MARKER_RST0..=constants::MARKER_RST7)
+                    .contains(&self.data[self.position + 1]);
+            self.position += 1;
+            if is_restart_marker {
+                self.position += 1;
+                return;
+            }
+        }
+    }
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    position: usize,
+    quant_tables: [[u8; 64]; 4],
+    dc_tables: [Option<HuffmanLookup>; 4],
+    ac_tables: [Option<HuffmanLookup>; 4],
+    restart_interval: usize,
+    frame: Option<Frame>,
+    scans: Vec<ScanInfo>,
+    scan_limit: usize,
+}
+
+pub fn decode(data: &[u8]) -> Result<DecodedJpeg, DecodeError> {
+    decode_scans(data, usize::MAX)
+}
+
+/// Decodes only the first `scan_limit` scans, which shows the image as it looks
+/// while a progressive file is still loading
+pub fn decode_scans(data: &[u8], scan_limit: usize) -> Result<DecodedJpeg, DecodeError> {
+    let mut decoder = Decoder {
+        data,
+        position: 0,
+        quant_tables: [[1; 64]; 4],
+        dc_tables: [None, None, None, None],
+        ac_tables: [None, None, None, None],
+        restart_interval: 0,
+        frame: None,
+        scans: Vec::new(),
+        scan_limit,
+    };
+    decoder.decode()
+}
+
+impl<'a> Decoder<'a> {
+    fn decode(&mut self) -> Result<DecodedJpeg, DecodeError> {
+        if self.data.len() < 2 || self.data[0] != 0xFF || self.data[1] != constants::MARKER_SOI {
+            return Err(DecodeError::NotJpeg);
+        }
+        self.position = 2;
+
+        loop {
+            let marker = self.next_marker()?;
+            match marker {
+                constants::MARKER_EOI => break,
+                constants::MARKER_DQT => self.read_dqt()?,
+                constants::MARKER_DHT => self.read_dht()?,
+                constants::MARKER_DRI => {
+                    let segment = self.read_segment()?;
+                    if segment.len() < 2 {
+                        return Err(DecodeError::Malformed("DRI segment too short"));
+                    }
+                    self.restart_interval = u16::from_be_bytes([segment[0], segment[1]]) as usize;
+                }
+                constants::MARKER_SOF0 | constants::MARKER_SOF1 => self.read_sof(false)?,
+                constants::MARKER_SOF2 => self.read_sof(true)?,
+                0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+                    return Err(DecodeError::Unsupported(
+                        "lossless, hierarchical or arithmetic coding",
+                    ));
+                }
+                constants::MARKER_SOS => {
+                    self.read_scan()?;
+                    if self.scans.len() >= self.scan_limit {
+                        break;
+                    }
+                }
+                _ => {
+                    self.read_segment()?;
+                }
+            }
+        }
+
+        let frame = self
+            .frame
+            .take()
+            .ok_or(DecodeError::Malformed("missing SOF segment"))?;
+        Ok(self.finish(frame))
+    }
+
+    fn finish(&mut self, frame: Frame) -> DecodedJpeg {
+        let components = frame
+            .components
+            .into_iter()
+            .map(|component| {
+                let mut quant_table = [[0_u8; 8]; 8];
+                for (i, natural) in ZIG_ZAG.iter().enumerate() {
+                    quant_table[natural / 8][natural % 8] =
+                        self.quant_tables[component.quant_table_id][i];
+                }
+                let blocks = component
+                    .coefficients
+                    .iter()
+                    .map(|zig_zagged| {
+                        let mut block = [[0_i16; 8]; 8];
+                        for (i, natural) in ZIG_ZAG.iter().enumerate() {
+                            block[natural / 8][natural % 8] = zig_zagged[i];
+                        }
+                        Block(block)
+                    })
+                    .collect::<Vec<Block>>();
+                DecodedComponent {
+                    id: component.id,
+                    horiz_sampling: component.horiz_sampling as u8,
+                    vert_sampling: component.vert_sampling as u8,
+                    quant_table,
+                    coefficients: BlockMatrix {
+                        blocks,
+                        width: component.padded_blocks_horiz,
+                        height: component.padded_blocks_vert,
+                    },
+                }
+            })
+            .collect::<Vec<DecodedComponent>>();
+        DecodedJpeg {
+            width: frame.width,
+            height: frame.height,
+            progressive: frame.progressive,
+            components,
+            scans: std::mem::take(&mut self.scans),
+        }
+    }
+
+    /// Skips everything up to the next marker and returns its code
+    fn next_marker(&mut self) -> Result<u8, DecodeError> {
+        loop {
+            if self.position + 1 >= self.data.len() {
+                return Err(DecodeError::UnexpectedEnd);
+            }
+            let (byte, code) = (self.data[self.position], self.data[self.position + 1]);
+            if byte == 0xFF
+                && code != 0x00
+                && code != 0xFF
+                && !(constants::MARKER_RST0..=constants::MARKER_RST7).contains(&code)
+            {
+                self.position += 2;
+                return Ok(code);
+            }
+            self.position += 1;
+        }
+    }
+
+    /// Returns the payload of the segment that follows the current marker
+    fn read_segment(&mut self) -> Result<&'a [u8], DecodeError> {
+        if self.position + 2 > self.data.len() {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let length =
+            u16::from_be_bytes([self.data[self.position], self.data[self.position + 1]]) as usize;
+        if length < 2 {
+            return Err(DecodeError::Malformed("segment length"));
+        }
+        if self.position + length > self.data.len() {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let data: &'a [u8] = self.data;
+        let segment = &data[self.position + 2..self.position + length];
+        self.position += length;
+        Ok(segment)
+    }
+
+    fn read_dqt(&mut self) -> Result<(), DecodeError> {
+        let segment = self.read_segment()?;
+        let mut i = 0;
+        while i < segment.len() {
+            let precision = segment[i] >> 4;
+            let id = (segment[i] & 0x0F) as usize;
+            if precision != 0 {
+                return Err(DecodeError::Unsupported("16-bit quantization tables"));
+            }
+            if id > 3 || i + 65 > segment.len() {
+                return Err(DecodeError::Malformed("DQT segment"));
+            }
+            self.quant_tables[id].copy_from_slice(&segment[i + 1..i + 65]);
+            i += 65;
+        }
+        Ok(())
+    }
+
+    fn read_dht(&mut self) -> Result<(), DecodeError> {
+        let segment = self.read_segment()?;
+        let mut i = 0;
+        while i < segment.len() {
+            if i + 17 > segment.len() {
+                return Err(DecodeError::Malformed("DHT segment"));
+            }
+            let class = segment[i] >> 4;
+            let id = (segment[i] & 0x0F) as usize;
+            let mut bits = [0_u8; 16];
+            bits.copy_from_slice(&segment[i + 1..i + 17]);
+            let count = bits.iter().map(|x| *x as usize).sum::<usize>();
+            if id > 3 || class > 1 || i + 17 + count > segment.len() {
+                return Err(DecodeError::Malformed("DHT segment"));
+            }
+            let values = &segment[i + 17..i + 17 + count];
+            // DC differences have up to 11 bits, AC coefficients up to 10
+            let is_valid = |value: &u8| match class {
+                0 => *value <= 11,
+                _ => *value & 0x0F <= 10,
+            };
+            if !values.iter().all(is_valid) {
+                return Err(DecodeError::Malformed("DHT symbol value"));
+            }
+            let table = HuffmanTable::new(bits, values);
+            if class == 0 {
+                self.dc_tables[id] = Some(table.lookup());
+            } else {
+                self.ac_tables[id] = Some(table.lookup());
+            }
+            i += 17 + count;
+        }
+        Ok(())
+    }
+
+    fn read_sof(&mut self, progressive: bool) -> Result<(), DecodeError> {
+        let segment = self.read_segment()?;
+        if segment.len() < 6 {
+            return Err(DecodeError::Malformed("SOF segment"));
+        }
+        if segment[0] != 8 {
+            return Err(DecodeError::Unsupported(
+                "sample precision other than 8 bits",
+            ));
+        }
+        let height = u16::from_be_bytes([segment[1], segment[2]]);
+        let width = u16::from_be_bytes([segment[3], segment[4]]);
+        let component_count = segment[5] as usize;
+        if height == 0 {
+            return Err(DecodeError::Unsupported("height defined by the DNL marker"));
+        }
+        if width == 0 || component_count == 0 || segment.len() < 6 + component_count * 3 {
+            return Err(DecodeError::Malformed("SOF segment"));
+        }
+
+        let mut components = Vec::<FrameComponent>::new();
+        for c in 0..component_count {
+            let offset = 6 + c * 3;
+            let sampling = segment[offset + 1];
+            let (horiz_sampling, vert_sampling) = if component_count == 1 {
+                // a single component is never interleaved, so its sampling factors are irrelevant
+                (1, 1)
+            } else {
+                ((sampling >> 4) as usize, (sampling & 0x0F) as usize)
+            };
+            if !(1..=4).contains(&horiz_sampling) || !(1..=4).contains(&vert_sampling) {
+                return Err(DecodeError::Malformed("sampling factors"));
+            }
+            components.push(FrameComponent {
+                id: segment[offset],
+                horiz_sampling,
+                vert_sampling,
+                quant_table_id: (segment[offset + 2] & 0x03) as usize,
+                blocks_horiz: 0,
+                blocks_vert: 0,
+                padded_blocks_horiz: 0,
+                padded_blocks_vert: 0,
+                coefficients: Vec::new(),
+            });
+        }
+
+        let max_horiz = components.iter().map(|c| c.horiz_sampling).max().unwrap();
+        let max_vert = components.iter().map(|c| c.vert_sampling).max().unwrap();
+        let mcus_horiz = (width as usize).div_ceil(8 * max_horiz);
+        let mcus_vert = (height as usize).div_ceil(8 * max_vert);
+        for component in components.iter_mut() {
+            component.blocks_horiz = (width as usize * component.horiz_sampling)
+                .div_ceil(max_horiz)
+                .div_ceil(8);
+            component.blocks_vert = (height as usize * component.vert_sampling)
+                .div_ceil(max_vert)
+                .div_ceil(8);
+            component.padded_blocks_horiz = mcus_horiz * component.horiz_sampling;
+            component.padded_blocks_vert = mcus_vert * component.vert_sampling;
+            component.coefficients =
+                vec![[0; 64]; component.padded_blocks_horiz * component.padded_blocks_vert];
+        }
+
+        self.frame = Some(Frame {
+            width,
+            height,
+            progressive,
+            mcus_horiz,
+            mcus_vert,
+            components,
+        });
+        Ok(())
+    }
+
+    fn read_scan(&mut self) -> Result<(), DecodeError> {
+        let segment = self.read_segment()?;
+        let frame = self
+            .frame
+            .as_mut()
+            .ok_or(DecodeError::Malformed("SOS before SOF"))?;
+        let component_count = *segment.first().ok_or(DecodeError::Malformed("SOS"))? as usize;
+        if component_count == 0 || segment.len() < 1 + component_count * 2 + 3 {
+            return Err(DecodeError::Malformed("SOS segment"));
+        }
+
+        let mut scan_components = Vec::<ScanComponent>::new();
+        for c in 0..component_count {
+            let id = segment[1 + c * 2];
+            let tables = segment[2 + c * 2];
+            let index = frame
+                .components
+                .iter()
+                .position(|component| component.id == id)
+                .ok_or(DecodeError::Malformed("unknown component in SOS"))?;
+            let (dc_table, ac_table) = (
+                (tables >> 4) as usize & 0x03,
+                (tables & 0x0F) as usize & 0x03,
+            );
+            scan_components.push(ScanComponent {
+                index,
+                dc_table,
+                ac_table,
+            });
+        }
+
+        let parameters = &segment[1 + component_count * 2..];
+        let scan = if frame.progressive {
+            ScanInfo {
+                components: scan_components.iter().map(|c| c.index).collect(),
+                spectral_start: parameters[0],
+                spectral_end: parameters[1],
+                successive_high: parameters[2] >> 4,
+                successive_low: parameters[2] & 0x0F,
+            }
+        } else {
+            // a sequential scan always codes every coefficient at full precision
+            ScanInfo {
+                components: scan_components.iter().map(|c| c.index).collect(),
+                spectral_start: 0,
+                spectral_end: 63,
+                successive_high: 0,
+                successive_low: 0,
+            }
+        };
+        if scan.spectral_start > scan.spectral_end
+            || scan.spectral_end > 63
+            || scan.successive_low > 13
+        {
+            return Err(DecodeError::Malformed("SOS scan parameters"));
+        }
+        if frame.progressive && scan.spectral_start == 0 && scan.spectral_end != 0 {
+            return Err(DecodeError::Malformed("progressive scan mixes DC and AC"));
+        }
+        if scan.spectral_start > 0 && component_count != 1 {
+            return Err(DecodeError::Malformed("interleaved AC scan"));
+        }
+
+        let mut reader = BitReader::new(self.data, self.position);
+        let mut previous_dcs = vec![0_i16; frame.components.len()];
+        let mut end_of_band_run: u32 = 0;
+        let spectral_start = cmp::max(scan.spectral_start, 1) as usize;
+        let spectral_end = scan.spectral_end as usize;
+        for (i, unit) in scan_units(frame, &scan_components).iter().enumerate() {
+            if self.restart_interval > 0 && i > 0 && i % self.restart_interval == 0 {
+                reader.restart();
+                previous_dcs.iter_mut().for_each(|dc| *dc = 0);
+                end_of_band_run = 0;
+            }
+            for (scan_component, block_index) in unit.iter() {
+                let scan_component = &scan_components[*scan_component];
+                let block = &mut frame.components[scan_component.index].coefficients[*block_index];
+                if scan.spectral_start == 0 {
+                    if scan.is_refinement() {
+                        decode_dc_refine(&mut reader, scan.successive_low, block);
+                    } else {
+                        decode_dc_first(
+                            &mut reader,
+                            huffman_table(&self.dc_tables, scan_component.dc_table)?,
+                            scan.successive_low,
+                            &mut previous_dcs[scan_component.index],
+                            block,
+                        )?;
+                    }
+                }
+                if scan.spectral_end > 0 {
+                    let ac_table = huffman_table(&self.ac_tables, scan_component.ac_table)?;
+                    if scan.is_refinement() {
+                        decode_ac_refine(
+                            &mut reader,
+                            ac_table,
+                            (spectral_start, spectral_end),
+                            scan.successive_low,
+                            &mut end_of_band_run,
+                            block,
+                        )?;
+                    } else {
+                        decode_ac_first(
+                            &mut reader,
+                            ac_table,
+                            (spectral_start, spectral_end),
+                            scan.successive_low,
+                            &mut end_of_band_run,
+                            block,
+                        )?;
+                    }
+                }
+            }
+        }
+        self.position = reader.position;
+        self.scans.push(scan);
+        Ok(())
+    }
+}
+
+fn huffman_table(
+    tables: &[Option<HuffmanLookup>; 4],
+    id: usize,
+) -> Result<&HuffmanLookup, DecodeError> {
+    tables[id]
+        .as_ref()
+        .ok_or(DecodeError::Malformed("missing Huffman table"))
+}
+
+/// Splits the scan into the units that restart intervals count - MCUs of an interleaved scan
+/// or single blocks of a non-interleaved one. Every unit lists (scan component, block index)
+/// pairs in the order they are coded
+fn scan_units(frame: &Frame, scan_components: &[ScanComponent]) -> Vec<Vec<(usize, usize)>> {
+    let mut units = Vec::<Vec<(usize, usize)>>::new();
+    if scan_components.len() == 1 {
+        // non-interleaved scan goes through the blocks of the component in raster order,
+        // skipping the MCU padding
+        let component = &frame.components[scan_components[0].index];
+        for block_y in 0..component.blocks_vert {
+            for block_x in 0..component.blocks_horiz {
+                units.push(vec![(0, block_x + block_y * component.padded_blocks_horiz)]);
+            }
+        }
+    } else {
+        for mcu_y in 0..frame.mcus_vert {
+            for mcu_x in 0..frame.mcus_horiz {
+                let mut unit = Vec::<(usize, usize)>::new();
+                for (i, scan_component) in scan_components.iter().enumerate() {
+                    let component = &frame.components[scan_component.index];
+                    for v in 0..component.vert_sampling {
+                        for u in 0..component.horiz_sampling {
+                            let block_x = mcu_x * component.horiz_sampling + u;
+                            let block_y = mcu_y * component.vert_sampling + v;
+                            unit.push((i, block_x + block_y * component.padded_blocks_horiz));
+                        }
+                    }
+                }
+                units.push(unit);
+            }
+        }
+    }
+    units
+}
+
+/// Decodes the DC difference, in a progressive file only the bits from `successive_low` up are sent
+fn decode_dc_first(
+    reader: &mut BitReader,
+    dc_table: &HuffmanLookup,
+    successive_low: u8,
+    previous_dc: &mut i16,
+    block: &mut [i16; 64],
+) -> Result<(), DecodeError> {
+    let size = reader.decode_huffman(dc_table)?;
+    *previous_dc = previous_dc
+        .checked_add(reader.receive_extend(size))
+        .ok_or(DecodeError::Malformed("DC difference out of range"))?;
+    block[0] = *previous_dc << successive_low;
+    Ok(())
+}
+
+/// Every refinement scan of DC sends one raw bit per block
+fn decode_dc_refine(reader: &mut BitReader, successive_low: u8, block: &mut [i16; 64]) {
+    if reader.read_bit() == 1 {
+        block[0] |= 1 << successive_low;
+    }
+}
+
+/// Decodes the run-length coded band of AC coefficients, a sequential block is the band 1..=63.
+/// EOBn symbols of progressive files end the band in `end_of_band_run` following blocks as well
+fn decode_ac_first(
+    reader: &mut BitReader,
+    ac_table: &HuffmanLookup,
+    (spectral_start, spectral_end): (usize, usize),
+    successive_low: u8,
+    end_of_band_run: &mut u32,
+    block: &mut [i16; 64],
+) -> Result<(), DecodeError> {
+    if *end_of_band_run > 0 {
+        *end_of_band_run -= 1;
+        return Ok(());
+    }
+
+    let mut k = spectral_start;
+    while k <= spectral_end {
+        let symbol = reader.decode_huffman(ac_table)?;
+        let (run, size) = (symbol >> 4, symbol & 0x0F);
+        if size == 0 {
+            if run == 15 {
+                k += 16;
+                continue;
+            }
+            // EOB0 (the baseline EOB) ends only the current block
+            *end_of_band_run = (1 << run) - 1 + reader.read_bits(run) as u32;
+            break;
+        }
+        k += run as usize;
+        if k > spectral_end {
+            return Err(DecodeError::Malformed("coefficient index out of range"));
+        }
+        block[k] = reader.receive_extend(size) << successive_low;
+        k += 1;
+    }
+    Ok(())
+}
+
+/// Adds one more bit to the coefficients of the band. Newly nonzero coefficients are coded
+/// with the run of zero coefficients before them, the already nonzero ones that are skipped
+/// over get a correction bit each
+fn decode_ac_refine(
+    reader: &mut BitReader,
+    ac_table: &HuffmanLookup,
+    (spectral_start, spectral_end): (usize, usize),
+    successive_low: u8,
+    end_of_band_run: &mut u32,
+    block: &mut [i16; 64],
+) -> Result<(), DecodeError> {
+    let positive = 1_i16 << successive_low;
+    let negative = -1_i16 << successive_low;
+
+    let mut k = spectral_start;
+    if *end_of_band_run == 0 {
+        while k <= spectral_end {
+            let symbol = reader.decode_huffman(ac_table)?;
+            let (mut run, size) = (symbol >> 4, symbol & 0x0F);
+            let mut value = 0;
+            if size != 0 {
+                value = if reader.read_bit() == 1 {
+                    positive
+                } else {
+                    negative
+                };
+            } else if run != 15 {
+                *end_of_band_run = (1 << run) + reader.read_bits(run) as u32;
+                break;
+            }
+
+            while k <= spectral_end {
+                if block[k] != 0 {
+                    refine_coefficient(reader, &mut block[k], positive, negative);
+                } else {
+                    if run == 0 {
+                        break;
+                    }
+                    run -= 1;
+                }
+                k += 1;
+            }
+            if value != 0 {
+                if k > spectral_end {
+                    return Err(DecodeError::Malformed("coefficient index out of range"));
+                }
+                block[k] = value;
+            }
+            k += 1;
+        }
+    }
+
+    if *end_of_band_run > 0 {
+        while k <= spectral_end {
+            if block[k] != 0 {
+                refine_coefficient(reader, &mut block[k], positive, negative);
+            }
+            k += 1;
+        }
+        *end_of_band_run -= 1;
+    }
+    Ok(())
+}
+
+fn refine_coefficient(reader: &mut BitReader, coefficient: &mut i16, positive: i16, negative: i16) {
+    if reader.read_bit() == 1 && *coefficient & positive == 0 {
+        *coefficient += if *coefficient >= 0 {
+            positive
+        } else {
+            negative
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, decode_scans, DecodeError};
+    use crate::block::{Block, BlockMatrix};
+    use crate::codec::jpeg::encoder::{count_symbols, encode, EncoderInput, HuffmanTables};
+    use crate::quant::{CHROMINANCE_QUANTIZATION_TABLE, LUMINANCE_QUANTIZATION_TABLE};
+
+    fn block_matrix(width: usize, height: usize, seed: i16) -> BlockMatrix {
+        let blocks = (0..width * height)
+            .map(|i| {
+                let mut block = [[0_i16; 8]; 8];
+                for (y, row) in block.iter_mut().enumerate() {
+                    for (x, value) in row.iter_mut().enumerate() {
+                        if x + y < 5 {
+                            *value = ((i as i16 + seed) * 7 + (x * 3 + y) as i16) % 41 - 20;
+                        }
+                    }
+                }
+                Block(block)
+            })
+            .collect::<Vec<Block>>();
+        BlockMatrix {
+            blocks,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    pub fn test_decode_encoded() {
+        let ys = block_matrix(8, 4, 0);
+        let cbs = block_matrix(4, 2, 1);
+        let crs = block_matrix(4, 2, 2);
+
+        let encoded = encode(&EncoderInput {
+            width: 64,
+            height: 32,
+            ys: &ys,
+            cbs: &cbs,
+            crs: &crs,
+            horiz_sampling: 2,
+            vert_sampling: 2,
+            cb_horiz_sampling: 1,
+            cb_vert_sampling: 1,
+            cr_horiz_sampling: 1,
+            cr_vert_sampling: 1,
+            luminance_quant_table: &LUMINANCE_QUANTIZATION_TABLE,
+            chrominance_quant_table: &CHROMINANCE_QUANTIZATION_TABLE,
+            huffman_tables: &HuffmanTables::standard(),
+        });
+        let decoded = decode(&encoded).unwrap();
+
+        assert_eq!((decoded.width, decoded.height), (64, 32));
+        assert_eq!(decoded.components.len(), 3);
+        assert_eq!(decoded.max_horiz_sampling(), 2);
+        assert_eq!(decoded.components[1].horiz_sampling, 1);
+        assert_eq!(
+            decoded.components[0].quant_table,
+            LUMINANCE_QUANTIZATION_TABLE
+        );
+        assert_eq!(
+            decoded.components[2].quant_table,
+            CHROMINANCE_QUANTIZATION_TABLE
+        );
+        for (decoded_component, expected) in decoded.components.iter().zip([ys, cbs, crs].iter()) {
+            assert_eq!(decoded_component.coefficients.width, expected.width);
+            assert_eq!(decoded_component.coefficients.height, expected.height);
+            for (decoded_block, expected_block) in decoded_component
+                .coefficients
+                .blocks
+                .iter()
+                .zip(expected.blocks.iter())
+            {
+                assert_eq!(decoded_block.0, expected_block.0);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_decode_encoded_with_chroma_sampling_factors() {
+        // 4x2 luma with 2x1 chroma, so every MCU holds 8 Y blocks and 2 blocks of Cb and Cr
+        let ys = block_matrix(8, 2, 0);
+        let cbs = block_matrix(4, 1, 1);
+        let crs = block_matrix(4, 1, 2);
+
+        let encoded = encode(&EncoderInput {
+            width: 64,
+            height: 16,
+            ys: &ys,
+            cbs: &cbs,
+            crs: &crs,
+            horiz_sampling: 4,
+            vert_sampling: 2,
+            cb_horiz_sampling: 2,
+            cb_vert_sampling: 1,
+            cr_horiz_sampling: 2,
+            cr_vert_sampling: 1,
+            luminance_quant_table: &LUMINANCE_QUANTIZATION_TABLE,
+            chrominance_quant_table: &CHROMINANCE_QUANTIZATION_TABLE,
+            huffman_tables: &HuffmanTables::standard(),
+        });
+        let decoded = decode(&encoded).unwrap();
+
+        assert_eq!(decoded.components[0].horiz_sampling, 4);
+        assert_eq!(decoded.components[2].horiz_sampling, 2);
+        assert_eq!(decoded.components[2].vert_sampling, 1);
+        for (decoded_component, expected) in decoded.components.iter().zip([ys, cbs, crs].iter()) {
+            assert_eq!(decoded_component.coefficients.width, expected.width);
+            for (decoded_block, expected_block) in decoded_component
+                .coefficients
+                .blocks
+                .iter()
+                .zip(expected.blocks.iter())
+            {
+                assert_eq!(decoded_block.0, expected_block.0);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_decode_encoded_with_separate_chroma_factors() {
+        // 2x2 luma, 1x1 Cb and 2x1 Cr, so the Cr blocks of an MCU sit side by side
+        let ys = block_matrix(4, 4, 0);
+        let cbs = block_matrix(2, 2, 1);
+        let crs = block_matrix(4, 2, 2);
+
+        let encoded = encode(&EncoderInput {
+            width: 32,
+            height: 32,
+            ys: &ys,
+            cbs: &cbs,
+            crs: &crs,
+            horiz_sampling: 2,
+            vert_sampling: 2,
+            cb_horiz_sampling: 1,
+            cb_vert_sampling: 1,
+            cr_horiz_sampling: 2,
+            cr_vert_sampling: 1,
+            luminance_quant_table: &LUMINANCE_QUANTIZATION_TABLE,
+            chrominance_quant_table: &CHROMINANCE_QUANTIZATION_TABLE,
+            huffman_tables: &HuffmanTables::standard(),
+        });
+        let decoded = decode(&encoded).unwrap();
+
+        assert_eq!(decoded.components[1].horiz_sampling, 1);
+        assert_eq!(decoded.components[2].horiz_sampling, 2);
+        for (decoded_component, expected) in decoded.components.iter().zip([ys, cbs, crs].iter()) {
+            assert_eq!(decoded_component.coefficients.width, expected.width);
+            assert_eq!(decoded_component.coefficients.height, expected.height);
+            for (decoded_block, expected_block) in decoded_component
+                .coefficients
+                .blocks
+                .iter()
+                .zip(expected.blocks.iter())
+            {
+                assert_eq!(decoded_block.0, expected_block.0);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_decode_optimized_tables() {
+        let ys = block_matrix(8, 4, 0);
+        let cbs = block_matrix(4, 2, 1);
+        let crs = block_matrix(4, 2, 2);
+        let (luminance, chrominance) = count_symbols(&ys, &cbs, &crs, [(2, 2), (1, 1), (1, 1)]);
+        let encode_with = |huffman_tables: &HuffmanTables| {
+            encode(&EncoderInput {
+                width: 64,
+                height: 32,
+                ys: &ys,
+                cbs: &cbs,
+                crs: &crs,
+                horiz_sampling: 2,
+                vert_sampling: 2,
+                cb_horiz_sampling: 1,
+                cb_vert_sampling: 1,
+                cr_horiz_sampling: 1,
+                cr_vert_sampling: 1,
+                luminance_quant_table: &LUMINANCE_QUANTIZATION_TABLE,
+                chrominance_quant_table: &CHROMINANCE_QUANTIZATION_TABLE,
+                huffman_tables,
+            })
+        };
+        let optimized = encode_with(&HuffmanTables::optimal(&luminance, &chrominance));
+        let decoded = decode(&optimized).unwrap();
+
+        // the tables of the few symbols used are shorter than the Annex K ones and so is the data
+        assert!(optimized.len() < encode_with(&HuffmanTables::standard()).len());
+        for (decoded_component, expected) in decoded.components.iter().zip([&ys, &cbs, &crs]) {
+            for (decoded_block, expected_block) in decoded_component
+                .coefficients
+                .blocks
+                .iter()
+                .zip(expected.blocks.iter())
+            {
+                assert_eq!(decoded_block.0, expected_block.0);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_decode_with_restart_markers() {
+        let decoded = decode(include_bytes!("../../../www/public/preset_images/agh.jpg")).unwrap();
+
+        assert_eq!((decoded.width, decoded.height), (471, 744));
+        assert_eq!(decoded.components.len(), 3);
+        // 471x744 is padded to 59x93 blocks
+        assert_eq!(decoded.components[0].coefficients.width, 59);
+        assert_eq!(decoded.components[0].coefficients.height, 93);
+    }
+
+    #[test]
+    pub fn test_decode_progressive_scans() {
+        let data = include_bytes!("../../../www/public/preset_images/green.jpg");
+        let decoded = decode(data).unwrap();
+        // the first three scans carry only the DC coefficients of Y, Cb and Cr
+        let dc_only = decode_scans(data, 3).unwrap();
+
+        assert!(decoded.progressive);
+        assert_eq!(decoded.scans.len(), 13);
+        assert_eq!(dc_only.scans[..], decoded.scans[..3]);
+        assert!(decoded.scans.last().unwrap().is_refinement());
+        for (full, partial) in decoded.components.iter().zip(dc_only.components.iter()) {
+            for (full_block, partial_block) in full
+                .coefficients
+                .blocks
+                .iter()
+                .zip(partial.coefficients.blocks.iter())
+            {
+                assert_eq!(full_block.0[0][0], partial_block.0[0][0]);
+                assert!(partial_block.0.iter().flatten().skip(1).all(|x| *x == 0));
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_decode_invalid() {
+        assert_eq!(
+            decode(&[0x89, 0x50, 0x4E, 0x47]).err(),
+            Some(DecodeError::NotJpeg)
+        );
+        assert_eq!(
+            decode(&[0xFF, 0xD8, 0xFF]).err(),
+            Some(DecodeError::UnexpectedEnd)
+        );
+
+        let mut data = include_bytes!("../../../www/public/preset_images/green.jpg").to_vec();
+        let dht = data
+            .windows(2)
+            .position(|marker| marker == [0xFF, 0xC4])
+            .unwrap();
+        // the first value of a DC table, after the length, Tc/Th and 16 counts
+        assert_eq!(data[dht + 4] >> 4, 0);
+        data[dht + 21] = 40;
+        assert_eq!(
+            decode(&data).err(),
+            Some(DecodeError::Malformed("DHT symbol value"))
+        );
+    }
+}
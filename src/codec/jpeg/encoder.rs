@@ -0,0 +1,462 @@
+use super::constants::{self, ZIG_ZAG};
+use super::entropy::{self, Symbol, SymbolFrequencies};
+use super::huffman::{HuffmanCode, HuffmanTable};
+use crate::block::BlockMatrix;
+
+const LUMINANCE_TABLE_ID: u8 = 0;
+const CHROMINANCE_TABLE_ID: u8 = 1;
+
+/// Quantized Y, Cb and Cr planes together with everything needed to write a baseline JFIF file
+pub struct EncoderInput<'a> {
+    pub width: u16,
+    pub height: u16,
+
+    pub ys: &'a BlockMatrix,
+    pub cbs: &'a BlockMatrix,
+    pub crs: &'a BlockMatrix,
+
+    /// Luminance sampling factors
+    pub horiz_sampling: u8,
+    pub vert_sampling: u8,
+    /// Sampling factors of Cb and Cr, they must divide the luminance factors
+    pub cb_horiz_sampling: u8,
+    pub cb_vert_sampling: u8,
+    pub cr_horiz_sampling: u8,
+    pub cr_vert_sampling: u8,
+
+    pub luminance_quant_table: &'a [[u8; 8]; 8],
+    pub chrominance_quant_table: &'a [[u8; 8]; 8],
+
+    pub huffman_tables: &'a HuffmanTables,
+}
+
+/// DC and AC Huffman tables of the luminance and of the chrominance, Cb and Cr share theirs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HuffmanTables {
+    pub luminance_dc: HuffmanTable,
+    pub luminance_ac: HuffmanTable,
+    pub chrominance_dc: HuffmanTable,
+    pub chrominance_ac: HuffmanTable,
+}
+
+impl HuffmanTables {
+    /// Tables of the Annex K of the JPEG standard
+    pub fn standard() -> HuffmanTables {
+        HuffmanTables {
+            luminance_dc: HuffmanTable::luminance_dc(),
+            luminance_ac: HuffmanTable::luminance_ac(),
+            chrominance_dc: HuffmanTable::chrominance_dc(),
+            chrominance_ac: HuffmanTable::chrominance_ac(),
+        }
+    }
+
+    /// Tables built for the symbols counted by `count_symbols`
+    pub fn optimal(
+        luminance: &SymbolFrequencies,
+        chrominance: &SymbolFrequencies,
+    ) -> HuffmanTables {
+        HuffmanTables {
+            luminance_dc: HuffmanTable::optimal(&luminance.dc),
+            luminance_ac: HuffmanTable::optimal(&luminance.ac),
+            chrominance_dc: HuffmanTable::optimal(&chrominance.dc),
+            chrominance_ac: HuffmanTable::optimal(&chrominance.ac),
+        }
+    }
+
+    /// DC and AC table of the luminance
+    pub fn luminance(&self) -> (&HuffmanTable, &HuffmanTable) {
+        (&self.luminance_dc, &self.luminance_ac)
+    }
+
+    /// DC and AC table shared by Cb and Cr
+    pub fn chrominance(&self) -> (&HuffmanTable, &HuffmanTable) {
+        (&self.chrominance_dc, &self.chrominance_ac)
+    }
+
+    /// Entropy coded bits of the counted symbols - the codewords and the amplitude bits
+    pub fn coded_bits(
+        &self,
+        luminance: &SymbolFrequencies,
+        chrominance: &SymbolFrequencies,
+    ) -> u64 {
+        self.luminance_dc.coded_bits(&luminance.dc)
+            + self.luminance_ac.coded_bits(&luminance.ac)
+            + self.chrominance_dc.coded_bits(&chrominance.dc)
+            + self.chrominance_ac.coded_bits(&chrominance.ac)
+            + luminance.amplitude_bits
+            + chrominance.amplitude_bits
+    }
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    accumulator: u32,
+    bit_count: u8,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            bytes: Vec::new(),
+            accumulator: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn write_bits(&mut self, bits: u16, length: u8) {
+        self.accumulator = (self.accumulator << length) | (bits as u32 & ((1 << length) - 1));
+        self.bit_count += length;
+        while self.bit_count >= 8 {
+            self.bit_count -= 8;
+            let byte = (self.accumulator >> self.bit_count) as u8;
+            self.bytes.push(byte);
+            // 0xFF inside the entropy coded segment has to be followed by a stuffed zero byte
+            if byte == 0xFF {
+                self.bytes.push(0x00);
+            }
+        }
+        self.accumulator &= (1 << self.bit_count) - 1;
+    }
+
+    fn write_code(&mut self, code: HuffmanCode) {
+        self.write_bits(code.code, code.length);
+    }
+
+    /// Pads the last byte with 1-bits
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.write_bits(0x7F, 8 - self.bit_count);
+        }
+        self.bytes
+    }
+}
+
+struct ComponentCoder {
+    previous_dc: i16,
+    dc_codes: [HuffmanCode; 256],
+    ac_codes: [HuffmanCode; 256],
+}
+
+impl ComponentCoder {
+    fn new(dc_table: &HuffmanTable, ac_table: &HuffmanTable) -> ComponentCoder {
+        ComponentCoder {
+            previous_dc: 0,
+            dc_codes: dc_table.codes(),
+            ac_codes: ac_table.codes(),
+        }
+    }
+
+    fn encode_block(&mut self, block: &[[i16; 8]; 8], writer: &mut BitWriter) {
+        let symbols = entropy::block_symbols(block, self.previous_dc);
+        self.previous_dc = block[0][0];
+        for symbol in symbols.iter() {
+            self.write_symbol(symbol, writer);
+        }
+    }
+
+    fn write_symbol(&self, symbol: &Symbol, writer: &mut BitWriter) {
+        let codes = match symbol.kind {
+            entropy::SymbolKind::Dc => &self.dc_codes,
+            _ => &self.ac_codes,
+        };
+        let code = codes[symbol.huffman_value() as usize];
+        // a value without a code would be written as nothing and desynchronize the decoder
+        debug_assert!(
+            code.length > 0,
+            "no Huffman code for the value {:#04x}",
+            symbol.huffman_value()
+        );
+        writer.write_code(code);
+        if symbol.size > 0 {
+            writer.write_bits(symbol.amplitude_bits(), symbol.size);
+        }
+    }
+}
+
+pub fn encode(input: &EncoderInput) -> Vec<u8> {
+    // the chrominance blocks are grouped into MCUs the same way, just with their own factors
+    let [(y_order, y_mcu_blocks), (cb_order, cb_mcu_blocks), (cr_order, cr_mcu_blocks)] = [
+        (input.ys, input.horiz_sampling, input.vert_sampling),
+        (input.cbs, input.cb_horiz_sampling, input.cb_vert_sampling),
+        (input.crs, input.cr_horiz_sampling, input.cr_vert_sampling),
+    ]
+    .map(|(blocks, horiz_sampling, vert_sampling)| {
+        let (horiz_sampling, vert_sampling) = (horiz_sampling as usize, vert_sampling as usize);
+        (
+            luminance_block_order(blocks.width, blocks.height, horiz_sampling, vert_sampling),
+            horiz_sampling * vert_sampling,
+        )
+    });
+
+    let tables = input.huffman_tables;
+
+    let mut output = Vec::<u8>::new();
+    write_marker(&mut output, constants::MARKER_SOI);
+    write_app0(&mut output);
+    write_dqt(
+        &mut output,
+        &[
+            (LUMINANCE_TABLE_ID, input.luminance_quant_table),
+            (CHROMINANCE_TABLE_ID, input.chrominance_quant_table),
+        ],
+    );
+    write_sof0(&mut output, input);
+    write_dht(
+        &mut output,
+        &[
+            (0, LUMINANCE_TABLE_ID, &tables.luminance_dc),
+            (1, LUMINANCE_TABLE_ID, &tables.luminance_ac),
+            (0, CHROMINANCE_TABLE_ID, &tables.chrominance_dc),
+            (1, CHROMINANCE_TABLE_ID, &tables.chrominance_ac),
+        ],
+    );
+    write_sos(&mut output);
+
+    let mut writer = BitWriter::new();
+    let mut y_coder = ComponentCoder::new(&tables.luminance_dc, &tables.luminance_ac);
+    let mut cb_coder = ComponentCoder::new(&tables.chrominance_dc, &tables.chrominance_ac);
+    let mut cr_coder = ComponentCoder::new(&tables.chrominance_dc, &tables.chrominance_ac);
+
+    // every MCU holds the Y blocks followed by the Cb and the Cr blocks, each in their sampling factors
+    for ((y_indices, cb_indices), cr_indices) in y_order
+        .chunks(y_mcu_blocks)
+        .zip(cb_order.chunks(cb_mcu_blocks))
+        .zip(cr_order.chunks(cr_mcu_blocks))
+    {
+        for index in y_indices.iter() {
+            y_coder.encode_block(&input.ys.blocks[*index].0, &mut writer);
+        }
+        for index in cb_indices.iter() {
+            cb_coder.encode_block(&input.cbs.blocks[*index].0, &mut writer);
+        }
+        for index in cr_indices.iter() {
+            cr_coder.encode_block(&input.crs.blocks[*index].0, &mut writer);
+        }
+    }
+
+    output.extend(writer.finish());
+    write_marker(&mut output, constants::MARKER_EOI);
+    output
+}
+
+/// Symbols of the luminance and of both chrominance components, each counted in the order
+/// the interleaved scan codes its blocks with the Y, Cb and Cr sampling factors
+pub fn count_symbols(
+    ys: &BlockMatrix,
+    cbs: &BlockMatrix,
+    crs: &BlockMatrix,
+    sampling: [(usize, usize); 3],
+) -> (SymbolFrequencies, SymbolFrequencies) {
+    let order = |blocks: &BlockMatrix, (horiz_sampling, vert_sampling): (usize, usize)| {
+        luminance_block_order(blocks.width, blocks.height, horiz_sampling, vert_sampling)
+    };
+    let mut luminance = SymbolFrequencies::default();
+    luminance.add_blocks(ys, &order(ys, sampling[0]));
+    let mut chrominance = SymbolFrequencies::default();
+    chrominance.add_blocks(cbs, &order(cbs, sampling[1]));
+    chrominance.add_blocks(crs, &order(crs, sampling[2]));
+    (luminance, chrominance)
+}
+
+/// Indices of the Y blocks in the order they are coded in the interleaved scan - MCU by MCU,
+/// row by row inside the MCU
+pub fn luminance_block_order(
+    width: usize,
+    height: usize,
+    horiz_sampling: usize,
+    vert_sampling: usize,
+) -> Vec<usize> {
+    let mut order = Vec::<usize>::with_capacity(width * height);
+    for mcu_y in 0..height / vert_sampling {
+        for mcu_x in 0..width / horiz_sampling {
+            for v in 0..vert_sampling {
+                for u in 0..horiz_sampling {
+                    let block_x = mcu_x * horiz_sampling + u;
+                    let block_y = mcu_y * vert_sampling + v;
+                    order.push(block_x + block_y * width);
+                }
+            }
+        }
+    }
+    order
+}
+
+fn write_marker(output: &mut Vec<u8>, marker: u8) {
+    output.push(0xFF);
+    output.push(marker);
+}
+
+/// Writes the marker and the segment length, which includes the two length bytes
+fn write_segment_header(output: &mut Vec<u8>, marker: u8, payload_length: usize) {
+    write_marker(output, marker);
+    output.extend(&((payload_length + 2) as u16).to_be_bytes());
+}
+
+fn write_app0(output: &mut Vec<u8>) {
+    write_segment_header(output, constants::MARKER_APP0, 14);
+    output.extend(b"JFIF\0");
+    // version 1.01
+    output.extend(&[1, 1]);
+    // no units, 1:1 pixel aspect ratio
+    output.push(0);
+    output.extend(&1_u16.to_be_bytes());
+    output.extend(&1_u16.to_be_bytes());
+    // no thumbnail
+    output.extend(&[0, 0]);
+}
+
+fn write_dqt(output: &mut Vec<u8>, tables: &[(u8, &[[u8; 8]; 8])]) {
+    write_segment_header(output, constants::MARKER_DQT, tables.len() * 65);
+    for (id, table) in tables {
+        // 8-bit precision
+        output.push(*id);
+        for natural in ZIG_ZAG.iter() {
+            output.push(table[natural / 8][natural % 8]);
+        }
+    }
+}
+
+fn write_sof0(output: &mut Vec<u8>, input: &EncoderInput) {
+    write_segment_header(output, constants::MARKER_SOF0, 6 + 3 * 3);
+    output.push(8);
+    output.extend(&input.height.to_be_bytes());
+    output.extend(&input.width.to_be_bytes());
+    output.push(3);
+    output.extend(&[
+        1,
+        (input.horiz_sampling << 4) | input.vert_sampling,
+        LUMINANCE_TABLE_ID,
+    ]);
+    output.extend(&[
+        2,
+        (input.cb_horiz_sampling << 4) | input.cb_vert_sampling,
+        CHROMINANCE_TABLE_ID,
+    ]);
+    output.extend(&[
+        3,
+        (input.cr_horiz_sampling << 4) | input.cr_vert_sampling,
+        CHROMINANCE_TABLE_ID,
+    ]);
+}
+
+/// Every table is described by (class, id, table), where class 0 is DC and 1 is AC
+fn write_dht(output: &mut Vec<u8>, tables: &[(u8, u8, &HuffmanTable)]) {
+    let payload_length = tables
+        .iter()
+        .map(|(_, _, table)| 1 + 16 + table.values.len())
+        .sum();
+    write_segment_header(output, constants::MARKER_DHT, payload_length);
+    for (class, id, table) in tables {
+        output.push((class << 4) | id);
+        output.extend(&table.bits);
+        output.extend(&table.values);
+    }
+}
+
+fn write_sos(output: &mut Vec<u8>) {
+    write_segment_header(output, constants::MARKER_SOS, 1 + 3 * 2 + 3);
+    output.push(3);
+    output.extend(&[1, (LUMINANCE_TABLE_ID << 4) | LUMINANCE_TABLE_ID]);
+    output.extend(&[2, (CHROMINANCE_TABLE_ID << 4) | CHROMINANCE_TABLE_ID]);
+    output.extend(&[3, (CHROMINANCE_TABLE_ID << 4) | CHROMINANCE_TABLE_ID]);
+    // full spectral selection, no successive approximation
+    output.extend(&[0, 63, 0]);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{encode, luminance_block_order, EncoderInput, HuffmanTables};
+    use crate::block::{Block, BlockMatrix};
+    use crate::quant::{CHROMINANCE_QUANTIZATION_TABLE, LUMINANCE_QUANTIZATION_TABLE};
+
+    fn flat_block_matrix(width: usize, height: usize, dc: i16) -> BlockMatrix {
+        let mut blocks = Vec::<Block>::new();
+        for _ in 0..width * height {
+            let mut block = [[0_i16; 8]; 8];
+            block[0][0] = dc;
+            blocks.push(Block(block));
+        }
+        BlockMatrix {
+            blocks,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    pub fn test_encode_structure() {
+        let ys = flat_block_matrix(4, 2, 10);
+        let cbs = flat_block_matrix(2, 1, 0);
+        let crs = flat_block_matrix(2, 1, 0);
+
+        let encoded = encode(&EncoderInput {
+            width: 32,
+            height: 16,
+            ys: &ys,
+            cbs: &cbs,
+            crs: &crs,
+            horiz_sampling: 2,
+            vert_sampling: 2,
+            cb_horiz_sampling: 1,
+            cb_vert_sampling: 1,
+            cr_horiz_sampling: 1,
+            cr_vert_sampling: 1,
+            luminance_quant_table: &LUMINANCE_QUANTIZATION_TABLE,
+            chrominance_quant_table: &CHROMINANCE_QUANTIZATION_TABLE,
+            huffman_tables: &HuffmanTables::standard(),
+        });
+
+        assert_eq!(&encoded[0..2], &[0xFF, 0xD8]);
+        assert_eq!(&encoded[6..11], b"JFIF\0");
+        assert_eq!(&encoded[encoded.len() - 2..], &[0xFF, 0xD9]);
+
+        let sof_position = encoded
+            .windows(2)
+            .position(|marker| marker == [0xFF, 0xC0])
+            .unwrap();
+        // height, width and the sampling factors of Y
+        assert_eq!(
+            &encoded[sof_position + 5..sof_position + 9],
+            &[0, 16, 0, 32]
+        );
+        assert_eq!(encoded[sof_position + 11], 0x22);
+    }
+
+    #[test]
+    pub fn test_luminance_block_order() {
+        assert_eq!(luminance_block_order(4, 1, 1, 1), vec![0, 1, 2, 3]);
+        assert_eq!(
+            luminance_block_order(4, 2, 2, 2),
+            vec![0, 1, 4, 5, 2, 3, 6, 7]
+        );
+        assert_eq!(luminance_block_order(2, 2, 1, 2), vec![0, 2, 1, 3]);
+    }
+
+    #[test]
+    pub fn test_encode_entropy_coded_data() {
+        let ys = flat_block_matrix(1, 1, 0);
+        let cbs = flat_block_matrix(1, 1, 0);
+        let crs = flat_block_matrix(1, 1, 0);
+
+        let encoded = encode(&EncoderInput {
+            width: 8,
+            height: 8,
+            ys: &ys,
+            cbs: &cbs,
+            crs: &crs,
+            horiz_sampling: 1,
+            vert_sampling: 1,
+            cb_horiz_sampling: 1,
+            cb_vert_sampling: 1,
+            cr_horiz_sampling: 1,
+            cr_vert_sampling: 1,
+            luminance_quant_table: &LUMINANCE_QUANTIZATION_TABLE,
+            chrominance_quant_table: &CHROMINANCE_QUANTIZATION_TABLE,
+            huffman_tables: &HuffmanTables::standard(),
+        });
+
+        // Y: DC 00, EOB 1010; Cb and Cr: DC 00, EOB 00; padded with ones
+        let scan_data = &encoded[encoded.len() - 4..encoded.len() - 2];
+        assert_eq!(scan_data, &[0b0010_1000, 0b0000_0011]);
+    }
+}
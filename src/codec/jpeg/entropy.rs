@@ -0,0 +1,221 @@
+use super::constants::ZIG_ZAG;
+use crate::block::BlockMatrix;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Dc,
+    Ac,
+    /// ZRL - run of 16 zero coefficients
+    ZeroRun,
+    /// EOB - all remaining coefficients are zero
+    EndOfBlock,
+}
+
+/// Single symbol of the entropy coded block, followed by `size` additional bits of the amplitude
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub kind: SymbolKind,
+    pub run: u8,
+    pub size: u8,
+    /// DC difference or AC coefficient value
+    pub amplitude: i16,
+    /// Zig-zag position of the coefficient that the symbol describes,
+    /// for ZRL and EOB it is the position of the last skipped coefficient
+    pub zig_zag_index: usize,
+}
+
+impl Symbol {
+    /// The value that is Huffman coded - SSSS for DC and RRRRSSSS for AC
+    pub fn huffman_value(&self) -> u8 {
+        (self.run << 4) | self.size
+    }
+
+    /// Additional bits that follow the codeword, negative values are stored as one's complement
+    pub fn amplitude_bits(&self) -> u16 {
+        // i32, so that neither i16::MIN - 1 nor the mask of 16 bits overflows
+        let amplitude = self.amplitude as i32;
+        if amplitude < 0 {
+            ((amplitude - 1) & ((1 << self.size) - 1)) as u16
+        } else {
+            amplitude as u16
+        }
+    }
+}
+
+/// Number of times every DC and AC value is Huffman coded, the amplitude bits that follow
+/// the codewords don't depend on the tables, so only their sum is kept
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SymbolFrequencies {
+    pub dc: [u32; 256],
+    pub ac: [u32; 256],
+    pub amplitude_bits: u64,
+}
+
+impl Default for SymbolFrequencies {
+    fn default() -> SymbolFrequencies {
+        SymbolFrequencies {
+            dc: [0; 256],
+            ac: [0; 256],
+            amplitude_bits: 0,
+        }
+    }
+}
+
+impl SymbolFrequencies {
+    /// Counts the symbols of the blocks coded in `order`, the DC of each block is predicted
+    /// from the previous one, so components sharing the tables are added one after another
+    pub fn add_blocks(&mut self, blocks: &BlockMatrix, order: &[usize]) {
+        let mut previous_dc = 0;
+        for index in order {
+            let block = &blocks.blocks[*index].0;
+            for symbol in block_symbols(block, previous_dc) {
+                match symbol.kind {
+                    SymbolKind::Dc => self.dc[symbol.huffman_value() as usize] += 1,
+                    _ => self.ac[symbol.huffman_value() as usize] += 1,
+                }
+                self.amplitude_bits += symbol.size as u64;
+            }
+            previous_dc = block[0][0];
+        }
+    }
+}
+
+pub fn magnitude_category(value: i16) -> u8 {
+    (16 - value.unsigned_abs().leading_zeros()) as u8
+}
+
+pub fn zig_zag(block: &[[i16; 8]; 8]) -> [i16; 64] {
+    let mut result = [0_i16; 64];
+    for (i, natural) in ZIG_ZAG.iter().enumerate() {
+        result[i] = block[natural / 8][natural % 8];
+    }
+    result
+}
+
+/// Converts a quantized block into the sequence of symbols: DC difference from the previous block
+/// of the same component, then the run-length coded AC coefficients
+pub fn block_symbols(block: &[[i16; 8]; 8], previous_dc: i16) -> Vec<Symbol> {
+    let coefficients = zig_zag(block);
+    let mut symbols = Vec::<Symbol>::new();
+
+    let dc_diff = coefficients[0] - previous_dc;
+    symbols.push(Symbol {
+        kind: SymbolKind::Dc,
+        run: 0,
+        size: magnitude_category(dc_diff),
+        amplitude: dc_diff,
+        zig_zag_index: 0,
+    });
+
+    let mut run: u8 = 0;
+    for (i, coefficient) in coefficients.iter().enumerate().skip(1) {
+        if *coefficient == 0 {
+            run += 1;
+            continue;
+        }
+        while run > 15 {
+            symbols.push(Symbol {
+                kind: SymbolKind::ZeroRun,
+                run: 15,
+                size: 0,
+                amplitude: 0,
+                zig_zag_index: i - run as usize + 15,
+            });
+            run -= 16;
+        }
+        symbols.push(Symbol {
+            kind: SymbolKind::Ac,
+            run,
+            size: magnitude_category(*coefficient),
+            amplitude: *coefficient,
+            zig_zag_index: i,
+        });
+        run = 0;
+    }
+    if run > 0 {
+        symbols.push(Symbol {
+            kind: SymbolKind::EndOfBlock,
+            run: 0,
+            size: 0,
+            amplitude: 0,
+            zig_zag_index: 63,
+        });
+    }
+    symbols
+}
+
+#[cfg(test)]
+mod test {
+    use super::{block_symbols, magnitude_category, SymbolKind};
+
+    #[test]
+    pub fn test_magnitude_category() {
+        assert_eq!(magnitude_category(0), 0);
+        assert_eq!(magnitude_category(1), 1);
+        assert_eq!(magnitude_category(-1), 1);
+        assert_eq!(magnitude_category(-3), 2);
+        assert_eq!(magnitude_category(4), 3);
+        assert_eq!(magnitude_category(-1023), 10);
+        assert_eq!(magnitude_category(2047), 11);
+    }
+
+    #[test]
+    pub fn test_block_symbols() {
+        // quantized block from https://en.wikipedia.org/wiki/JPEG#Entropy_coding
+        let block: [[i16; 8]; 8] = [
+            [-26, -3, -6, 2, 2, -1, 0, 0],
+            [0, -2, -4, 1, 1, 0, 0, 0],
+            [-3, 1, 5, -1, -1, 0, 0, 0],
+            [-3, 1, 2, -1, 0, 0, 0, 0],
+            [1, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0],
+        ];
+
+        let symbols = block_symbols(&block, -20);
+
+        assert_eq!(symbols[0].kind, SymbolKind::Dc);
+        assert_eq!(symbols[0].amplitude, -6);
+        assert_eq!(symbols[0].size, 3);
+        assert_eq!(symbols[0].amplitude_bits(), 0b001);
+        let mut extreme = [[0; 8]; 8];
+        extreme[0][0] = i16::MIN;
+        let extreme = block_symbols(&extreme, 0)[0];
+        assert_eq!(extreme.size, 16);
+        assert_eq!(extreme.amplitude_bits(), 0x7FFF);
+        assert_eq!(
+            symbols[1..7]
+                .iter()
+                .map(|symbol| symbol.amplitude)
+                .collect::<Vec<i16>>(),
+            vec![-3, -3, -2, -6, 2, -4]
+        );
+        // the zero coefficient at zig-zag position 2 is coded as a run before -3
+        assert_eq!(symbols[2].run, 1);
+        assert_eq!(symbols[2].zig_zag_index, 3);
+        assert_eq!(symbols.last().unwrap().kind, SymbolKind::EndOfBlock);
+        assert_eq!(
+            symbols
+                .iter()
+                .filter(|symbol| symbol.kind == SymbolKind::Ac)
+                .count(),
+            block.iter().flatten().filter(|x| **x != 0).count() - 1
+        );
+    }
+
+    #[test]
+    pub fn test_zero_run() {
+        let mut block = [[0_i16; 8]; 8];
+        block[7][7] = 5;
+
+        let symbols = block_symbols(&block, 0);
+
+        assert_eq!(symbols.len(), 1 + 3 + 1);
+        assert!(symbols[1..4]
+            .iter()
+            .all(|symbol| symbol.kind == SymbolKind::ZeroRun && symbol.huffman_value() == 0xF0));
+        assert_eq!(symbols[4].run, 62 - 48);
+        assert_eq!(symbols[4].zig_zag_index, 63);
+    }
+}
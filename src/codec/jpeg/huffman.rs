@@ -0,0 +1,355 @@
+use super::constants;
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct HuffmanCode {
+    pub code: u16,
+    pub length: u8,
+}
+
+/// Huffman table in the form it is stored inside the DHT segment
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HuffmanTable {
+    /// bits[i] is the number of codes that are i + 1 bits long
+    pub bits: [u8; 16],
+    pub values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    pub fn new(bits: [u8; 16], values: &[u8]) -> HuffmanTable {
+        HuffmanTable {
+            bits,
+            values: values.to_vec(),
+        }
+    }
+
+    pub fn luminance_dc() -> HuffmanTable {
+        HuffmanTable::new(
+            constants::LUMINANCE_DC_BITS,
+            &constants::LUMINANCE_DC_VALUES,
+        )
+    }
+
+    pub fn chrominance_dc() -> HuffmanTable {
+        HuffmanTable::new(
+            constants::CHROMINANCE_DC_BITS,
+            &constants::CHROMINANCE_DC_VALUES,
+        )
+    }
+
+    pub fn luminance_ac() -> HuffmanTable {
+        HuffmanTable::new(
+            constants::LUMINANCE_AC_BITS,
+            &constants::LUMINANCE_AC_VALUES,
+        )
+    }
+
+    pub fn chrominance_ac() -> HuffmanTable {
+        HuffmanTable::new(
+            constants::CHROMINANCE_AC_BITS,
+            &constants::CHROMINANCE_AC_VALUES,
+        )
+    }
+
+    /// Builds the table with the shortest code for the symbol counts, following the Annex K.2
+    /// of the JPEG standard - a reserved symbol keeps any codeword from being all ones
+    /// and the codes longer than 16 bits are moved up the tree
+    pub fn optimal(frequencies: &[u32; 256]) -> HuffmanTable {
+        let mut frequencies = frequencies.iter().map(|x| *x as u64).collect::<Vec<u64>>();
+        frequencies.push(1);
+        let mut code_sizes = [0_usize; 257];
+        let mut others = [None::<usize>; 257];
+
+        // the least frequent symbol, the largest value wins a tie
+        let least_frequent = |frequencies: &[u64], excluded: Option<usize>| {
+            let mut least = None::<usize>;
+            for (value, frequency) in frequencies.iter().enumerate() {
+                if *frequency > 0
+                    && Some(value) != excluded
+                    && least.is_none_or(|least| *frequency <= frequencies[least])
+                {
+                    least = Some(value);
+                }
+            }
+            least
+        };
+        while let Some(v1) = least_frequent(&frequencies, None) {
+            let v2 = match least_frequent(&frequencies, Some(v1)) {
+                Some(v2) => v2,
+                None => break,
+            };
+            frequencies[v1] += frequencies[v2];
+            frequencies[v2] = 0;
+            // both branches get one bit longer, the second one is chained after the first
+            let mut v = v1;
+            code_sizes[v] += 1;
+            while let Some(other) = others[v] {
+                v = other;
+                code_sizes[v] += 1;
+            }
+            others[v] = Some(v2);
+            let mut v = v2;
+            code_sizes[v] += 1;
+            while let Some(other) = others[v] {
+                v = other;
+                code_sizes[v] += 1;
+            }
+        }
+
+        let mut bits = [0_u8; 33];
+        for size in code_sizes.iter().filter(|size| **size > 0) {
+            bits[*size.min(&32)] += 1;
+        }
+        for i in (17..=32).rev() {
+            while bits[i] > 0 {
+                let mut j = i - 2;
+                while bits[j] == 0 {
+                    j -= 1;
+                }
+                bits[i] -= 2;
+                bits[i - 1] += 1;
+                bits[j + 1] += 2;
+                bits[j] -= 1;
+            }
+        }
+        // the reserved symbol takes one of the longest codes
+        if let Some(longest) = (1..=16).rev().find(|i| bits[*i] > 0) {
+            bits[longest] -= 1;
+        }
+
+        let mut values = (0..256)
+            .filter(|value| code_sizes[*value] > 0)
+            .collect::<Vec<usize>>();
+        values.sort_by_key(|value| code_sizes[*value]);
+        let mut table_bits = [0_u8; 16];
+        table_bits.copy_from_slice(&bits[1..17]);
+        HuffmanTable {
+            bits: table_bits,
+            values: values.iter().map(|value| *value as u8).collect(),
+        }
+    }
+
+    /// Bits taken by the codewords of the symbols, the amplitude bits are left out
+    pub fn coded_bits(&self, frequencies: &[u32; 256]) -> u64 {
+        self.codes()
+            .iter()
+            .zip(frequencies.iter())
+            .map(|(code, frequency)| code.length as u64 * *frequency as u64)
+            .sum()
+    }
+
+    /// Assigns codewords to the symbols as described in the Annex C of the JPEG standard,
+    /// the result is indexed by the symbol value
+    pub fn codes(&self) -> [HuffmanCode; 256] {
+        let mut codes = [HuffmanCode::default(); 256];
+        let mut code: u16 = 0;
+        let mut k = 0;
+        for (i, count) in self.bits.iter().enumerate() {
+            for _ in 0..*count {
+                codes[self.values[k] as usize] = HuffmanCode {
+                    code,
+                    length: i as u8 + 1,
+                };
+                code += 1;
+                k += 1;
+            }
+            code <<= 1;
+        }
+        codes
+    }
+
+    /// Builds the MAXCODE/VALPTR/MINCODE tables from the Annex F.2.2.3 of the JPEG standard
+    pub fn lookup(&self) -> HuffmanLookup {
+        let mut lookup = HuffmanLookup {
+            max_code: [-1; 17],
+            min_code: [0; 17],
+            value_pointer: [0; 17],
+            values: self.values.clone(),
+        };
+        let mut code: i32 = 0;
+        let mut k: usize = 0;
+        for length in 1..=16 {
+            let count = self.bits[length - 1] as usize;
+            if count > 0 {
+                lookup.value_pointer[length] = k;
+                lookup.min_code[length] = code;
+                code += count as i32;
+                k += count;
+                lookup.max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        lookup
+    }
+}
+
+pub struct HuffmanLookup {
+    max_code: [i32; 17],
+    min_code: [i32; 17],
+    value_pointer: [usize; 17],
+    values: Vec<u8>,
+}
+
+impl HuffmanLookup {
+    /// Returns the symbol if `code` is a complete codeword of the given length (1 to 16 bits)
+    pub fn symbol(&self, code: i32, length: usize) -> Option<u8> {
+        if code <= self.max_code[length] {
+            let index = self.value_pointer[length] + (code - self.min_code[length]) as usize;
+            self.values.get(index).copied()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HuffmanCode, HuffmanTable};
+
+    #[test]
+    pub fn test_luminance_dc_codes() {
+        // codes from the Table K.3 of the JPEG standard
+        let codes = HuffmanTable::luminance_dc().codes();
+
+        assert_eq!(
+            codes[0],
+            HuffmanCode {
+                code: 0b00,
+                length: 2
+            }
+        );
+        assert_eq!(
+            codes[1],
+            HuffmanCode {
+                code: 0b010,
+                length: 3
+            }
+        );
+        assert_eq!(
+            codes[5],
+            HuffmanCode {
+                code: 0b110,
+                length: 3
+            }
+        );
+        assert_eq!(
+            codes[6],
+            HuffmanCode {
+                code: 0b1110,
+                length: 4
+            }
+        );
+        assert_eq!(
+            codes[11],
+            HuffmanCode {
+                code: 0b1_1111_1110,
+                length: 9
+            }
+        );
+    }
+
+    #[test]
+    pub fn test_luminance_ac_codes() {
+        // codes from the Table K.5 of the JPEG standard
+        let codes = HuffmanTable::luminance_ac().codes();
+
+        assert_eq!(
+            codes[0x00],
+            HuffmanCode {
+                code: 0b1010,
+                length: 4
+            }
+        );
+        assert_eq!(
+            codes[0x01],
+            HuffmanCode {
+                code: 0b00,
+                length: 2
+            }
+        );
+        assert_eq!(
+            codes[0x11],
+            HuffmanCode {
+                code: 0b1100,
+                length: 4
+            }
+        );
+        assert_eq!(
+            codes[0xF0],
+            HuffmanCode {
+                code: 0b111_1111_1001,
+                length: 11
+            }
+        );
+        assert_eq!(
+            codes[0xFA],
+            HuffmanCode {
+                code: 0xFFFE,
+                length: 16
+            }
+        );
+    }
+
+    #[test]
+    pub fn test_optimal_table() {
+        let mut frequencies = [0_u32; 256];
+        frequencies[0x00] = 50;
+        frequencies[0x01] = 30;
+        frequencies[0x11] = 15;
+        frequencies[0xF0] = 5;
+        let table = HuffmanTable::optimal(&frequencies);
+
+        assert_eq!(table.values, vec![0x00, 0x01, 0x11, 0xF0]);
+        assert_eq!(table.bits[..4], [1, 1, 1, 1]);
+        // 50 * 1 + 30 * 2 + 15 * 3 + 5 * 4 bits, the reserved code takes the other 4-bit slot
+        assert_eq!(table.coded_bits(&frequencies), 175);
+        assert!(
+            table.coded_bits(&frequencies) < HuffmanTable::luminance_ac().coded_bits(&frequencies)
+        );
+    }
+
+    #[test]
+    pub fn test_optimal_table_is_length_limited() {
+        // Fibonacci counts make the unlimited Huffman tree as deep as the number of symbols
+        let mut frequencies = [0_u32; 256];
+        let (mut a, mut b) = (1, 1);
+        for frequency in frequencies.iter_mut().take(30) {
+            *frequency = a;
+            (a, b) = (b, a + b);
+        }
+        let table = HuffmanTable::optimal(&frequencies);
+        let codes = table.codes();
+        let lookup = table.lookup();
+
+        assert_eq!(table.values.len(), 30);
+        assert_eq!(table.bits.iter().map(|x| *x as usize).sum::<usize>(), 30);
+        for value in table.values.iter() {
+            let code = codes[*value as usize];
+            assert!(code.length <= 16);
+            // no codeword is made of ones only
+            assert_ne!(code.code as u32, (1_u32 << code.length) - 1);
+            assert_eq!(
+                lookup.symbol(code.code as i32, code.length as usize),
+                Some(*value)
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_lookup_decodes_every_code() {
+        let table = HuffmanTable::chrominance_ac();
+        let codes = table.codes();
+        let lookup = table.lookup();
+
+        for value in table.values.iter() {
+            let code = codes[*value as usize];
+            for length in 1..code.length {
+                let prefix = (code.code >> (code.length - length)) as i32;
+                assert_eq!(lookup.symbol(prefix, length as usize), None);
+            }
+            assert_eq!(
+                lookup.symbol(code.code as i32, code.length as usize),
+                Some(*value)
+            );
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use super::entropy::magnitude_category;
+use super::huffman::HuffmanTable;
+use strum_macros::EnumIter;
+
+/// Predictors of the lossless process, Table H.1 of the JPEG standard - Ra is the sample
+/// to the left, Rb the one above and Rc the one above and to the left
+#[derive(Debug, PartialEq, Eq, Hash, EnumIter, Clone, Copy)]
+pub enum Predictor {
+    Left,
+    Above,
+    AboveLeft,
+    Gradient,
+    LeftGradient,
+    AboveGradient,
+    Average,
+}
+
+impl Predictor {
+    /// Value of the Ss field of the SOS segment that chooses the predictor
+    pub fn selection_value(&self) -> u8 {
+        match self {
+            Predictor::Left => 1,
+            Predictor::Above => 2,
+            Predictor::AboveLeft => 3,
+            Predictor::Gradient => 4,
+            Predictor::LeftGradient => 5,
+            Predictor::AboveGradient => 6,
+            Predictor::Average => 7,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Predictor::Left => "Ra",
+            Predictor::Above => "Rb",
+            Predictor::AboveLeft => "Rc",
+            Predictor::Gradient => "Ra + Rb - Rc",
+            Predictor::LeftGradient => "Ra + ((Rb - Rc) >> 1)",
+            Predictor::AboveGradient => "Rb + ((Ra - Rc) >> 1)",
+            Predictor::Average => "(Ra + Rb) >> 1",
+        }
+    }
+
+    fn predict(&self, ra: i32, rb: i32, rc: i32) -> i32 {
+        match self {
+            Predictor::Left => ra,
+            Predictor::Above => rb,
+            Predictor::AboveLeft => rc,
+            Predictor::Gradient => ra + rb - rc,
+            Predictor::LeftGradient => ra + ((rb - rc) >> 1),
+            Predictor::AboveGradient => rb + ((ra - rc) >> 1),
+            Predictor::Average => (ra + rb) >> 1,
+        }
+    }
+}
+
+/// Differences between the 8-bit samples and their predictions, both stored row by row.
+/// The first sample is predicted by 128, the rest of the first row by Ra and the first
+/// column by Rb, the differences of 8-bit samples never need the modulo 2^16 of the standard
+pub fn residuals(samples: &[u8], width: usize, predictor: Predictor) -> Vec<i16> {
+    let sample = |x: usize, y: usize| samples[y * width + x] as i32;
+    samples
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let (x, y) = (i % width, i / width);
+            let prediction = match (x, y) {
+                (0, 0) => 128,
+                (_, 0) => sample(x - 1, 0),
+                (0, _) => sample(0, y - 1),
+                _ => predictor.predict(sample(x - 1, y), sample(x, y - 1), sample(x - 1, y - 1)),
+            };
+            (*value as i32 - prediction) as i16
+        })
+        .collect()
+}
+
+/// Residuals of one component Huffman coded like the DC differences - the SSSS category
+/// with a table built for the component, followed by SSSS bits of the value
+#[derive(Clone, Debug)]
+pub struct ResidualCoding {
+    pub categories: [u32; 256],
+    pub table: HuffmanTable,
+    pub bits: u64,
+}
+
+impl ResidualCoding {
+    pub fn new(residuals: &[i16]) -> ResidualCoding {
+        let mut categories = [0_u32; 256];
+        for residual in residuals {
+            categories[magnitude_category(*residual) as usize] += 1;
+        }
+        let table = HuffmanTable::optimal(&categories);
+        let amplitude_bits: u64 = categories
+            .iter()
+            .enumerate()
+            .map(|(category, count)| category as u64 * *count as u64)
+            .sum();
+        let bits = table.coded_bits(&categories) + amplitude_bits;
+        ResidualCoding {
+            categories,
+            table,
+            bits,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{residuals, Predictor, ResidualCoding};
+    use strum::IntoEnumIterator;
+
+    #[test]
+    pub fn test_predictors() {
+        // the last sample has Ra = 30, Rb = 20, Rc = 11
+        let samples = [11, 20, 30, 40];
+        let last_residuals = Predictor::iter()
+            .map(|predictor| residuals(&samples, 2, predictor)[3])
+            .collect::<Vec<i16>>();
+
+        // 40 - 30, 40 - 20, 40 - 11, 40 - 39, 40 - 34, 40 - 29, 40 - 25
+        assert_eq!(last_residuals, vec![10, 20, 29, 1, 6, 11, 15]);
+        assert_eq!(
+            Predictor::iter()
+                .map(|predictor| predictor.selection_value())
+                .collect::<Vec<u8>>(),
+            vec![1, 2, 3, 4, 5, 6, 7]
+        );
+    }
+
+    #[test]
+    pub fn test_edges_use_neighbours() {
+        let samples = [100, 111, 120, 91, 0, 0];
+        // the first row and column don't depend on the predictor
+        for predictor in Predictor::iter() {
+            let residuals = residuals(&samples, 3, predictor);
+            assert_eq!(residuals[..4], [-28, 11, 9, -9]);
+        }
+        // 91 + (11 >> 1)
+        assert_eq!(residuals(&samples, 3, Predictor::LeftGradient)[4], -96);
+        // 111 + (-9 >> 1), the arithmetic shift rounds the negative half down to -5
+        assert_eq!(residuals(&samples, 3, Predictor::AboveGradient)[4], -106);
+    }
+
+    #[test]
+    pub fn test_residual_coding() {
+        // a flat grey area, even the first sample is predicted exactly
+        let flat = residuals(&[128; 64], 8, Predictor::Gradient);
+        let coding = ResidualCoding::new(&flat);
+        assert_eq!(coding.categories[0], 64);
+        // the reserved codeword keeps the only category one bit long
+        assert_eq!(coding.bits, 64);
+
+        let coding = ResidualCoding::new(&[0, 0, 1, -1, 3, -200]);
+        assert_eq!(coding.categories[..9], [2, 2, 1, 0, 0, 0, 0, 0, 1]);
+        let codes = coding.table.codes();
+        let codeword_bits: u64 = [0, 0, 1, 1, 2, 8]
+            .iter()
+            .map(|category: &usize| codes[*category].length as u64)
+            .sum();
+        assert_eq!(coding.bits, codeword_bits + 1 + 1 + 2 + 8);
+    }
+}
@@ -0,0 +1,7 @@
+pub mod constants;
+pub mod decoder;
+pub mod encoder;
+pub mod entropy;
+pub mod huffman;
+pub mod lossless;
+pub mod segments;
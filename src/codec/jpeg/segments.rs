@@ -0,0 +1,662 @@
+use super::constants::{self, ZIG_ZAG};
+use super::decoder::DecodeError;
+
+/// Marker segment of a JPEG file, or the entropy-coded data that follows a scan header
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+    // `None` for the entropy-coded data and its parts between the restart markers
+    pub marker: Option<u8>,
+    // position of the 0xFF of the marker and the number of bytes including the marker
+    pub offset: usize,
+    pub length: usize,
+    pub content: SegmentContent,
+    // restart markers and the entropy-coded data between them
+    pub children: Vec<Segment>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SegmentContent {
+    Empty,
+    Fields(Vec<(&'static str, String)>),
+    QuantizationTables(Vec<QuantizationTableDefinition>),
+    HuffmanTables(Vec<HuffmanTableDefinition>),
+    Invalid(&'static str),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuantizationTableDefinition {
+    pub id: u8,
+    // 8 or 16 bits per value
+    pub precision: u8,
+    // natural order
+    pub values: [[u16; 8]; 8],
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HuffmanTableDefinition {
+    // 0 for the DC and 1 for the AC tables
+    pub class: u8,
+    pub id: u8,
+    // number of codes of every length from 1 to 16 bits
+    pub bits: [u8; 16],
+    pub values: Vec<u8>,
+}
+
+impl Segment {
+    fn standalone(marker: u8, offset: usize) -> Segment {
+        Segment {
+            marker: Some(marker),
+            offset,
+            length: 2,
+            content: SegmentContent::Empty,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn end(&self) -> usize {
+        self.offset + self.length
+    }
+
+    pub fn name(&self) -> String {
+        match self.marker {
+            Some(marker) => marker_name(marker),
+            None => "ECS".to_owned(),
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self.marker {
+            Some(marker) => marker_description(marker),
+            None => "Entropy-coded data",
+        }
+    }
+
+    pub fn is_entropy_coded(&self) -> bool {
+        self.marker.is_none()
+    }
+}
+
+pub fn marker_name(marker: u8) -> String {
+    match marker {
+        0x01 => "TEM".to_owned(),
+        constants::MARKER_DHT => "DHT".to_owned(),
+        0xC8 => "JPG".to_owned(),
+        0xCC => "DAC".to_owned(),
+        0xC0..=0xCF => format!("SOF{}", marker - constants::MARKER_SOF0),
+        constants::MARKER_RST0..=constants::MARKER_RST7 => {
+            format!("RST{}", marker - constants::MARKER_RST0)
+        }
+        constants::MARKER_SOI => "SOI".to_owned(),
+        constants::MARKER_EOI => "EOI".to_owned(),
+        constants::MARKER_SOS => "SOS".to_owned(),
+        constants::MARKER_DQT => "DQT".to_owned(),
+        0xDC => "DNL".to_owned(),
+        constants::MARKER_DRI => "DRI".to_owned(),
+        0xDE => "DHP".to_owned(),
+        0xDF => "EXP".to_owned(),
+        0xE0..=0xEF => format!("APP{}", marker - constants::MARKER_APP0),
+        0xF0..=0xFD => format!("JPG{}", marker - 0xF0),
+        MARKER_COM => "COM".to_owned(),
+        _ => format!("0x{:02X}", marker),
+    }
+}
+
+pub fn marker_description(marker: u8) -> &'static str {
+    match marker {
+        0x01 => "Temporary",
+        constants::MARKER_DHT => "Define Huffman tables",
+        0xC8 => "Reserved",
+        0xCC => "Define arithmetic coding conditioning",
+        0xC0..=0xCF => "Start of frame",
+        constants::MARKER_RST0..=constants::MARKER_RST7 => "Restart",
+        constants::MARKER_SOI => "Start of image",
+        constants::MARKER_EOI => "End of image",
+        constants::MARKER_SOS => "Start of scan",
+        constants::MARKER_DQT => "Define quantization tables",
+        0xDC => "Define number of lines",
+        constants::MARKER_DRI => "Define restart interval",
+        0xDE => "Define hierarchical progression",
+        0xDF => "Expand reference components",
+        0xE0..=0xEF => "Application data",
+        MARKER_COM => "Comment",
+        _ => "Reserved",
+    }
+}
+
+const MARKER_COM: u8 = 0xFE;
+
+/// Walks all marker segments of the file, the entropy-coded data after every SOS is a segment
+/// of its own with the restart markers as children, a truncated segment ends the list as `Invalid`
+pub fn parse_segments(data: &[u8]) -> Result<Vec<Segment>, DecodeError> {
+    if !data.starts_with(&[0xFF, constants::MARKER_SOI]) {
+        return Err(DecodeError::NotJpeg);
+    }
+    let mut segments = vec![Segment::standalone(constants::MARKER_SOI, 0)];
+    let mut position = 2;
+    while position + 1 < data.len() {
+        // anything but a marker between the segments is skipped, so are the 0xFF fill bytes
+        if data[position] != 0xFF || data[position + 1] == 0xFF {
+            position += 1;
+            continue;
+        }
+        let marker = data[position + 1];
+        if [0x01, constants::MARKER_SOI, constants::MARKER_EOI].contains(&marker)
+            || (constants::MARKER_RST0..=constants::MARKER_RST7).contains(&marker)
+        {
+            segments.push(Segment::standalone(marker, position));
+            position += 2;
+            if marker == constants::MARKER_EOI {
+                break;
+            }
+            continue;
+        }
+
+        let length = data
+            .get(position + 2..position + 4)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize);
+        let (content, length) = match length {
+            Some(length) if length < 2 => (SegmentContent::Invalid("segment length"), 4),
+            Some(length) if position + 2 + length <= data.len() => {
+                let payload = &data[position + 4..position + 2 + length];
+                (segment_content(marker, payload), length + 2)
+            }
+            _ => (
+                SegmentContent::Invalid("truncated segment"),
+                data.len() - position,
+            ),
+        };
+        let invalid = matches!(content, SegmentContent::Invalid(_));
+        segments.push(Segment {
+            marker: Some(marker),
+            offset: position,
+            length,
+            content,
+            children: Vec::new(),
+        });
+        position += length;
+        if invalid {
+            break;
+        }
+        if marker == constants::MARKER_SOS {
+            let entropy_coded_data = entropy_coded_data(data, position);
+            position = entropy_coded_data.end();
+            segments.push(entropy_coded_data);
+        }
+    }
+    Ok(segments)
+}
+
+/// Quantization tables of the file ordered by their id, a table defined again replaces the first one
+pub fn quantization_tables(segments: &[Segment]) -> Vec<QuantizationTableDefinition> {
+    let mut tables = Vec::<QuantizationTableDefinition>::new();
+    for segment in segments {
+        if let SegmentContent::QuantizationTables(definitions) = &segment.content {
+            for definition in definitions {
+                tables.retain(|table| table.id != definition.id);
+                tables.push(definition.clone());
+            }
+        }
+    }
+    tables.sort_by_key(|table| table.id);
+    tables
+}
+
+/// Quantization table ids of the frame components in the order of the SOF segment,
+/// empty when the file has no readable frame header
+pub fn frame_quant_table_ids(data: &[u8], segments: &[Segment]) -> Vec<u8> {
+    segments
+        .iter()
+        .find(|segment| {
+            matches!(segment.marker, Some(0xC0..=0xCF))
+                && matches!(segment.content, SegmentContent::Fields(_))
+        })
+        .and_then(|segment| {
+            let payload = data.get(segment.offset + 4..segment.end())?;
+            let count = *payload.get(5)? as usize;
+            let components = payload.get(6..6 + 3 * count)?;
+            Some(components.chunks(3).map(|component| component[2]).collect())
+        })
+        .unwrap_or_default()
+}
+
+/// Entropy-coded data from `start` up to the first marker other than RSTn,
+/// 0xFF is followed by a stuffed zero byte wherever it is part of the data
+fn entropy_coded_data(data: &[u8], start: usize) -> Segment {
+    let mut children = Vec::<Segment>::new();
+    let mut position = start;
+    let mut part_start = start;
+    let mut stuffed_bytes = 0;
+    let mut total_stuffed_bytes = 0;
+    let part = |start: usize, end: usize, stuffed_bytes: usize| Segment {
+        marker: None,
+        offset: start,
+        length: end - start,
+        content: SegmentContent::Fields(vec![("Stuffed bytes", stuffed_bytes.to_string())]),
+        children: Vec::new(),
+    };
+
+    while position + 1 < data.len() {
+        if data[position] != 0xFF {
+            position += 1;
+            continue;
+        }
+        match data[position + 1] {
+            0x00 => {
+                stuffed_bytes += 1;
+                position += 2;
+            }
+            0xFF => position += 1,
+            marker @ constants::MARKER_RST0..=constants::MARKER_RST7 => {
+                children.push(part(part_start, position, stuffed_bytes));
+                children.push(Segment::standalone(marker, position));
+                total_stuffed_bytes += stuffed_bytes;
+                stuffed_bytes = 0;
+                position += 2;
+                part_start = position;
+            }
+            _ => break,
+        }
+    }
+    let end = if position + 1 < data.len() {
+        position
+    } else {
+        data.len()
+    };
+    total_stuffed_bytes += stuffed_bytes;
+
+    let restart_markers = children.len() / 2;
+    if restart_markers > 0 {
+        children.push(part(part_start, end, stuffed_bytes));
+    }
+    Segment {
+        marker: None,
+        offset: start,
+        length: end - start,
+        content: SegmentContent::Fields(vec![
+            ("Restart markers", restart_markers.to_string()),
+            ("Stuffed bytes", total_stuffed_bytes.to_string()),
+        ]),
+        children,
+    }
+}
+
+fn segment_content(marker: u8, payload: &[u8]) -> SegmentContent {
+    let content = match marker {
+        constants::MARKER_DQT => {
+            read_quantization_tables(payload).map(SegmentContent::QuantizationTables)
+        }
+        constants::MARKER_DHT => read_huffman_tables(payload).map(SegmentContent::HuffmanTables),
+        0xC8 | 0xCC => Some(SegmentContent::Empty),
+        0xC0..=0xCF => frame_fields(marker, payload).map(SegmentContent::Fields),
+        constants::MARKER_SOS => scan_fields(payload).map(SegmentContent::Fields),
+        constants::MARKER_DRI => read_u16(payload, 0).map(|interval| {
+            SegmentContent::Fields(vec![("Restart interval", format!("{} MCUs", interval))])
+        }),
+        0xE0..=0xEF => Some(SegmentContent::Fields(application_fields(marker, payload))),
+        MARKER_COM => Some(SegmentContent::Fields(vec![(
+            "Text",
+            String::from_utf8_lossy(payload).into_owned(),
+        )])),
+        _ => Some(SegmentContent::Empty),
+    };
+    content.unwrap_or(SegmentContent::Invalid("malformed segment"))
+}
+
+fn read_u16(payload: &[u8], at: usize) -> Option<u16> {
+    payload
+        .get(at..at + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_quantization_tables(payload: &[u8]) -> Option<Vec<QuantizationTableDefinition>> {
+    let mut tables = Vec::new();
+    let mut i = 0;
+    while i < payload.len() {
+        let precision = payload[i] >> 4;
+        let value_size = if precision == 0 { 1 } else { 2 };
+        let bytes = payload.get(i + 1..i + 1 + 64 * value_size)?;
+        let mut values = [[0_u16; 8]; 8];
+        for (k, natural) in ZIG_ZAG.iter().enumerate() {
+            values[natural / 8][natural % 8] = if value_size == 1 {
+                bytes[k] as u16
+            } else {
+                u16::from_be_bytes([bytes[2 * k], bytes[2 * k + 1]])
+            };
+        }
+        tables.push(QuantizationTableDefinition {
+            id: payload[i] & 0x0F,
+            precision: 8 * value_size as u8,
+            values,
+        });
+        i += 1 + 64 * value_size;
+    }
+    Some(tables)
+}
+
+fn read_huffman_tables(payload: &[u8]) -> Option<Vec<HuffmanTableDefinition>> {
+    let mut tables = Vec::new();
+    let mut i = 0;
+    while i < payload.len() {
+        let mut bits = [0_u8; 16];
+        bits.copy_from_slice(payload.get(i + 1..i + 17)?);
+        let count = bits.iter().map(|x| *x as usize).sum::<usize>();
+        let values = payload.get(i + 17..i + 17 + count)?.to_vec();
+        tables.push(HuffmanTableDefinition {
+            class: payload[i] >> 4,
+            id: payload[i] & 0x0F,
+            bits,
+            values,
+        });
+        i += 17 + count;
+    }
+    Some(tables)
+}
+
+fn frame_fields(marker: u8, payload: &[u8]) -> Option<Vec<(&'static str, String)>> {
+    let process = match marker & 0x03 {
+        _ if marker == constants::MARKER_SOF0 => "Baseline DCT",
+        0 | 1 => "Extended sequential DCT",
+        2 => "Progressive DCT",
+        _ => "Lossless",
+    };
+    let coding = if marker & 0x08 != 0 {
+        "Arithmetic"
+    } else {
+        "Huffman"
+    };
+    let differential = if marker & 0x04 != 0 {
+        ", differential"
+    } else {
+        ""
+    };
+    let count = *payload.get(5)? as usize;
+    let components = payload.get(6..6 + 3 * count)?;
+    let mut fields = vec![
+        ("Process", format!("{}{}", process, differential)),
+        ("Entropy coding", coding.to_owned()),
+        ("Precision", format!("{} bits", payload[0])),
+        (
+            "Size",
+            format!("{}x{}", read_u16(payload, 3)?, read_u16(payload, 1)?),
+        ),
+    ];
+    for component in components.chunks(3) {
+        fields.push((
+            "Component",
+            format!(
+                "{}: sampling {}x{}, quantization table {}",
+                component[0],
+                component[1] >> 4,
+                component[1] & 0x0F,
+                component[2]
+            ),
+        ));
+    }
+    Some(fields)
+}
+
+fn scan_fields(payload: &[u8]) -> Option<Vec<(&'static str, String)>> {
+    let count = *payload.first()? as usize;
+    let components = payload.get(1..1 + 2 * count)?;
+    let parameters = payload.get(1 + 2 * count..4 + 2 * count)?;
+    let mut fields = components
+        .chunks(2)
+        .map(|component| {
+            (
+                "Component",
+                format!(
+                    "{}: DC table {}, AC table {}",
+                    component[0],
+                    component[1] >> 4,
+                    component[1] & 0x0F
+                ),
+            )
+        })
+        .collect::<Vec<(&'static str, String)>>();
+    fields.push((
+        "Spectral selection",
+        format!("{}..{}", parameters[0], parameters[1]),
+    ));
+    fields.push((
+        "Successive approximation",
+        format!("Ah {}, Al {}", parameters[2] >> 4, parameters[2] & 0x0F),
+    ));
+    Some(fields)
+}
+
+/// Identifier of the application segment and the fields of the few well-known ones
+fn application_fields(marker: u8, payload: &[u8]) -> Vec<(&'static str, String)> {
+    let identifier_end = payload
+        .iter()
+        .take(64)
+        .position(|byte| *byte == 0)
+        .unwrap_or(0);
+    let identifier = String::from_utf8_lossy(&payload[..identifier_end]).into_owned();
+    let mut fields = vec![("Identifier", identifier.clone())];
+    match (marker - constants::MARKER_APP0, identifier.as_str()) {
+        (0, "JFIF") if payload.len() >= 14 => {
+            let units = match payload[7] {
+                0 => "aspect ratio",
+                1 => "dots per inch",
+                2 => "dots per cm",
+                _ => "unknown units",
+            };
+            fields.push(("Version", format!("{}.{:02}", payload[5], payload[6])));
+            fields.push((
+                "Density",
+                format!(
+                    "{}x{} {}",
+                    read_u16(payload, 8).unwrap_or(0),
+                    read_u16(payload, 10).unwrap_or(0),
+                    units
+                ),
+            ));
+            fields.push(("Thumbnail", format!("{}x{}", payload[12], payload[13])));
+        }
+        (1, "Exif") if payload.len() >= 8 => {
+            let byte_order = match &payload[6..8] {
+                b"II" => "little-endian (II)",
+                b"MM" => "big-endian (MM)",
+                _ => "unknown",
+            };
+            fields.push(("TIFF byte order", byte_order.to_owned()));
+        }
+        (2, "ICC_PROFILE") if payload.len() >= 14 => {
+            fields.push(("Chunk", format!("{} of {}", payload[12], payload[13])));
+            // only the first chunk starts with the profile header
+            if payload[12] == 1 && payload.len() >= 14 + 20 {
+                let size = u32::from_be_bytes([payload[14], payload[15], payload[16], payload[17]]);
+                fields.push(("Profile size", format!("{} bytes", size)));
+                fields.push((
+                    "Colour space",
+                    String::from_utf8_lossy(&payload[14 + 16..14 + 20])
+                        .trim()
+                        .to_owned(),
+                ));
+            }
+        }
+        (14, _) if payload.starts_with(b"Adobe") && payload.len() >= 12 => {
+            fields[0].1 = "Adobe".to_owned();
+            let transform = match payload[11] {
+                0 => "none (RGB or CMYK)",
+                1 => "YCbCr",
+                2 => "YCCK",
+                _ => "unknown",
+            };
+            fields.push(("Version", read_u16(payload, 5).unwrap_or(0).to_string()));
+            fields.push(("Colour transform", transform.to_owned()));
+        }
+        _ => {}
+    }
+    fields
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        frame_quant_table_ids, parse_segments, quantization_tables, QuantizationTableDefinition,
+        Segment, SegmentContent,
+    };
+    use crate::block::{Block, BlockMatrix};
+    use crate::codec::jpeg::constants::{
+        LUMINANCE_DC_BITS, LUMINANCE_DC_VALUES, MARKER_DHT, MARKER_DQT,
+    };
+    use crate::codec::jpeg::decoder::DecodeError;
+    use crate::codec::jpeg::encoder::{encode, EncoderInput, HuffmanTables};
+    use crate::quant::{CHROMINANCE_QUANTIZATION_TABLE, LUMINANCE_QUANTIZATION_TABLE};
+
+    #[test]
+    pub fn test_parse_encoded() {
+        let blocks = |width: usize, height: usize| BlockMatrix {
+            blocks: vec![Block([[0; 8]; 8]); width * height],
+            width,
+            height,
+        };
+        let encoded = encode(&EncoderInput {
+            width: 16,
+            height: 16,
+            ys: &blocks(2, 2),
+            cbs: &blocks(1, 1),
+            crs: &blocks(1, 1),
+            horiz_sampling: 2,
+            vert_sampling: 2,
+            cb_horiz_sampling: 1,
+            cb_vert_sampling: 1,
+            cr_horiz_sampling: 1,
+            cr_vert_sampling: 1,
+            luminance_quant_table: &LUMINANCE_QUANTIZATION_TABLE,
+            chrominance_quant_table: &CHROMINANCE_QUANTIZATION_TABLE,
+            huffman_tables: &HuffmanTables::standard(),
+        });
+        let segments = parse_segments(&encoded).unwrap();
+
+        let names = segments
+            .iter()
+            .map(|segment| segment.name())
+            .collect::<Vec<String>>();
+        assert_eq!(names.first().unwrap(), "SOI");
+        assert_eq!(names[names.len() - 3..], ["SOS", "ECS", "EOI"]);
+        // the segments cover the whole file without gaps
+        for pair in segments.windows(2) {
+            assert_eq!(pair[0].end(), pair[1].offset);
+        }
+        assert_eq!(segments.last().unwrap().end(), encoded.len());
+
+        let dqt = segments.iter().find(|segment| segment.name() == "DQT");
+        match &dqt.unwrap().content {
+            SegmentContent::QuantizationTables(tables) => {
+                assert_eq!(tables.len(), 2);
+                assert_eq!(tables[0].precision, 8);
+                assert_eq!(
+                    tables[0].values[0][1],
+                    LUMINANCE_QUANTIZATION_TABLE[0][1] as u16
+                );
+                assert_eq!(
+                    tables[1].values[7][0],
+                    CHROMINANCE_QUANTIZATION_TABLE[7][0] as u16
+                );
+            }
+            _ => panic!("DQT is not decoded"),
+        }
+        assert_eq!(frame_quant_table_ids(&encoded, &segments), vec![0, 1, 1]);
+        let dc_table = segments
+            .iter()
+            .filter_map(|segment| match &segment.content {
+                SegmentContent::HuffmanTables(tables) => Some(tables),
+                _ => None,
+            })
+            .flatten()
+            .find(|table| table.class == 0 && table.id == 0)
+            .unwrap();
+        assert_eq!(dc_table.bits, LUMINANCE_DC_BITS);
+        assert_eq!(dc_table.values, LUMINANCE_DC_VALUES);
+    }
+
+    #[test]
+    pub fn test_quantization_tables() {
+        let table = |id: u8, value: u16| QuantizationTableDefinition {
+            id,
+            precision: 8,
+            values: [[value; 8]; 8],
+        };
+        let segment = |marker: u8, content: SegmentContent| Segment {
+            marker: Some(marker),
+            offset: 0,
+            length: 0,
+            content,
+            children: Vec::new(),
+        };
+        let segments = [
+            segment(
+                MARKER_DQT,
+                SegmentContent::QuantizationTables(vec![table(1, 10), table(0, 20)]),
+            ),
+            segment(MARKER_DHT, SegmentContent::HuffmanTables(Vec::new())),
+            segment(
+                MARKER_DQT,
+                SegmentContent::QuantizationTables(vec![table(1, 30)]),
+            ),
+        ];
+
+        // sorted by the id, the second definition of the table 1 replaces the first
+        assert_eq!(
+            quantization_tables(&segments),
+            vec![table(0, 20), table(1, 30)]
+        );
+        assert_eq!(quantization_tables(&segments[1..2]), Vec::new());
+    }
+
+    #[test]
+    pub fn test_parse_restart_markers() {
+        let data = include_bytes!("../../../www/public/preset_images/agh.jpg");
+        let segments = parse_segments(data).unwrap();
+
+        let app1 = &segments[1];
+        assert_eq!(app1.name(), "APP1");
+        assert_eq!(
+            app1.content,
+            SegmentContent::Fields(vec![
+                ("Identifier", "Exif".to_owned()),
+                ("TIFF byte order", "big-endian (MM)".to_owned()),
+            ])
+        );
+        let dri = segments.iter().find(|segment| segment.name() == "DRI");
+        assert_eq!(
+            dri.unwrap().content,
+            SegmentContent::Fields(vec![("Restart interval", "59 MCUs".to_owned())])
+        );
+
+        let entropy_coded_data = segments
+            .iter()
+            .find(|segment| segment.is_entropy_coded())
+            .unwrap();
+        let restart_markers = entropy_coded_data
+            .children
+            .iter()
+            .filter(|child| !child.is_entropy_coded())
+            .map(|child| child.name())
+            .collect::<Vec<String>>();
+        assert_eq!(restart_markers.len(), 92);
+        assert_eq!(restart_markers[..2], ["RST0", "RST1"]);
+        assert_eq!(restart_markers[8], "RST0");
+        for pair in entropy_coded_data.children.windows(2) {
+            assert_eq!(pair[0].end(), pair[1].offset);
+        }
+        assert_eq!(segments.last().unwrap().name(), "EOI");
+    }
+
+    #[test]
+    pub fn test_parse_invalid() {
+        assert_eq!(
+            parse_segments(&[0x89, 0x50, 0x4E, 0x47]).err(),
+            Some(DecodeError::NotJpeg)
+        );
+        let segments = parse_segments(&[0xFF, 0xD8, 0xFF, 0xDB, 0x00, 0x43, 0x00]).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(
+            segments[1].content,
+            SegmentContent::Invalid("truncated segment")
+        );
+        assert_eq!(segments[1].length, 5);
+    }
+}
@@ -1,75 +1,205 @@
+use crate::codec::jpeg::constants::ZIG_ZAG;
+use once_cell::sync::Lazy;
 use std::f32::consts::{FRAC_1_SQRT_2, PI};
+use strum_macros::EnumIter;
 
-#[allow(non_snake_case)]
-pub fn spatial_to_freq(block: &[[i16; 8]; 8]) -> [[i16; 8]; 8] {
-    let mut result = [[0_i16; 8]; 8];
+pub mod aan;
+pub mod loeffler;
+pub mod mpeg1;
+
+/// Forward and inverse 8x8 DCT, the samples are level shifted by 128 inside the transform
+/// and the coefficients are in the natural order
+pub trait Transform {
+    fn spatial_to_freq(&self, block: &[[i16; 8]; 8]) -> [[i16; 8]; 8];
+
+    /// The result is clamped to [0, 255]
+    fn freq_to_spatial(&self, block: &[[i16; 8]; 8]) -> [[i16; 8]; 8];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter)]
+pub enum DctAlgorithm {
+    Reference,
+    Aan,
+    Loeffler,
+    Mpeg1,
+}
 
-    for x in 0..8 {
-        for y in 0..8 {
-            result[y][x] = G(x, y, block).round() as i16;
+impl DctAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DctAlgorithm::Reference => "Reference (float)",
+            DctAlgorithm::Aan => "AAN (float)",
+            DctAlgorithm::Loeffler => "Loeffler (integer)",
+            DctAlgorithm::Mpeg1 => "MPEG-1 IDCT (integer)",
         }
     }
 
-    result
+    pub fn transform(&self) -> &'static dyn Transform {
+        match self {
+            DctAlgorithm::Reference => &Reference,
+            DctAlgorithm::Aan => &aan::Aan,
+            DctAlgorithm::Loeffler => &loeffler::Loeffler,
+            DctAlgorithm::Mpeg1 => &mpeg1::Mpeg1,
+        }
+    }
 }
 
-#[allow(non_snake_case)]
-fn G(u: usize, v: usize, block: &[[i16; 8]; 8]) -> f32 {
-    let (u, v) = (u as f32, v as f32);
-    let au = if u == 0.0 { FRAC_1_SQRT_2 } else { 1.0 };
-    let av = if v == 0.0 { FRAC_1_SQRT_2 } else { 1.0 };
+/// Transform computed straight from the DCT definition, the other algorithms are compared against it
+pub struct Reference;
 
-    let mut sum = 0.0;
-    for x in 0..8 {
-        for y in 0..8 {
-            let g = block[y][x] as f32 - 128.0;
-            let cosx = (((2 * x + 1) as f32 * u * PI) / 16.0).cos();
-            let cosy = (((2 * y + 1) as f32 * v * PI) / 16.0).cos();
+impl Transform for Reference {
+    fn spatial_to_freq(&self, block: &[[i16; 8]; 8]) -> [[i16; 8]; 8] {
+        spatial_to_freq(block)
+    }
 
-            sum += g * cosx * cosy;
-        }
+    fn freq_to_spatial(&self, block: &[[i16; 8]; 8]) -> [[i16; 8]; 8] {
+        freq_to_spatial(block)
     }
+}
 
-    0.25 * au * av * sum
+/// Rounds the level shifted spatial value and clamps it to the sample range
+pub(crate) fn to_sample(value: f32) -> i16 {
+    // we clamp the result, because it could land outside [0, 255] range after the dequantization step
+    // it would be flipped after the conversion to u8, this caused the "burned in" pixels
+    (value + 128.0).round().clamp(0.0, 255.0) as i16
 }
 
-pub fn freq_to_spatial(block: &[[i16; 8]; 8]) -> [[i16; 8]; 8] {
-    let mut result = [[0_i16; 8]; 8];
+/// BASIS[u][x] = C(u) / 2 * cos((2x + 1)uπ / 16), the 2D transform is done separably,
+/// first along the rows and then along the columns, as the whole image is transformed block by block
+static BASIS: Lazy<[[f32; 8]; 8]> = Lazy::new(|| {
+    let mut basis = [[0_f32; 8]; 8];
+    for (u, row) in basis.iter_mut().enumerate() {
+        let cu = if u == 0 { FRAC_1_SQRT_2 } else { 1.0 };
+        for (x, value) in row.iter_mut().enumerate() {
+            *value = 0.5 * cu * (((2 * x + 1) as f32 * u as f32 * PI) / 16.0).cos();
+        }
+    }
+    basis
+});
+
+pub fn spatial_to_freq(block: &[[i16; 8]; 8]) -> [[i16; 8]; 8] {
+    let basis = &*BASIS;
+
+    // rows[y][u] - 1D transform of every row
+    let mut rows = [[0_f32; 8]; 8];
+    for y in 0..8 {
+        for u in 0..8 {
+            rows[y][u] = (0..8)
+                .map(|x| (block[y][x] as f32 - 128.0) * basis[u][x])
+                .sum();
+        }
+    }
 
-    for x in 0..8 {
-        for y in 0..8 {
-            // we clamp the result, because it could land outside [0, 255] range after the dequantization step
-            // it would be flipped after the conversion to u8, this caused the "burned in" pixels
-            result[y][x] = (f(y, x, block) + 128.0).round().clamp(0.0, 255.0) as i16;
+    let mut result = [[0_i16; 8]; 8];
+    for v in 0..8 {
+        for u in 0..8 {
+            let value: f32 = (0..8).map(|y| rows[y][u] * basis[v][y]).sum();
+            result[v][u] = value.round() as i16;
         }
     }
 
     result
 }
 
-fn f(x: usize, y: usize, block: &[[i16; 8]; 8]) -> f32 {
-    let mut sum = 0.0;
-    for u in 0..8 {
-        for v in 0..8 {
-            let au = if u == 0 { FRAC_1_SQRT_2 } else { 1.0 };
-            let av = if v == 0 { FRAC_1_SQRT_2 } else { 1.0 };
-
-            let ff = block[u][v] as f32;
-            let (u, v) = (u as f32, v as f32);
-            let cosx = (((2 * x + 1) as f32 * u * PI) / 16.0).cos();
-            let cosy = (((2 * y + 1) as f32 * v * PI) / 16.0).cos();
-            sum += ff * au * av * cosx * cosy
+pub fn freq_to_spatial(block: &[[i16; 8]; 8]) -> [[i16; 8]; 8] {
+    let basis = &*BASIS;
+
+    // rows[v][x] - inverse 1D transform of every row of coefficients
+    let mut rows = [[0_f32; 8]; 8];
+    for v in 0..8 {
+        for x in 0..8 {
+            rows[v][x] = (0..8).map(|u| block[v][u] as f32 * basis[u][x]).sum();
+        }
+    }
+
+    let mut result = [[0_i16; 8]; 8];
+    for y in 0..8 {
+        for x in 0..8 {
+            result[y][x] = to_sample((0..8).map(|v| rows[v][x] * basis[v][y]).sum());
         }
     }
 
-    0.25 * sum
+    result
+}
+
+/// Samples of the basis function of the coefficient in row `v` and column `u`, the amplitude
+/// is 127 divided by C(u) * C(v) / 4, so every function spans nearly the whole sample range
+pub fn basis_image(u: usize, v: usize) -> [[i16; 8]; 8] {
+    let c = |k: usize| if k == 0 { FRAC_1_SQRT_2 } else { 1.0 };
+    let mut block = [[0_i16; 8]; 8];
+    block[v][u] = (508.0 / (c(u) * c(v))).round() as i16;
+    freq_to_spatial(&block)
+}
+
+/// Inverse transform of the first `kept` coefficients in the zig-zag order, the rest are zeroed
+pub fn partial_freq_to_spatial(block: &[[i16; 8]; 8], kept: usize) -> [[i16; 8]; 8] {
+    let mut partial = [[0_i16; 8]; 8];
+    for natural in ZIG_ZAG.iter().take(kept) {
+        partial[natural / 8][natural % 8] = block[natural / 8][natural % 8];
+    }
+    freq_to_spatial(&partial)
 }
 
 #[cfg(test)]
 mod test {
     use std::convert::TryFrom;
+    use strum::IntoEnumIterator;
+
+    use super::{
+        basis_image, freq_to_spatial, partial_freq_to_spatial, spatial_to_freq, DctAlgorithm,
+    };
+
+    /// Gradients with pseudo random noise, similar to the blocks of a photo
+    fn test_blocks() -> Vec<[[i16; 8]; 8]> {
+        let mut seed: u32 = 12345;
+        let mut random = move || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            ((seed >> 16) % 256) as i16
+        };
+        (0..100)
+            .map(|_| {
+                let (base, step_x, step_y) = (random() / 2, random() % 16, random() % 12);
+                let mut block = [[0_i16; 8]; 8];
+                for (y, row) in block.iter_mut().enumerate() {
+                    for (x, value) in row.iter_mut().enumerate() {
+                        let gradient = base + x as i16 * step_x + y as i16 * step_y;
+                        *value = (gradient + random() % 16).clamp(0, 255);
+                    }
+                }
+                block
+            })
+            .collect()
+    }
+
+    fn max_difference(a: &[[i16; 8]; 8], b: &[[i16; 8]; 8]) -> i16 {
+        a.iter()
+            .flatten()
+            .zip(b.iter().flatten())
+            .map(|(a, b)| (a - b).abs())
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    pub fn test_algorithms_match_reference() {
+        for algorithm in DctAlgorithm::iter() {
+            let transform = algorithm.transform();
+            for block in test_blocks() {
+                let freq = spatial_to_freq(&block);
 
-    use super::{freq_to_spatial, spatial_to_freq};
+                assert!(
+                    max_difference(&transform.spatial_to_freq(&block), &freq) <= 1,
+                    "{}",
+                    algorithm.name()
+                );
+                assert!(
+                    max_difference(&transform.freq_to_spatial(&freq), &freq_to_spatial(&freq)) <= 1,
+                    "{}",
+                    algorithm.name()
+                );
+            }
+        }
+    }
 
     #[test]
     pub fn test_spatial_to_freq() {
@@ -139,4 +269,40 @@ mod test {
 
         assert_eq!(freq_to_spatial(&freq_block), expected_spatial_block);
     }
+
+    #[test]
+    pub fn test_basis_image() {
+        assert_eq!(basis_image(0, 0), [[255; 8]; 8]);
+
+        // the first horizontal frequency changes only along the rows and goes from light to dark
+        let horizontal = basis_image(1, 0);
+        assert!(horizontal.iter().all(|row| *row == horizontal[0]));
+        assert!(horizontal[0].windows(2).all(|pair| pair[0] > pair[1]));
+        assert!(horizontal[0][0] > 240 && horizontal[0][7] < 16);
+
+        for (u, v) in [(7, 7), (3, 5), (0, 6)].iter() {
+            let image = basis_image(*u, *v);
+            let max = image.iter().flatten().max().unwrap();
+            let min = image.iter().flatten().min().unwrap();
+            assert!(*max > 220 && *min < 36);
+        }
+    }
+
+    #[test]
+    pub fn test_partial_freq_to_spatial() {
+        for block in test_blocks().iter().take(10) {
+            let freq = spatial_to_freq(block);
+
+            assert_eq!(partial_freq_to_spatial(&freq, 64), freq_to_spatial(&freq));
+            // the DC coefficient alone gives the mean of the block
+            let mean = block.iter().flatten().map(|x| *x as i32).sum::<i32>() as f32 / 64.0;
+            let dc_only = partial_freq_to_spatial(&freq, 1);
+            assert!(dc_only.iter().flatten().all(|x| *x == dc_only[0][0]));
+            assert!((dc_only[0][0] as f32 - mean).abs() <= 1.0);
+        }
+        assert_eq!(
+            partial_freq_to_spatial(&spatial_to_freq(&[[200; 8]; 8]), 0),
+            [[128; 8]; 8]
+        );
+    }
 }
@@ -0,0 +1,155 @@
+//! Float AAN (Arai, Agui, Nakajima) DCT in the form used by the libjpeg's jfdctflt.c and jidctflt.c,
+//! the 1-D passes need 5 multiplications per 8 samples because their output is left scaled.
+//! libjpeg folds that scale into the (de)quantization, here it's removed by a separate pass over
+//! the block so the coefficients match the other transforms
+
+use super::{to_sample, Transform};
+use once_cell::sync::Lazy;
+use std::f32::consts::{PI, SQRT_2};
+
+pub struct Aan;
+
+/// Output of the forward transform for the frequency `k` is scaled by SCALES[k], in 2D by SCALES[u] * SCALES[v] * 8
+static SCALES: Lazy<[f32; 8]> = Lazy::new(|| {
+    let mut scales = [1_f32; 8];
+    for (k, scale) in scales.iter_mut().enumerate().skip(1) {
+        *scale = (k as f32 * PI / 16.0).cos() * SQRT_2;
+    }
+    scales
+});
+
+fn forward_1d(data: &mut [f32; 8]) {
+    let tmp0 = data[0] + data[7];
+    let tmp7 = data[0] - data[7];
+    let tmp1 = data[1] + data[6];
+    let tmp6 = data[1] - data[6];
+    let tmp2 = data[2] + data[5];
+    let tmp5 = data[2] - data[5];
+    let tmp3 = data[3] + data[4];
+    let tmp4 = data[3] - data[4];
+
+    // even part
+    let tmp10 = tmp0 + tmp3;
+    let tmp13 = tmp0 - tmp3;
+    let tmp11 = tmp1 + tmp2;
+    let tmp12 = tmp1 - tmp2;
+
+    data[0] = tmp10 + tmp11;
+    data[4] = tmp10 - tmp11;
+
+    let z1 = (tmp12 + tmp13) * 0.707_106_77;
+    data[2] = tmp13 + z1;
+    data[6] = tmp13 - z1;
+
+    // odd part
+    let tmp10 = tmp4 + tmp5;
+    let tmp11 = tmp5 + tmp6;
+    let tmp12 = tmp6 + tmp7;
+
+    let z5 = (tmp10 - tmp12) * 0.382_683_43;
+    let z2 = 0.541_196_1 * tmp10 + z5;
+    let z4 = 1.306_563 * tmp12 + z5;
+    let z3 = tmp11 * 0.707_106_77;
+
+    let z11 = tmp7 + z3;
+    let z13 = tmp7 - z3;
+
+    data[5] = z13 + z2;
+    data[3] = z13 - z2;
+    data[1] = z11 + z4;
+    data[7] = z11 - z4;
+}
+
+fn inverse_1d(data: &mut [f32; 8]) {
+    // even part
+    let tmp10 = data[0] + data[4];
+    let tmp11 = data[0] - data[4];
+
+    let tmp13 = data[2] + data[6];
+    let tmp12 = (data[2] - data[6]) * SQRT_2 - tmp13;
+
+    let tmp0 = tmp10 + tmp13;
+    let tmp3 = tmp10 - tmp13;
+    let tmp1 = tmp11 + tmp12;
+    let tmp2 = tmp11 - tmp12;
+
+    // odd part
+    let z13 = data[5] + data[3];
+    let z10 = data[5] - data[3];
+    let z11 = data[1] + data[7];
+    let z12 = data[1] - data[7];
+
+    let tmp7 = z11 + z13;
+    let tmp11 = (z11 - z13) * SQRT_2;
+
+    let z5 = (z10 + z12) * 1.847_759;
+    let tmp10 = 1.082_392_2 * z12 - z5;
+    let tmp12 = -2.613_126 * z10 + z5;
+
+    let tmp6 = tmp12 - tmp7;
+    let tmp5 = tmp11 - tmp6;
+    let tmp4 = tmp10 + tmp5;
+
+    data[0] = tmp0 + tmp7;
+    data[7] = tmp0 - tmp7;
+    data[1] = tmp1 + tmp6;
+    data[6] = tmp1 - tmp6;
+    data[2] = tmp2 + tmp5;
+    data[5] = tmp2 - tmp5;
+    data[4] = tmp3 + tmp4;
+    data[3] = tmp3 - tmp4;
+}
+
+/// Runs `transform_1d` on every row and then on every column
+fn separable(data: &mut [[f32; 8]; 8], transform_1d: fn(&mut [f32; 8])) {
+    for row in data.iter_mut() {
+        transform_1d(row);
+    }
+    for x in 0..8 {
+        let mut column = data.map(|row| row[x]);
+        transform_1d(&mut column);
+        for (row, value) in data.iter_mut().zip(column.iter()) {
+            row[x] = *value;
+        }
+    }
+}
+
+impl Transform for Aan {
+    fn spatial_to_freq(&self, block: &[[i16; 8]; 8]) -> [[i16; 8]; 8] {
+        let mut data = [[0_f32; 8]; 8];
+        for y in 0..8 {
+            for x in 0..8 {
+                data[y][x] = block[y][x] as f32 - 128.0;
+            }
+        }
+        separable(&mut data, forward_1d);
+
+        let scales = &*SCALES;
+        let mut result = [[0_i16; 8]; 8];
+        for v in 0..8 {
+            for u in 0..8 {
+                result[v][u] = (data[v][u] / (scales[u] * scales[v] * 8.0)).round() as i16;
+            }
+        }
+        result
+    }
+
+    fn freq_to_spatial(&self, block: &[[i16; 8]; 8]) -> [[i16; 8]; 8] {
+        let scales = &*SCALES;
+        let mut data = [[0_f32; 8]; 8];
+        for v in 0..8 {
+            for u in 0..8 {
+                data[v][u] = block[v][u] as f32 * scales[u] * scales[v];
+            }
+        }
+        separable(&mut data, inverse_1d);
+
+        let mut result = [[0_i16; 8]; 8];
+        for y in 0..8 {
+            for x in 0..8 {
+                result[y][x] = to_sample(data[y][x] / 8.0);
+            }
+        }
+        result
+    }
+}
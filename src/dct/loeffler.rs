@@ -0,0 +1,183 @@
+//! Integer DCT based on the Loeffler, Ligtenberg and Moschytz algorithm as in the libjpeg's
+//! jfdctint.c and jidctint.c, the constants are fixed point numbers with 13 fractional bits
+//! and the first pass keeps 2 extra bits of precision
+
+use super::Transform;
+
+const CONST_BITS: i32 = 13;
+const PASS1_BITS: i32 = 2;
+
+const FIX_0_298631336: i32 = 2446;
+const FIX_0_390180644: i32 = 3196;
+const FIX_0_541196100: i32 = 4433;
+const FIX_0_765366865: i32 = 6270;
+const FIX_0_899976223: i32 = 7373;
+const FIX_1_175875602: i32 = 9633;
+const FIX_1_501321110: i32 = 12299;
+const FIX_1_847759065: i32 = 15137;
+const FIX_1_961570560: i32 = 16069;
+const FIX_2_053119869: i32 = 16819;
+const FIX_2_562915447: i32 = 20995;
+const FIX_3_072711026: i32 = 25172;
+
+pub struct Loeffler;
+
+/// Shifts right by `n` bits with rounding
+fn descale(value: i32, n: i32) -> i32 {
+    (value + (1 << (n - 1))) >> n
+}
+
+/// One pass of the forward transform over 8 values with the `stride`,
+/// the first pass keeps PASS1_BITS extra bits which the second one removes
+fn forward_1d(data: &mut [i32; 64], start: usize, stride: usize, first_pass: bool) {
+    let d = |i: usize| data[start + i * stride];
+    let tmp0 = d(0) + d(7);
+    let tmp7 = d(0) - d(7);
+    let tmp1 = d(1) + d(6);
+    let tmp6 = d(1) - d(6);
+    let tmp2 = d(2) + d(5);
+    let tmp5 = d(2) - d(5);
+    let tmp3 = d(3) + d(4);
+    let tmp4 = d(3) - d(4);
+
+    let bits = if first_pass {
+        CONST_BITS - PASS1_BITS
+    } else {
+        CONST_BITS + PASS1_BITS
+    };
+    let mut out = [0_i32; 8];
+
+    // even part
+    let tmp10 = tmp0 + tmp3;
+    let tmp13 = tmp0 - tmp3;
+    let tmp11 = tmp1 + tmp2;
+    let tmp12 = tmp1 - tmp2;
+
+    if first_pass {
+        out[0] = (tmp10 + tmp11) << PASS1_BITS;
+        out[4] = (tmp10 - tmp11) << PASS1_BITS;
+    } else {
+        out[0] = descale(tmp10 + tmp11, PASS1_BITS);
+        out[4] = descale(tmp10 - tmp11, PASS1_BITS);
+    }
+
+    let z1 = (tmp12 + tmp13) * FIX_0_541196100;
+    out[2] = descale(z1 + tmp13 * FIX_0_765366865, bits);
+    out[6] = descale(z1 - tmp12 * FIX_1_847759065, bits);
+
+    // odd part
+    let z1 = tmp4 + tmp7;
+    let z2 = tmp5 + tmp6;
+    let z3 = tmp4 + tmp6;
+    let z4 = tmp5 + tmp7;
+    let z5 = (z3 + z4) * FIX_1_175875602;
+
+    let tmp4 = tmp4 * FIX_0_298631336;
+    let tmp5 = tmp5 * FIX_2_053119869;
+    let tmp6 = tmp6 * FIX_3_072711026;
+    let tmp7 = tmp7 * FIX_1_501321110;
+    let z1 = z1 * -FIX_0_899976223;
+    let z2 = z2 * -FIX_2_562915447;
+    let z3 = z3 * -FIX_1_961570560 + z5;
+    let z4 = z4 * -FIX_0_390180644 + z5;
+
+    out[7] = descale(tmp4 + z1 + z3, bits);
+    out[5] = descale(tmp5 + z2 + z4, bits);
+    out[3] = descale(tmp6 + z2 + z3, bits);
+    out[1] = descale(tmp7 + z1 + z4, bits);
+
+    for (i, value) in out.iter().enumerate() {
+        data[start + i * stride] = *value;
+    }
+}
+
+/// One pass of the inverse transform over 8 values with the `stride`, the results are descaled by `bits`
+fn inverse_1d(data: &mut [i32; 64], start: usize, stride: usize, bits: i32) {
+    let d = |i: usize| data[start + i * stride];
+
+    // even part
+    let z1 = (d(2) + d(6)) * FIX_0_541196100;
+    let tmp2 = z1 - d(6) * FIX_1_847759065;
+    let tmp3 = z1 + d(2) * FIX_0_765366865;
+
+    let tmp0 = (d(0) + d(4)) << CONST_BITS;
+    let tmp1 = (d(0) - d(4)) << CONST_BITS;
+
+    let tmp10 = tmp0 + tmp3;
+    let tmp13 = tmp0 - tmp3;
+    let tmp11 = tmp1 + tmp2;
+    let tmp12 = tmp1 - tmp2;
+
+    // odd part
+    let (tmp0, tmp1, tmp2, tmp3) = (d(7), d(5), d(3), d(1));
+    let z1 = tmp0 + tmp3;
+    let z2 = tmp1 + tmp2;
+    let z3 = tmp0 + tmp2;
+    let z4 = tmp1 + tmp3;
+    let z5 = (z3 + z4) * FIX_1_175875602;
+
+    let z1 = z1 * -FIX_0_899976223;
+    let z2 = z2 * -FIX_2_562915447;
+    let z3 = z3 * -FIX_1_961570560 + z5;
+    let z4 = z4 * -FIX_0_390180644 + z5;
+
+    let tmp0 = tmp0 * FIX_0_298631336 + z1 + z3;
+    let tmp1 = tmp1 * FIX_2_053119869 + z2 + z4;
+    let tmp2 = tmp2 * FIX_3_072711026 + z2 + z3;
+    let tmp3 = tmp3 * FIX_1_501321110 + z1 + z4;
+
+    let out = [
+        tmp10 + tmp3,
+        tmp11 + tmp2,
+        tmp12 + tmp1,
+        tmp13 + tmp0,
+        tmp13 - tmp0,
+        tmp12 - tmp1,
+        tmp11 - tmp2,
+        tmp10 - tmp3,
+    ];
+    for (i, value) in out.iter().enumerate() {
+        data[start + i * stride] = descale(*value, bits);
+    }
+}
+
+impl Transform for Loeffler {
+    fn spatial_to_freq(&self, block: &[[i16; 8]; 8]) -> [[i16; 8]; 8] {
+        let mut data = [0_i32; 64];
+        for (i, value) in block.iter().flatten().enumerate() {
+            data[i] = *value as i32 - 128;
+        }
+        for row in 0..8 {
+            forward_1d(&mut data, row * 8, 1, true);
+        }
+        for column in 0..8 {
+            forward_1d(&mut data, column, 8, false);
+        }
+
+        // the output is scaled up by 8 compared to the DCT definition
+        let mut result = [[0_i16; 8]; 8];
+        for (i, value) in data.iter().enumerate() {
+            result[i / 8][i % 8] = descale(*value, 3) as i16;
+        }
+        result
+    }
+
+    fn freq_to_spatial(&self, block: &[[i16; 8]; 8]) -> [[i16; 8]; 8] {
+        let mut data = [0_i32; 64];
+        for (i, value) in block.iter().flatten().enumerate() {
+            data[i] = *value as i32;
+        }
+        for column in 0..8 {
+            inverse_1d(&mut data, column, 8, CONST_BITS - PASS1_BITS);
+        }
+        for row in 0..8 {
+            inverse_1d(&mut data, row * 8, 1, CONST_BITS + PASS1_BITS + 3);
+        }
+
+        let mut result = [[0_i16; 8]; 8];
+        for (i, value) in data.iter().enumerate() {
+            result[i / 8][i % 8] = (*value + 128).clamp(0, 255) as i16;
+        }
+        result
+    }
+}
@@ -0,0 +1,28 @@
+//! Integer IDCT of the MPEG-1 decoder, its input has to be premultiplied by the AAN scale factors
+//! which the decoder does during the dequantization. There is no matching forward transform,
+//! so the reference one is used for the encoding
+
+use super::{spatial_to_freq, Transform};
+use crate::section::mpeg_visualization::mpeg1::{constants::PREMULTIPLIER_MATRIX, IDCT};
+
+pub struct Mpeg1;
+
+impl Transform for Mpeg1 {
+    fn spatial_to_freq(&self, block: &[[i16; 8]; 8]) -> [[i16; 8]; 8] {
+        spatial_to_freq(block)
+    }
+
+    fn freq_to_spatial(&self, block: &[[i16; 8]; 8]) -> [[i16; 8]; 8] {
+        let mut data = [0_i32; 64];
+        for (i, value) in block.iter().flatten().enumerate() {
+            data[i] = *value as i32 * PREMULTIPLIER_MATRIX[i] as i32;
+        }
+        IDCT(&mut data);
+
+        let mut result = [[0_i16; 8]; 8];
+        for (i, value) in data.iter().enumerate() {
+            result[i / 8][i % 8] = (*value + 128).clamp(0, 255) as i16;
+        }
+        result
+    }
+}
@@ -1,7 +1,11 @@
 use crate::block::BlockMatrix;
+use std::cmp;
 use std::rc::Rc;
+use strum_macros::EnumIter;
 
 pub mod pixel {
+    use super::ColorTransform;
+
     pub struct RGB {
         pub r: u8,
         pub g: u8,
@@ -13,14 +17,9 @@ pub mod pixel {
             [self.r, self.g, self.b, 255]
         }
 
+        /// Full range BT.601 conversion used by JFIF
         pub fn to_ycbcr(&self) -> YCbCr {
-            let (r, g, b) = (self.r as f32, self.g as f32, self.b as f32);
-
-            let y = (0.299 * r + 0.587 * g + 0.114 * b) as u8;
-            let cb = (128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b) as u8;
-            let cr = (128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b) as u8;
-
-            YCbCr { y, cb, cr }
+            ColorTransform::Bt601Full.to_ycbcr(self)
         }
     }
 
@@ -31,18 +30,311 @@ pub mod pixel {
     }
 
     impl YCbCr {
+        /// Full range BT.601 conversion used by JFIF
         pub fn to_rgb(&self) -> RGB {
-            let (y, cb, cr) = (self.y as f32, self.cb as f32, self.cr as f32);
+            ColorTransform::Bt601Full.to_rgb(self)
+        }
+    }
+}
+
+/// Conversion of RGB into the luma and two chroma channels that get compressed,
+/// neutral chroma is stored as 128 in all of them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, EnumIter)]
+pub enum ColorTransform {
+    #[default]
+    Bt601Full,
+    Bt601Studio,
+    Bt709,
+    YCoCg,
+    // lifting steps done modulo 256, so the chroma fits into 8 bits and stays lossless,
+    // colours that need the 9th bit of the chroma wrap around - only the round trip uses it
+    YCoCgR,
+}
 
-            let r = (y + 1.402 * (cr - 128.0)) as u8;
-            let g = (y - 0.344136 * (cb - 128.0) - 0.714136 * (cr - 128.0)) as u8;
-            let b = (y + 1.772 * (cb - 128.0)) as u8;
+impl ColorTransform {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorTransform::Bt601Full => "BT.601 full range (JFIF)",
+            ColorTransform::Bt601Studio => "BT.601 studio range",
+            ColorTransform::Bt709 => "BT.709 full range",
+            ColorTransform::YCoCg => "YCoCg",
+            ColorTransform::YCoCgR => "YCoCg-R (reversible)",
+        }
+    }
 
-            RGB { r, g, b }
+    pub fn to_ycbcr(self, rgb: &pixel::RGB) -> pixel::YCbCr {
+        let (r, g, b) = (rgb.r as f64, rgb.g as f64, rgb.b as f64);
+        match self {
+            ColorTransform::Bt601Full | ColorTransform::Bt601Studio | ColorTransform::Bt709 => {
+                let (kr, kb) = self.luma_weights();
+                let y = kr * r + (1.0 - kr - kb) * g + kb * b;
+                let cb = (b - y) / (2.0 * (1.0 - kb));
+                let cr = (r - y) / (2.0 * (1.0 - kr));
+                if self == ColorTransform::Bt601Studio {
+                    pixel::YCbCr {
+                        y: round_to_sample(16.0 + y * 219.0 / 255.0),
+                        cb: round_to_sample(128.0 + cb * 224.0 / 255.0),
+                        cr: round_to_sample(128.0 + cr * 224.0 / 255.0),
+                    }
+                } else {
+                    pixel::YCbCr {
+                        y: round_to_sample(y),
+                        cb: round_to_sample(128.0 + cb),
+                        cr: round_to_sample(128.0 + cr),
+                    }
+                }
+            }
+            // Co is stored in cb and Cg in cr
+            ColorTransform::YCoCg => pixel::YCbCr {
+                y: round_to_sample((r + 2.0 * g + b) / 4.0),
+                cb: round_to_sample(128.0 + (r - b) / 2.0),
+                cr: round_to_sample(128.0 + (2.0 * g - r - b) / 4.0),
+            },
+            ColorTransform::YCoCgR => {
+                let co = (rgb.r as i32 - rgb.b as i32) as i8;
+                let t = rgb.b as i32 + (co >> 1) as i32;
+                let cg = (rgb.g as i32 - t) as i8;
+                pixel::YCbCr {
+                    y: (t + (cg >> 1) as i32) as u8,
+                    cb: (co as u8) ^ 0x80,
+                    cr: (cg as u8) ^ 0x80,
+                }
+            }
+        }
+    }
+
+    pub fn to_rgb(self, ycbcr: &pixel::YCbCr) -> pixel::RGB {
+        let (y, cb, cr) = (ycbcr.y as f64, ycbcr.cb as f64, ycbcr.cr as f64);
+        match self {
+            ColorTransform::Bt601Full | ColorTransform::Bt601Studio | ColorTransform::Bt709 => {
+                let (kr, kb) = self.luma_weights();
+                let (y, cb, cr) = if self == ColorTransform::Bt601Studio {
+                    (
+                        (y - 16.0) * 255.0 / 219.0,
+                        (cb - 128.0) * 255.0 / 224.0,
+                        (cr - 128.0) * 255.0 / 224.0,
+                    )
+                } else {
+                    (y, cb - 128.0, cr - 128.0)
+                };
+                let r = y + 2.0 * (1.0 - kr) * cr;
+                let b = y + 2.0 * (1.0 - kb) * cb;
+                let g = (y - kr * r - kb * b) / (1.0 - kr - kb);
+                pixel::RGB {
+                    r: round_to_sample(r),
+                    g: round_to_sample(g),
+                    b: round_to_sample(b),
+                }
+            }
+            ColorTransform::YCoCg => {
+                let (co, cg) = (cb - 128.0, cr - 128.0);
+                pixel::RGB {
+                    r: round_to_sample(y + co - cg),
+                    g: round_to_sample(y + cg),
+                    b: round_to_sample(y - co - cg),
+                }
+            }
+            ColorTransform::YCoCgR => {
+                let co = (ycbcr.cb ^ 0x80) as i8;
+                let cg = (ycbcr.cr ^ 0x80) as i8;
+                let t = ycbcr.y as i32 - (cg >> 1) as i32;
+                let g = cg as i32 + t;
+                let b = t - (co >> 1) as i32;
+                pixel::RGB {
+                    r: (b + co as i32) as u8,
+                    g: g as u8,
+                    b: b as u8,
+                }
+            }
+        }
+    }
+
+    /// Transforms the compression can use, the wrapped YCoCg-R chroma is no longer a colour
+    /// difference and the smallest quantization error next to the wrap point flips the colour
+    pub fn is_lossy_safe(&self) -> bool {
+        *self != ColorTransform::YCoCgR
+    }
+
+    /// Kr and Kb of the transforms defined by the luma weights
+    fn luma_weights(&self) -> (f64, f64) {
+        match self {
+            ColorTransform::Bt709 => (0.2126, 0.0722),
+            _ => (0.299, 0.114),
         }
     }
 }
 
+/// Filter applied to the chroma planes before the subsampling drops samples
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, EnumIter)]
+pub enum DownsamplingFilter {
+    // keeps the top left sample, sharp colour edges alias
+    #[default]
+    Point,
+    Box,
+    Triangle,
+    Lanczos,
+}
+
+impl DownsamplingFilter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DownsamplingFilter::Point => "Point (drop samples)",
+            DownsamplingFilter::Box => "Box average",
+            DownsamplingFilter::Triangle => "Triangle (bilinear)",
+            DownsamplingFilter::Lanczos => "Lanczos-3",
+        }
+    }
+
+    /// Weight of an input sample `distance` output samples away from the center of the output sample
+    fn weight(&self, distance: f64) -> f64 {
+        let sinc = |x: f64| {
+            if x == 0.0 {
+                1.0
+            } else {
+                (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+            }
+        };
+        match self {
+            DownsamplingFilter::Point => (distance == 0.0) as u8 as f64,
+            DownsamplingFilter::Box => (distance.abs() < 0.5) as u8 as f64,
+            DownsamplingFilter::Triangle => (1.0 - distance.abs()).max(0.0),
+            DownsamplingFilter::Lanczos if distance.abs() < 3.0 => {
+                sinc(distance) * sinc(distance / 3.0)
+            }
+            DownsamplingFilter::Lanczos => 0.0,
+        }
+    }
+
+    /// Input samples and their normalized weights for every output sample of one dimension,
+    /// the samples outside of the plane are replaced by the edge ones
+    fn kernels(&self, mult: usize, input_len: usize, output_len: usize) -> Vec<Vec<(usize, f64)>> {
+        let reach = 3 * mult as isize;
+        (0..output_len)
+            .map(|output| {
+                let center = (output * mult) as f64 + (mult - 1) as f64 / 2.0;
+                let first = (output * mult) as isize;
+                let mut kernel = (first - reach..first + mult as isize + reach)
+                    .filter_map(|input| {
+                        let weight = self.weight((input as f64 - center) / mult as f64);
+                        let index = input.clamp(0, input_len as isize - 1) as usize;
+                        if weight != 0.0 {
+                            Some((index, weight))
+                        } else {
+                            None
+                        }
+                    })
+                    .collect::<Vec<(usize, f64)>>();
+                let total = kernel.iter().map(|(_, weight)| weight).sum::<f64>();
+                for (_, weight) in kernel.iter_mut() {
+                    *weight /= total;
+                }
+                kernel
+            })
+            .collect()
+    }
+}
+
+/// Interpolation of the chroma planes back to the resolution of luma
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, EnumIter)]
+pub enum UpsamplingFilter {
+    #[default]
+    Nearest,
+    Bilinear,
+    // triangle filter of the libjpeg, only for the factor of 2, like there
+    Fancy,
+}
+
+impl UpsamplingFilter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            UpsamplingFilter::Nearest => "Nearest (replicate)",
+            UpsamplingFilter::Bilinear => "Bilinear",
+            UpsamplingFilter::Fancy => "Fancy (libjpeg)",
+        }
+    }
+}
+
+/// Position of a chroma sample relative to the luma samples it covers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, EnumIter)]
+pub enum ChromaSiting {
+    // in the middle of the covered area, as JFIF defines it
+    #[default]
+    Centered,
+    // on top of the top left luma sample
+    Cosited,
+}
+
+impl ChromaSiting {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChromaSiting::Centered => "Centered",
+            ChromaSiting::Cosited => "Co-sited",
+        }
+    }
+
+    /// Position of the luma sample `index` in the coordinates of the chroma samples
+    fn chroma_position(&self, index: usize, mult: usize) -> f64 {
+        match self {
+            ChromaSiting::Centered => (index as f64 + 0.5) / mult as f64 - 0.5,
+            ChromaSiting::Cosited => index as f64 / mult as f64,
+        }
+    }
+}
+
+/// Smoothing of the edges between the 8x8 blocks of a recovered plane
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter)]
+pub enum DeblockingFilter {
+    // low-pass filter across every block edge
+    Simple,
+    // like the H.264 loop filter, it only touches the edges weak enough to come from the quantization
+    Adaptive,
+}
+
+impl DeblockingFilter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeblockingFilter::Simple => "Simple boundary",
+            DeblockingFilter::Adaptive => "Adaptive (H.264-style)",
+        }
+    }
+
+    /// Filters the samples p2 p1 p0 | q0 q1 q2 across one block edge, `index` maps their positions
+    /// to the plane data
+    fn filter_edge(&self, data: &mut [u8], step: u8, index: impl Fn(usize) -> usize) {
+        let [p2, p1, p0, q0, q1, q2] = [0, 1, 2, 3, 4, 5].map(|i| data[index(i)] as i32);
+        match self {
+            DeblockingFilter::Simple => {
+                let filtered = [
+                    (p2 + 2 * p1 + p0 + 2) >> 2,
+                    (p1 + 2 * p0 + q0 + 2) >> 2,
+                    (p0 + 2 * q0 + q1 + 2) >> 2,
+                    (q0 + 2 * q1 + q2 + 2) >> 2,
+                ];
+                for (i, value) in filtered.iter().enumerate() {
+                    data[index(i + 1)] = *value as u8;
+                }
+            }
+            DeblockingFilter::Adaptive => {
+                // the thresholds grow with the quantization step, so do the block edges it causes
+                let step = step as i32;
+                let alpha = step / 2 + 2;
+                let beta = step / 4 + 1;
+                let clip = step / 8 + 1;
+                if (p0 - q0).abs() >= alpha || (p1 - p0).abs() >= beta || (q1 - q0).abs() >= beta {
+                    return;
+                }
+                let delta = (((q0 - p0) * 4 + (p1 - q1) + 4) >> 3).clamp(-clip, clip);
+                data[index(2)] = (p0 + delta).clamp(0, 255) as u8;
+                data[index(3)] = (q0 - delta).clamp(0, 255) as u8;
+            }
+        }
+    }
+}
+
+fn round_to_sample(value: f64) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
 #[derive(Default, Clone)]
 pub struct RawImage {
     width: u32,
@@ -70,6 +362,18 @@ impl RawImage {
         RGBImage(rgb)
     }
 
+    /// Splits the whole image into full resolution Y, Cb and Cr planes
+    pub fn to_ycbcr_planes(&self, color_transform: ColorTransform) -> YCbCrPlanes {
+        let ycbcr = self.to_rgb_image().to_ycbcr_image(color_transform);
+        let (width, height) = (self.width as usize, self.height as usize);
+        YCbCrPlanes {
+            ys: Plane::new(ycbcr.to_ys_channel(), width, height),
+            cbs: Plane::new(ycbcr.to_cbs_channel(), width, height),
+            crs: Plane::new(ycbcr.to_crs_channel(), width, height),
+            color_transform,
+        }
+    }
+
     pub fn height(&self) -> u32 {
         self.height
     }
@@ -173,11 +477,11 @@ impl std::ops::Index<usize> for RawImageWindow {
 pub struct RGBImage(pub Vec<pixel::RGB>);
 
 impl RGBImage {
-    pub fn to_ycbcr_image(&self) -> YCbCrImage {
+    pub fn to_ycbcr_image(&self, color_transform: ColorTransform) -> YCbCrImage {
         YCbCrImage(
             self.0
                 .iter()
-                .map(|rgb| rgb.to_ycbcr())
+                .map(|rgb| color_transform.to_ycbcr(rgb))
                 .collect::<Vec<pixel::YCbCr>>(),
         )
     }
@@ -225,3 +529,467 @@ impl YCbCrImage {
         self.0.iter().map(|x| x.cr).collect::<Vec<u8>>()
     }
 }
+
+/// Single channel of an image stored row by row
+#[derive(Clone, Default)]
+pub struct Plane {
+    pub data: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Plane {
+    pub fn new(data: Vec<u8>, width: usize, height: usize) -> Plane {
+        assert_eq!(data.len(), width * height);
+        Plane {
+            data,
+            width,
+            height,
+        }
+    }
+
+    /// Extends the plane to `width` x `height` by repeating the last column and row,
+    /// so the padding doesn't add edges that would cost bits
+    pub fn pad(&self, width: usize, height: usize) -> Plane {
+        let mut data = Vec::<u8>::with_capacity(width * height);
+        for y in 0..height {
+            let row = cmp::min(y, self.height - 1) * self.width;
+            for x in 0..width {
+                data.push(self.data[row + cmp::min(x, self.width - 1)]);
+            }
+        }
+        Plane::new(data, width, height)
+    }
+
+    /// Keeps the top left sample of every `horiz_mult` x `vert_mult` area
+    pub fn subsample(&self, horiz_mult: usize, vert_mult: usize) -> Plane {
+        let width = self.width.div_ceil(horiz_mult);
+        let height = self.height.div_ceil(vert_mult);
+        let mut data = Vec::<u8>::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                data.push(self.data[y * vert_mult * self.width + x * horiz_mult]);
+            }
+        }
+        Plane::new(data, width, height)
+    }
+
+    /// Subsamples the plane like `subsample`, every output sample is filtered from the area around it
+    pub fn downsample(
+        &self,
+        horiz_mult: usize,
+        vert_mult: usize,
+        filter: DownsamplingFilter,
+    ) -> Plane {
+        if filter == DownsamplingFilter::Point {
+            return self.subsample(horiz_mult, vert_mult);
+        }
+        let width = self.width.div_ceil(horiz_mult);
+        let height = self.height.div_ceil(vert_mult);
+
+        // the filter is separable, rows are filtered first
+        let horizontal = filter.kernels(horiz_mult, self.width, width);
+        let mut rows = Vec::<f64>::with_capacity(width * self.height);
+        for y in 0..self.height {
+            let row = &self.data[y * self.width..(y + 1) * self.width];
+            rows.extend(horizontal.iter().map(|kernel| {
+                kernel
+                    .iter()
+                    .map(|(x, weight)| row[*x] as f64 * weight)
+                    .sum::<f64>()
+            }));
+        }
+
+        let vertical = filter.kernels(vert_mult, self.height, height);
+        let mut data = Vec::<u8>::with_capacity(width * height);
+        for kernel in vertical.iter() {
+            for x in 0..width {
+                let value = kernel
+                    .iter()
+                    .map(|(y, weight)| rows[y * width + x] * weight)
+                    .sum::<f64>();
+                data.push(round_to_sample(value));
+            }
+        }
+        Plane::new(data, width, height)
+    }
+
+    /// Repeats every sample `horiz_mult` x `vert_mult` times and cuts the result to `width` x `height`
+    pub fn upsample(
+        &self,
+        horiz_mult: usize,
+        vert_mult: usize,
+        width: usize,
+        height: usize,
+    ) -> Plane {
+        let mut data = Vec::<u8>::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                data.push(self.data[(y / vert_mult) * self.width + x / horiz_mult]);
+            }
+        }
+        Plane::new(data, width, height)
+    }
+
+    /// Interpolates the plane to `width` x `height`, the fancy filter ignores the siting
+    /// because the libjpeg always treats the chroma as centered
+    pub fn upsample_filtered(
+        &self,
+        horiz_mult: usize,
+        vert_mult: usize,
+        width: usize,
+        height: usize,
+        filter: UpsamplingFilter,
+        siting: ChromaSiting,
+    ) -> Plane {
+        match filter {
+            UpsamplingFilter::Nearest => {
+                let nearest = |index: usize, mult: usize, len: usize| {
+                    let position = siting.chroma_position(index, mult);
+                    cmp::min((position + 0.5).floor() as usize, len - 1)
+                };
+                let mut data = Vec::<u8>::with_capacity(width * height);
+                for y in 0..height {
+                    let row = nearest(y, vert_mult, self.height) * self.width;
+                    for x in 0..width {
+                        data.push(self.data[row + nearest(x, horiz_mult, self.width)]);
+                    }
+                }
+                Plane::new(data, width, height)
+            }
+            UpsamplingFilter::Bilinear => {
+                // neighbouring samples and the weight of the second one
+                let neighbours = |index: usize, mult: usize, len: usize| {
+                    let position = siting.chroma_position(index, mult).max(0.0);
+                    let first = cmp::min(position.floor() as usize, len - 1);
+                    (
+                        first,
+                        cmp::min(first + 1, len - 1),
+                        position - position.floor(),
+                    )
+                };
+                let mut data = Vec::<u8>::with_capacity(width * height);
+                for y in 0..height {
+                    let (top, bottom, y_weight) = neighbours(y, vert_mult, self.height);
+                    for x in 0..width {
+                        let (left, right, x_weight) = neighbours(x, horiz_mult, self.width);
+                        let sample =
+                            |row: usize, col: usize| self.data[row * self.width + col] as f64;
+                        let upper =
+                            sample(top, left) * (1.0 - x_weight) + sample(top, right) * x_weight;
+                        let lower = sample(bottom, left) * (1.0 - x_weight)
+                            + sample(bottom, right) * x_weight;
+                        data.push(round_to_sample(upper * (1.0 - y_weight) + lower * y_weight));
+                    }
+                }
+                Plane::new(data, width, height)
+            }
+            UpsamplingFilter::Fancy => self.fancy_upsample(horiz_mult, vert_mult, width, height),
+        }
+    }
+
+    /// The h2v1, h1v2 and h2v2 fancy upsampling of the libjpeg with the same integer rounding,
+    /// every output sample is 3/4 of the nearer and 1/4 of the farther input sample,
+    /// other factors are replicated as the libjpeg does
+    fn fancy_upsample(
+        &self,
+        horiz_mult: usize,
+        vert_mult: usize,
+        width: usize,
+        height: usize,
+    ) -> Plane {
+        if horiz_mult > 2 || vert_mult > 2 {
+            return self.upsample(horiz_mult, vert_mult, width, height);
+        }
+        let sample = |row: usize, col: isize| {
+            let col = col.clamp(0, self.width as isize - 1) as usize;
+            self.data[row * self.width + col] as i32
+        };
+
+        let mut data = Vec::<u8>::with_capacity(width * height);
+        for y in 0..height {
+            let row = y / vert_mult;
+            // the vertical pass sums the nearer row three times and the farther one once
+            let column_sum = |col: isize| {
+                if vert_mult == 2 {
+                    let far_row = if y % 2 == 0 {
+                        row.saturating_sub(1)
+                    } else {
+                        cmp::min(row + 1, self.height - 1)
+                    };
+                    3 * sample(row, col) + sample(far_row, col)
+                } else {
+                    sample(row, col)
+                }
+            };
+            for x in 0..width {
+                let col = (x / horiz_mult) as isize;
+                let value = match (horiz_mult, vert_mult) {
+                    (2, 2) if x % 2 == 0 => (3 * column_sum(col) + column_sum(col - 1) + 8) >> 4,
+                    (2, 2) => (3 * column_sum(col) + column_sum(col + 1) + 7) >> 4,
+                    (2, _) if x % 2 == 0 => (3 * column_sum(col) + column_sum(col - 1) + 1) >> 2,
+                    (2, _) => (3 * column_sum(col) + column_sum(col + 1) + 2) >> 2,
+                    (_, 2) => (column_sum(col) + 1 + (y % 2) as i32) >> 2,
+                    _ => column_sum(col),
+                };
+                data.push(value as u8);
+            }
+        }
+        Plane::new(data, width, height)
+    }
+
+    /// Filters the vertical block edges and then the horizontal ones, `step` is the quantization step
+    /// of the DC coefficient the plane was compressed with
+    pub fn deblock(&self, filter: DeblockingFilter, step: u8) -> Plane {
+        let (width, height) = (self.width, self.height);
+        let mut data = self.data.clone();
+        // an edge is filtered only with three samples on each side, the plane can end just after one
+        for y in 0..height {
+            for x in (8..width.saturating_sub(2)).step_by(8) {
+                filter.filter_edge(&mut data, step, |i| y * width + x + i - 3);
+            }
+        }
+        for y in (8..height.saturating_sub(2)).step_by(8) {
+            for x in 0..width {
+                filter.filter_edge(&mut data, step, |i| (y + i - 3) * width + x);
+            }
+        }
+        Plane::new(data, width, height)
+    }
+
+    pub fn crop(&self, start_x: usize, start_y: usize, width: usize, height: usize) -> Plane {
+        let mut data = Vec::<u8>::with_capacity(width * height);
+        for y in start_y..start_y + height {
+            data.extend(&self.data[y * self.width + start_x..y * self.width + start_x + width]);
+        }
+        Plane::new(data, width, height)
+    }
+}
+
+#[derive(Default)]
+pub struct YCbCrPlanes {
+    pub ys: Plane,
+    pub cbs: Plane,
+    pub crs: Plane,
+    pub color_transform: ColorTransform,
+}
+
+impl YCbCrPlanes {
+    /// Converts the planes back to RGBA data of `width` x `height` pixels,
+    /// the chroma planes are upsampled to the resolution of luma
+    pub fn to_rgba(
+        &self,
+        horiz_mult: usize,
+        vert_mult: usize,
+        width: usize,
+        height: usize,
+    ) -> Vec<u8> {
+        let ys = self.ys.crop(0, 0, width, height);
+        let cbs = self.cbs.upsample(horiz_mult, vert_mult, width, height);
+        let crs = self.crs.upsample(horiz_mult, vert_mult, width, height);
+        ys.data
+            .iter()
+            .zip(cbs.data.iter())
+            .zip(crs.data.iter())
+            .flat_map(|((y, cb), cr)| {
+                self.color_transform
+                    .to_rgb(&pixel::YCbCr {
+                        y: *y,
+                        cb: *cb,
+                        cr: *cr,
+                    })
+                    .to_flat_data()
+            })
+            .collect::<Vec<u8>>()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        pixel, ChromaSiting, ColorTransform, DeblockingFilter, DownsamplingFilter, Plane,
+        UpsamplingFilter,
+    };
+    use strum::IntoEnumIterator;
+
+    fn round_trip_error(color_transform: ColorTransform, step: usize) -> u8 {
+        let mut max_error = 0;
+        for r in (0..=255).step_by(step) {
+            for g in (0..=255).step_by(step) {
+                for b in (0..=255).step_by(step) {
+                    let rgb = pixel::RGB { r, g, b };
+                    let recovered = color_transform.to_rgb(&color_transform.to_ycbcr(&rgb));
+                    max_error = [
+                        max_error,
+                        r.abs_diff(recovered.r),
+                        g.abs_diff(recovered.g),
+                        b.abs_diff(recovered.b),
+                    ]
+                    .iter()
+                    .copied()
+                    .max()
+                    .unwrap();
+                }
+            }
+        }
+        max_error
+    }
+
+    #[test]
+    pub fn test_ycocg_r_is_lossless() {
+        assert_eq!(round_trip_error(ColorTransform::YCoCgR, 1), 0);
+    }
+
+    #[test]
+    pub fn test_round_trip_error_is_small() {
+        assert!(round_trip_error(ColorTransform::Bt601Full, 3) <= 1);
+        assert!(round_trip_error(ColorTransform::Bt709, 3) <= 1);
+        assert!(round_trip_error(ColorTransform::YCoCg, 3) <= 1);
+        // the studio range has fewer levels than RGB
+        assert!(round_trip_error(ColorTransform::Bt601Studio, 3) <= 2);
+    }
+
+    #[test]
+    pub fn test_gray_has_neutral_chroma() {
+        for color_transform in ColorTransform::iter() {
+            for value in [0, 77, 128, 255] {
+                let ycbcr = color_transform.to_ycbcr(&pixel::RGB {
+                    r: value,
+                    g: value,
+                    b: value,
+                });
+                assert_eq!((ycbcr.cb, ycbcr.cr), (128, 128), "{:?}", color_transform);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_bt601_studio_range() {
+        let black = ColorTransform::Bt601Studio.to_ycbcr(&pixel::RGB { r: 0, g: 0, b: 0 });
+        let white = ColorTransform::Bt601Studio.to_ycbcr(&pixel::RGB {
+            r: 255,
+            g: 255,
+            b: 255,
+        });
+
+        assert_eq!((black.y, white.y), (16, 235));
+    }
+
+    #[test]
+    pub fn test_pad_repeats_edges() {
+        let plane = Plane::new(vec![1, 2, 3, 4], 2, 2);
+
+        let padded = plane.pad(3, 3);
+
+        assert_eq!(padded.data, vec![1, 2, 2, 3, 4, 4, 3, 4, 4]);
+    }
+
+    #[test]
+    pub fn test_subsample_and_upsample() {
+        let plane = Plane::new((0..16).collect::<Vec<u8>>(), 4, 4);
+
+        let subsampled = plane.subsample(2, 2);
+        let upsampled = subsampled.upsample(2, 2, 3, 4);
+
+        assert_eq!((subsampled.width, subsampled.height), (2, 2));
+        assert_eq!(subsampled.data, vec![0, 2, 8, 10]);
+        assert_eq!(upsampled.data, vec![0, 0, 2, 0, 0, 2, 8, 8, 10, 8, 8, 10]);
+    }
+
+    #[test]
+    pub fn test_downsample() {
+        // a sharp vertical edge between the second and the third column
+        let plane = Plane::new(
+            (0..32).map(|i| if i % 8 < 3 { 0 } else { 240 }).collect(),
+            8,
+            4,
+        );
+
+        let point = plane.downsample(2, 2, DownsamplingFilter::Point);
+        let average = plane.downsample(2, 2, DownsamplingFilter::Box);
+
+        assert_eq!(point.data, plane.subsample(2, 2).data);
+        assert_eq!(average.data[..4], [0, 120, 240, 240]);
+        for filter in DownsamplingFilter::iter() {
+            let flat = Plane::new(vec![77; 64], 8, 8).downsample(4, 2, filter);
+            assert_eq!((flat.width, flat.height), (2, 4));
+            assert!(flat.data.iter().all(|value| *value == 77), "{:?}", filter);
+        }
+    }
+
+    #[test]
+    pub fn test_upsample_filtered() {
+        let plane = Plane::new(vec![0, 100], 2, 1);
+        let upsample = |filter, siting| plane.upsample_filtered(2, 1, 4, 1, filter, siting).data;
+
+        assert_eq!(
+            upsample(UpsamplingFilter::Nearest, ChromaSiting::Centered),
+            plane.upsample(2, 1, 4, 1).data
+        );
+        assert_eq!(
+            upsample(UpsamplingFilter::Nearest, ChromaSiting::Cosited),
+            vec![0, 100, 100, 100]
+        );
+        assert_eq!(
+            upsample(UpsamplingFilter::Bilinear, ChromaSiting::Centered),
+            vec![0, 25, 75, 100]
+        );
+        assert_eq!(
+            upsample(UpsamplingFilter::Bilinear, ChromaSiting::Cosited),
+            vec![0, 50, 100, 100]
+        );
+        assert_eq!(
+            upsample(UpsamplingFilter::Fancy, ChromaSiting::Cosited),
+            vec![0, 25, 75, 100]
+        );
+    }
+
+    #[test]
+    pub fn test_fancy_upsample_h2v2() {
+        // values computed by hand from the h2v2_fancy_upsample of the libjpeg
+        let plane = Plane::new(vec![0, 64, 128, 255], 2, 2);
+
+        let upsampled =
+            plane.upsample_filtered(2, 2, 4, 4, UpsamplingFilter::Fancy, ChromaSiting::Centered);
+
+        assert_eq!(&upsampled.data[..4], &[0, 16, 48, 64]);
+        assert_eq!(&upsampled.data[4..8], &[32, 52, 92, 112]);
+        assert_eq!(&upsampled.data[12..], &[128, 160, 223, 255]);
+    }
+
+    #[test]
+    pub fn test_crop() {
+        let plane = Plane::new((0..16).collect::<Vec<u8>>(), 4, 4);
+
+        assert_eq!(plane.crop(1, 2, 2, 2).data, vec![9, 10, 13, 14]);
+    }
+
+    #[test]
+    pub fn test_deblock() {
+        let plane = Plane::new([[10; 8], [30; 8]].concat(), 16, 1);
+
+        let smoothed = plane.deblock(DeblockingFilter::Simple, 16);
+        assert_eq!(&smoothed.data[5..11], &[10, 10, 15, 25, 30, 30]);
+
+        // the edge is too strong to come from the quantization with the step of 16
+        let kept = plane.deblock(DeblockingFilter::Adaptive, 16);
+        assert_eq!(kept.data, plane.data);
+        let softened = plane.deblock(DeblockingFilter::Adaptive, 48);
+        assert_eq!(&softened.data[5..11], &[10, 10, 17, 23, 30, 30]);
+
+        let flat = Plane::new(vec![77; 16 * 16], 16, 16);
+        for filter in DeblockingFilter::iter() {
+            assert_eq!(flat.deblock(filter, 16).data, flat.data);
+        }
+
+        // the planes are not always whole blocks, an edge without three samples after it is skipped
+        let cut = Plane::new([vec![10; 8], vec![30; 2]].concat(), 10, 1);
+        assert_eq!(cut.deblock(DeblockingFilter::Simple, 16).data, cut.data);
+        let cut = Plane::new([vec![10; 8 * 2], vec![30; 3 * 2]].concat(), 2, 11);
+        let smoothed = cut.deblock(DeblockingFilter::Simple, 16);
+        assert_eq!(
+            &smoothed.data[5 * 2..11 * 2],
+            &[10, 10, 10, 10, 15, 15, 25, 25, 30, 30, 30, 30]
+        );
+    }
+}
@@ -5,9 +5,10 @@ use section::*;
 mod bench;
 mod block;
 mod codec;
-mod dct;
+pub mod dct;
 mod graphic_helpers;
 mod image;
+mod metrics;
 mod quant;
 pub mod section;
 
@@ -0,0 +1,150 @@
+use crate::image::Plane;
+
+// side of the square window the local SSIM statistics are computed over
+const SSIM_WINDOW: usize = 8;
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// Objective quality of a recovered channel compared to the original one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metrics {
+    pub mse: f64,
+    // in decibels, infinite for identical channels
+    pub psnr: f64,
+    pub ssim: f64,
+}
+
+impl Metrics {
+    pub fn compare(original: &Plane, recovered: &Plane) -> Metrics {
+        let mse = mse(&original.data, &recovered.data);
+        Metrics {
+            mse,
+            psnr: psnr(mse),
+            ssim: ssim(original, recovered),
+        }
+    }
+
+    /// Metrics of several channels taken together, PSNR is computed from the mean MSE
+    pub fn combine(channels: &[Metrics]) -> Metrics {
+        let count = channels.len() as f64;
+        let mse = channels.iter().map(|metrics| metrics.mse).sum::<f64>() / count;
+        Metrics {
+            mse,
+            psnr: psnr(mse),
+            ssim: channels.iter().map(|metrics| metrics.ssim).sum::<f64>() / count,
+        }
+    }
+}
+
+pub fn mse(original: &[u8], recovered: &[u8]) -> f64 {
+    assert_eq!(original.len(), recovered.len());
+    original
+        .iter()
+        .zip(recovered.iter())
+        .map(|(a, b)| (*a as f64 - *b as f64).powi(2))
+        .sum::<f64>()
+        / original.len() as f64
+}
+
+pub fn psnr(mse: f64) -> f64 {
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// Mean of the structural similarity index over every 8x8 window of the channels
+pub fn ssim(original: &Plane, recovered: &Plane) -> f64 {
+    assert_eq!(original.width, recovered.width);
+    assert_eq!(original.height, recovered.height);
+    let window_width = original.width.min(SSIM_WINDOW);
+    let window_height = original.height.min(SSIM_WINDOW);
+    let count = (window_width * window_height) as f64;
+
+    let mut sum = 0.0;
+    let mut windows = 0;
+    for start_y in 0..=original.height - window_height {
+        for start_x in 0..=original.width - window_width {
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) =
+                (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in start_y..start_y + window_height {
+                for x in start_x..start_x + window_width {
+                    let a = original.data[y * original.width + x] as f64;
+                    let b = recovered.data[y * recovered.width + x] as f64;
+                    sum_a += a;
+                    sum_b += b;
+                    sum_aa += a * a;
+                    sum_bb += b * b;
+                    sum_ab += a * b;
+                }
+            }
+            let (mean_a, mean_b) = (sum_a / count, sum_b / count);
+            let variance_a = sum_aa / count - mean_a * mean_a;
+            let variance_b = sum_bb / count - mean_b * mean_b;
+            let covariance = sum_ab / count - mean_a * mean_b;
+
+            sum += ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1)
+                    * (variance_a + variance_b + SSIM_C2));
+            windows += 1;
+        }
+    }
+    sum / windows as f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::{mse, psnr, Metrics};
+    use crate::image::Plane;
+
+    fn gradient(offset: u8) -> Plane {
+        let data = (0..16 * 16)
+            .map(|i| ((i % 16) * 8 + (i / 16) * 4) as u8 + offset)
+            .collect::<Vec<u8>>();
+        Plane::new(data, 16, 16)
+    }
+
+    #[test]
+    pub fn test_identical_channels() {
+        let metrics = Metrics::compare(&gradient(0), &gradient(0));
+
+        assert_eq!(metrics.mse, 0.0);
+        assert_eq!(metrics.psnr, f64::INFINITY);
+        assert!((metrics.ssim - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    pub fn test_known_error() {
+        let metrics = Metrics::compare(&gradient(0), &gradient(4));
+
+        assert_eq!(metrics.mse, 16.0);
+        assert!((metrics.psnr - 36.0896).abs() < 1e-3);
+        // a constant shift keeps the structure, only the luminance term drops a bit
+        assert!(metrics.ssim > 0.99 && metrics.ssim < 1.0);
+    }
+
+    #[test]
+    pub fn test_flattened_channel_loses_structure() {
+        let flat = Plane::new(vec![128; 16 * 16], 16, 16);
+        let metrics = Metrics::compare(&gradient(0), &flat);
+
+        assert!(metrics.ssim < 0.5);
+        assert_eq!(metrics.psnr, psnr(mse(&gradient(0).data, &flat.data)));
+    }
+
+    #[test]
+    pub fn test_combine() {
+        let a = Metrics {
+            mse: 10.0,
+            psnr: psnr(10.0),
+            ssim: 0.9,
+        };
+        let b = Metrics {
+            mse: 30.0,
+            psnr: psnr(30.0),
+            ssim: 0.7,
+        };
+        let combined = Metrics::combine(&[a, b]);
+
+        assert_eq!(combined.mse, 20.0);
+        assert_eq!(combined.psnr, psnr(20.0));
+        assert!((combined.ssim - 0.8).abs() < 1e-9);
+    }
+}
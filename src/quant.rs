@@ -1,3 +1,8 @@
+use crate::codec::jpeg::constants::{END_OF_BLOCK, ZERO_RUN, ZIG_ZAG};
+use crate::codec::jpeg::entropy::magnitude_category;
+use crate::codec::jpeg::huffman::HuffmanTable;
+use strum_macros::EnumIter;
+
 pub fn apply_quantization(data: &mut [[i16; 8]; 8], quantization: &[[u8; 8]; 8]) {
     for y in 0..8 {
         for x in 0..8 {
@@ -14,6 +19,240 @@ pub fn undo_quantization(data: &mut [[i16; 8]; 8], quantization: &[[u8; 8]; 8])
     }
 }
 
+/// Largest magnitude of a quantized coefficient that can be set by hand. AC values and DC
+/// differences need a code in the Annex K tables - categories up to 10 and 11 - the DC keeps
+/// its dequantized value within 11 bits and every dequantized value has to fit into i16
+pub fn editable_coefficient_limit(coefficient_index: usize, step: u8) -> i16 {
+    let step = step.max(1) as i16;
+    let limit = if coefficient_index == 0 {
+        (2047 / step).min(1023)
+    } else {
+        1023
+    };
+    limit.min(i16::MAX / step)
+}
+
+/// How the DCT coefficients are turned into multiples of the quantization steps
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter)]
+pub enum QuantizationStrategy {
+    /// Integer division like `apply_quantization`, every coefficient is rounded toward zero
+    Truncate,
+    Round,
+    /// AC coefficients are rounded up from two thirds of the step, so the small ones fall to zero
+    Deadzone,
+    /// Rounded, then the AC coefficients are chosen to minimize the squared error plus lambda times the bits
+    Trellis,
+}
+
+impl QuantizationStrategy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            QuantizationStrategy::Truncate => "Truncate",
+            QuantizationStrategy::Round => "Round to nearest",
+            QuantizationStrategy::Deadzone => "Deadzone",
+            QuantizationStrategy::Trellis => "Trellis (RDO)",
+        }
+    }
+}
+
+/// Quantization strategy with the codeword lengths of the AC table the blocks are coded with,
+/// only the trellis needs those
+pub struct Quantizer {
+    pub strategy: QuantizationStrategy,
+    // squared error of the coefficients a single bit is worth
+    pub lambda: f64,
+    ac_code_lengths: [u8; 256],
+}
+
+impl Quantizer {
+    pub fn new(strategy: QuantizationStrategy, lambda: f64, ac_table: &HuffmanTable) -> Quantizer {
+        Quantizer {
+            strategy,
+            lambda,
+            ac_code_lengths: ac_table.codes().map(|code| code.length),
+        }
+    }
+
+    pub fn quantize(
+        &self,
+        coefficients: &[[i16; 8]; 8],
+        quantization: &[[u8; 8]; 8],
+    ) -> [[i16; 8]; 8] {
+        let mut quantized = *coefficients;
+        match self.strategy {
+            QuantizationStrategy::Truncate => apply_quantization(&mut quantized, quantization),
+            QuantizationStrategy::Round | QuantizationStrategy::Trellis => divide_with_rounding(
+                &mut quantized,
+                quantization,
+                |step| step / 2,
+                |step| step / 2,
+            ),
+            QuantizationStrategy::Deadzone => divide_with_rounding(
+                &mut quantized,
+                quantization,
+                |step| step / 2,
+                |step| step / 3,
+            ),
+        }
+        if self.strategy == QuantizationStrategy::Trellis {
+            self.trellis(coefficients, quantization, &mut quantized);
+        }
+        quantized
+    }
+
+    /// Chooses the AC coefficients by dynamic programming over the zig-zag positions - the cost
+    /// of every nonzero coefficient depends on the run of zeros before it, so the best way to reach
+    /// each position is kept together with the nonzero coefficient before it.
+    /// Every coefficient is either rounded, moved one step toward zero or zeroed, the DC is left rounded
+    fn trellis(
+        &self,
+        coefficients: &[[i16; 8]; 8],
+        quantization: &[[u8; 8]; 8],
+        quantized: &mut [[i16; 8]; 8],
+    ) {
+        let at = |block: &[[i16; 8]; 8], k: usize| block[ZIG_ZAG[k] / 8][ZIG_ZAG[k] % 8];
+        let code_length = |value: usize| match self.ac_code_lengths[value] {
+            // the Annex K tables have no codes for the amplitudes of 11 bits
+            0 => 16.0,
+            length => length as f64,
+        };
+        // squared error of zeroing the coefficients before the position
+        let mut zeroed_error = [0.0; 65];
+        for k in 1..64 {
+            zeroed_error[k + 1] = zeroed_error[k] + (at(coefficients, k) as f64).powi(2);
+        }
+
+        // best[k] is the cost of the block up to the nonzero coefficient at k, 0 stands for the DC
+        let mut best = [f64::INFINITY; 64];
+        let mut choices = [(0_usize, 0_i16); 64];
+        best[0] = 0.0;
+        for k in 1..64 {
+            let rounded = at(quantized, k);
+            if rounded == 0 {
+                continue;
+            }
+            let coefficient = at(coefficients, k) as f64;
+            let step = quantization[ZIG_ZAG[k] / 8][ZIG_ZAG[k] % 8] as f64;
+            for value in [rounded, rounded - rounded.signum()] {
+                if value == 0 {
+                    continue;
+                }
+                let size = magnitude_category(value);
+                let error = (coefficient - value as f64 * step).powi(2);
+                for previous in 0..k {
+                    if best[previous].is_infinite() {
+                        continue;
+                    }
+                    let run = k - previous - 1;
+                    let bits = (run / 16) as f64 * code_length(ZERO_RUN)
+                        + code_length(((run % 16) << 4) | size as usize)
+                        + size as f64;
+                    let cost = best[previous] + zeroed_error[k] - zeroed_error[previous + 1]
+                        + error
+                        + self.lambda * bits;
+                    if cost < best[k] {
+                        best[k] = cost;
+                        choices[k] = (previous, value);
+                    }
+                }
+            }
+        }
+
+        // every block but one ending with the 63rd coefficient needs the EOB
+        let end_cost = |last: usize| {
+            let end_of_block = if last < 63 {
+                self.lambda * code_length(END_OF_BLOCK)
+            } else {
+                0.0
+            };
+            best[last] + zeroed_error[64] - zeroed_error[last + 1] + end_of_block
+        };
+        let mut last = (0..64)
+            .filter(|k| best[*k].is_finite())
+            .min_by(|a, b| end_cost(*a).total_cmp(&end_cost(*b)))
+            .unwrap_or(0);
+
+        let dc = quantized[0][0];
+        *quantized = [[0; 8]; 8];
+        quantized[0][0] = dc;
+        while last > 0 {
+            let (previous, value) = choices[last];
+            quantized[ZIG_ZAG[last] / 8][ZIG_ZAG[last] % 8] = value;
+            last = previous;
+        }
+    }
+}
+
+/// Divides by the steps rounding the magnitudes, `dc_offset` and `ac_offset` are added before
+/// the division, half of the step rounds to the nearest multiple
+fn divide_with_rounding(
+    data: &mut [[i16; 8]; 8],
+    quantization: &[[u8; 8]; 8],
+    dc_offset: fn(i32) -> i32,
+    ac_offset: fn(i32) -> i32,
+) {
+    for y in 0..8 {
+        for x in 0..8 {
+            let step = quantization[y][x] as i32;
+            let offset = if x == 0 && y == 0 {
+                dc_offset(step)
+            } else {
+                ac_offset(step)
+            };
+            let value = data[y][x] as i32;
+            data[y][x] = (value.signum() * ((value.abs() + offset) / step)) as i16;
+        }
+    }
+}
+
+/// How the quality setting turns the Annex K tables into the ones used for the compression
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter)]
+pub enum QualityScaling {
+    /// Curve of the libjpeg from the Independent JPEG Group, used by most of the encoders
+    Ijg,
+    /// Tables multiplied by (100 - quality) / 100
+    Linear,
+}
+
+impl QualityScaling {
+    pub fn name(&self) -> &'static str {
+        match self {
+            QualityScaling::Ijg => "IJG (libjpeg)",
+            QualityScaling::Linear => "Linear",
+        }
+    }
+
+    pub fn scale(&self, quantization_table: &[[u8; 8]; 8], quality: u8) -> [[u8; 8]; 8] {
+        match self {
+            QualityScaling::Ijg => ijg_scale_quantization_table(quantization_table, quality),
+            QualityScaling::Linear => scale_quantization_table(quantization_table, quality),
+        }
+    }
+}
+
+/// Scales the table like the jpeg_quality_scaling of the libjpeg, quality 50 keeps the table as it is,
+/// lower qualities divide 5000 by the quality and higher ones go linearly down to all ones at 100
+pub fn ijg_scale_quantization_table(
+    quantization_table: &[[u8; 8]; 8],
+    quality: u8,
+) -> [[u8; 8]; 8] {
+    let quality = quality.clamp(1, 100) as u32;
+    let scaling_factor = if quality < 50 {
+        5000 / quality
+    } else {
+        200 - quality * 2
+    };
+
+    let mut scaled_quantization_table: [[u8; 8]; 8] = [[0; 8]; 8];
+    for y in 0..8 {
+        for x in 0..8 {
+            scaled_quantization_table[y][x] =
+                ((quantization_table[y][x] as u32 * scaling_factor + 50) / 100).clamp(1, 255) as u8;
+        }
+    }
+    scaled_quantization_table
+}
+
 pub fn scale_quantization_table(quantization_table: &[[u8; 8]; 8], quality: u8) -> [[u8; 8]; 8] {
     let scaling_factor = 100 - quality;
 
@@ -30,6 +269,52 @@ pub fn scale_quantization_table(quantization_table: &[[u8; 8]; 8], quality: u8)
     scaled_quantization_table
 }
 
+/// IJG quality whose scaled Annex K table is the closest to a table found in a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QualityEstimate {
+    pub quality: u8,
+    // number of the 64 values equal to the scaled table
+    pub matching_values: usize,
+    pub max_difference: u16,
+}
+
+impl QualityEstimate {
+    pub fn is_exact(&self) -> bool {
+        self.matching_values == 64
+    }
+}
+
+/// Tries every quality and keeps the one with the smallest sum of differences from the table,
+/// the highest quality wins a tie as libjpeg writes the same table for neighbouring low qualities
+pub fn estimate_ijg_quality(
+    quantization_table: &[[u16; 8]; 8],
+    base_table: &[[u8; 8]; 8],
+) -> QualityEstimate {
+    let mut best: Option<(u32, QualityEstimate)> = None;
+    for quality in 1..=100 {
+        let scaled = ijg_scale_quantization_table(base_table, quality);
+        let differences = quantization_table
+            .iter()
+            .flatten()
+            .zip(scaled.iter().flatten())
+            .map(|(value, scaled)| (*value as i32 - *scaled as i32).unsigned_abs() as u16)
+            .collect::<Vec<u16>>();
+        let total = differences.iter().map(|x| *x as u32).sum::<u32>();
+        if best.is_none_or(|(best_total, _)| total <= best_total) {
+            let estimate = QualityEstimate {
+                quality,
+                matching_values: differences.iter().filter(|x| **x == 0).count(),
+                max_difference: *differences.iter().max().unwrap(),
+            };
+            best = Some((total, estimate));
+        }
+    }
+    best.unwrap().1
+}
+
+/// Luminance and chrominance quantization table
+pub type QuantizationTables = ([[u8; 8]; 8], [[u8; 8]; 8]);
+
 pub const LUMINANCE_QUANTIZATION_TABLE: [[u8; 8]; 8] = [
     [16, 11, 10, 16, 24, 40, 51, 61],
     [12, 12, 14, 19, 26, 58, 60, 55],
@@ -51,3 +336,182 @@ pub const CHROMINANCE_QUANTIZATION_TABLE: [[u8; 8]; 8] = [
     [99, 99, 99, 99, 99, 99, 99, 99],
     [99, 99, 99, 99, 99, 99, 99, 99],
 ];
+
+/// Tables that can be loaded into the quantization table editor
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter)]
+pub enum QuantizationPreset {
+    /// Every coefficient quantized with the same step of 16
+    Flat,
+    /// Tables K.1 and K.2 of the JPEG standard
+    AnnexK,
+    // the Annex K tables scaled to the IJG qualities 10, 30, 60, 80 and 100
+    Low,
+    Medium,
+    High,
+    VeryHigh,
+    Maximum,
+}
+
+impl QuantizationPreset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            QuantizationPreset::Flat => "Flat (16)",
+            QuantizationPreset::AnnexK => "Annex K",
+            QuantizationPreset::Low => "IJG quality 10",
+            QuantizationPreset::Medium => "IJG quality 30",
+            QuantizationPreset::High => "IJG quality 60",
+            QuantizationPreset::VeryHigh => "IJG quality 80",
+            QuantizationPreset::Maximum => "IJG quality 100",
+        }
+    }
+
+    /// Luminance and chrominance table of the preset
+    pub fn tables(&self) -> QuantizationTables {
+        let quality = match self {
+            QuantizationPreset::Flat => return ([[16; 8]; 8], [[16; 8]; 8]),
+            QuantizationPreset::AnnexK => 50,
+            QuantizationPreset::Low => 10,
+            QuantizationPreset::Medium => 30,
+            QuantizationPreset::High => 60,
+            QuantizationPreset::VeryHigh => 80,
+            QuantizationPreset::Maximum => 100,
+        };
+        (
+            ijg_scale_quantization_table(&LUMINANCE_QUANTIZATION_TABLE, quality),
+            ijg_scale_quantization_table(&CHROMINANCE_QUANTIZATION_TABLE, quality),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        editable_coefficient_limit, estimate_ijg_quality, ijg_scale_quantization_table,
+        QuantizationStrategy, Quantizer, CHROMINANCE_QUANTIZATION_TABLE,
+        LUMINANCE_QUANTIZATION_TABLE,
+    };
+    use crate::codec::jpeg::entropy;
+    use crate::codec::jpeg::huffman::HuffmanTable;
+
+    fn widen(table: &[[u8; 8]; 8]) -> [[u16; 8]; 8] {
+        let mut wide = [[0; 8]; 8];
+        for (wide_row, row) in wide.iter_mut().zip(table.iter()) {
+            for (wide_value, value) in wide_row.iter_mut().zip(row.iter()) {
+                *wide_value = *value as u16;
+            }
+        }
+        wide
+    }
+
+    #[test]
+    pub fn test_ijg_scaling() {
+        assert_eq!(
+            ijg_scale_quantization_table(&LUMINANCE_QUANTIZATION_TABLE, 50),
+            LUMINANCE_QUANTIZATION_TABLE
+        );
+        assert_eq!(
+            ijg_scale_quantization_table(&CHROMINANCE_QUANTIZATION_TABLE, 100),
+            [[1; 8]; 8]
+        );
+
+        // values from the tables libjpeg writes at quality 75 and 25
+        let quality_75 = ijg_scale_quantization_table(&LUMINANCE_QUANTIZATION_TABLE, 75);
+        assert_eq!(quality_75[0], [8, 6, 5, 8, 12, 20, 26, 31]);
+        let quality_25 = ijg_scale_quantization_table(&LUMINANCE_QUANTIZATION_TABLE, 25);
+        assert_eq!(quality_25[0], [32, 22, 20, 32, 48, 80, 102, 122]);
+        // quality 0 is treated as 1 and the values are limited to the baseline range
+        let quality_0 = ijg_scale_quantization_table(&CHROMINANCE_QUANTIZATION_TABLE, 0);
+        assert_eq!(quality_0[7][7], 255);
+    }
+
+    #[test]
+    pub fn test_editable_coefficient_limit() {
+        // opposite DC values of neighbouring blocks still differ by category 11 at most
+        assert_eq!(editable_coefficient_limit(0, 1), 1023);
+        assert_eq!(editable_coefficient_limit(0, 16), 127);
+        assert_eq!(editable_coefficient_limit(1, 16), 1023);
+        // 1023 * 255 overflows i16
+        assert_eq!(editable_coefficient_limit(63, 255), 128);
+        assert!(128 * 255 <= i16::MAX as i32);
+    }
+
+    #[test]
+    pub fn test_estimate_ijg_quality() {
+        for quality in [10, 50, 75, 90, 100] {
+            let table = ijg_scale_quantization_table(&CHROMINANCE_QUANTIZATION_TABLE, quality);
+            let estimate = estimate_ijg_quality(&widen(&table), &CHROMINANCE_QUANTIZATION_TABLE);
+            assert_eq!(estimate.quality, quality);
+            assert!(estimate.is_exact());
+        }
+
+        // a table of another encoder only comes close
+        let mut table = widen(&ijg_scale_quantization_table(
+            &LUMINANCE_QUANTIZATION_TABLE,
+            80,
+        ));
+        table[7][7] += 9;
+        let estimate = estimate_ijg_quality(&table, &LUMINANCE_QUANTIZATION_TABLE);
+        assert_eq!(estimate.quality, 80);
+        assert_eq!(estimate.matching_values, 63);
+        assert_eq!(estimate.max_difference, 9);
+    }
+
+    fn quantize(
+        strategy: QuantizationStrategy,
+        lambda: f64,
+        block: &[[i16; 8]; 8],
+    ) -> [[i16; 8]; 8] {
+        Quantizer::new(strategy, lambda, &HuffmanTable::luminance_ac())
+            .quantize(block, &[[10; 8]; 8])
+    }
+
+    #[test]
+    pub fn test_rounding_strategies() {
+        let mut block = [[0; 8]; 8];
+        block[0] = [-26, 19, -15, 6, 4, -4, 0, 0];
+
+        assert_eq!(
+            quantize(QuantizationStrategy::Truncate, 0.0, &block)[0],
+            [-2, 1, -1, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            quantize(QuantizationStrategy::Round, 0.0, &block)[0],
+            [-3, 2, -2, 1, 0, 0, 0, 0]
+        );
+        // 6 is below two thirds of the step
+        assert_eq!(
+            quantize(QuantizationStrategy::Deadzone, 0.0, &block)[0],
+            [-3, 2, -1, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    pub fn test_trellis() {
+        let mut block = [[0; 8]; 8];
+        block[0] = [-26, 19, -15, 6, 4, -4, 0, 13];
+        block[7][7] = 7;
+        let bits = |quantized: &[[i16; 8]; 8]| -> u32 {
+            let codes = HuffmanTable::luminance_ac().codes();
+            entropy::block_symbols(quantized, 0)
+                .iter()
+                .skip(1)
+                .map(|symbol| (codes[symbol.huffman_value() as usize].length + symbol.size) as u32)
+                .sum()
+        };
+        let rounded = quantize(QuantizationStrategy::Round, 0.0, &block);
+
+        // without the bits only the error counts, so the rounded values stay
+        assert_eq!(
+            quantize(QuantizationStrategy::Trellis, 0.0, &block),
+            rounded
+        );
+        // the lone coefficients at the end of the block cost the most bits for their error
+        let trellis = quantize(QuantizationStrategy::Trellis, 30.0, &block);
+        assert_eq!(trellis[0][0], rounded[0][0]);
+        assert_eq!(trellis[7][7], 0);
+        assert!(bits(&trellis) < bits(&rounded));
+        // a high enough lambda leaves the DC alone
+        let dc_only = quantize(QuantizationStrategy::Trellis, 10_000.0, &block);
+        assert!(dc_only.iter().flatten().skip(1).all(|x| *x == 0));
+    }
+}
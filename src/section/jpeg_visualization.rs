@@ -1,6 +1,15 @@
 pub mod drawing_utils;
+mod generation_loss;
+mod grid_shift;
+mod heatmap;
+mod lossless;
+mod mcu;
 pub mod model;
 pub mod page;
+mod quantization_strategies;
+mod rate_distortion;
 mod subsampling_tests;
+#[cfg(test)]
+mod test_images;
 mod utils;
 mod view;
@@ -42,7 +42,6 @@ pub fn draw_scaled_image_with_image_data_with_w_h_and_scale(
     ctx.scale(1.0 / scale_x, 1.0 / scale_y).unwrap();
 }
 
-#[allow(dead_code)]
 pub fn draw_scaled_image_with_w_h_and_scale(
     canvas: &ElRef<HtmlCanvasElement>,
     image: &Vec<u8>,
@@ -0,0 +1,247 @@
+use super::model::{PlotName, SubsamplingPack};
+use crate::block::BlockMatrix;
+use crate::dct::Transform;
+use crate::image::{
+    ChromaSiting, ColorTransform, DownsamplingFilter, Plane, RawImage, UpsamplingFilter,
+};
+use crate::metrics;
+use crate::quant::{QuantizationTables, Quantizer};
+
+/// Settings the image is compressed and decoded with on every pass
+pub struct Recompression<'a> {
+    pub subsampling_pack: &'a SubsamplingPack,
+    pub color_transform: ColorTransform,
+    pub downsampling_filter: DownsamplingFilter,
+    pub upsampling_filter: UpsamplingFilter,
+    pub chroma_siting: ChromaSiting,
+    pub transform: &'a dyn Transform,
+    // round the luminance and the chrominance blocks
+    pub quantizers: &'a (Quantizer, Quantizer),
+    // luminance and chrominance tables of the odd and the even generations
+    pub quant_tables: [QuantizationTables; 2],
+    // every generation moves the block grid by one more pixel to the right and down
+    pub grid_shift: bool,
+}
+
+/// PSNR of every generation and the images of the few generations shown as thumbnails
+pub struct GenerationLoss {
+    pub label: String,
+    // PSNR against the original image, the first value belongs to the first generation
+    pub psnr: Vec<f64>,
+    pub thumbnails: Vec<(usize, RawImage)>,
+}
+
+impl Recompression<'_> {
+    /// Compresses and decodes the image once, generations are numbered from 1
+    pub fn pass(&self, image: &RawImage, generation: usize) -> RawImage {
+        let offset = if self.grid_shift {
+            (generation - 1) % 8
+        } else {
+            0
+        };
+        self.compress(
+            image,
+            &self.quant_tables[(generation - 1) % 2],
+            (offset, offset),
+        )
+    }
+
+    /// Compresses and decodes the image with the block grid moved `offset` pixels right and down,
+    /// the decoded image is moved back, so it lines up with the source
+    pub fn compress(
+        &self,
+        image: &RawImage,
+        quant_tables: &QuantizationTables,
+        offset: (usize, usize),
+    ) -> RawImage {
+        let (luminance_table, chrominance_table) = quant_tables;
+        let (offset_x, offset_y) = offset;
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let mcu_width = self.subsampling_pack.mcu_width();
+        let mcu_height = self.subsampling_pack.mcu_height();
+        let padded_width = (width + offset_x).div_ceil(mcu_width) * mcu_width;
+        let padded_height = (height + offset_y).div_ceil(mcu_height) * mcu_height;
+
+        let compress = |plane: &Plane, table: &[[u8; 8]; 8], quantizer: &Quantizer| {
+            BlockMatrix::from_plane(plane)
+                .quantize(table, self.transform, quantizer)
+                .undo_quantization(table, self.transform)
+                .to_plane()
+        };
+        let prepare = |plane: &Plane| shift(plane, offset).pad(padded_width, padded_height);
+        let compress_chroma = |plane: &Plane, plot_name: PlotName| {
+            let (horiz_mult, vert_mult) = self.subsampling_pack.mults(plot_name);
+            let downsampled =
+                prepare(plane).downsample(horiz_mult, vert_mult, self.downsampling_filter);
+            compress(&downsampled, chrominance_table, &self.quantizers.1)
+                .upsample_filtered(
+                    horiz_mult,
+                    vert_mult,
+                    padded_width,
+                    padded_height,
+                    self.upsampling_filter,
+                    self.chroma_siting,
+                )
+                .crop(offset_x, offset_y, width, height)
+        };
+
+        let planes = image.to_ycbcr_planes(self.color_transform);
+        let recovered = crate::image::YCbCrPlanes {
+            ys: compress(&prepare(&planes.ys), luminance_table, &self.quantizers.0)
+                .crop(offset_x, offset_y, width, height),
+            cbs: compress_chroma(&planes.cbs, PlotName::CbsQuant3d),
+            crs: compress_chroma(&planes.crs, PlotName::CrsQuant3d),
+            color_transform: self.color_transform,
+        };
+        RawImage::new(
+            recovered.to_rgba(1, 1, width, height),
+            width as u32,
+            height as u32,
+        )
+    }
+
+    /// Compresses the image `count` times, every pass starts from the image decoded by the previous one
+    pub fn run(&self, original: &RawImage, count: usize, label: String) -> GenerationLoss {
+        let thumbnail_generations = thumbnail_generations(count);
+        let mut psnr = Vec::<f64>::with_capacity(count);
+        let mut thumbnails = Vec::<(usize, RawImage)>::new();
+        let mut image = original.clone();
+        for generation in 1..=count {
+            image = self.pass(&image, generation);
+            psnr.push(metrics::psnr(rgb_mse(original.as_ref(), image.as_ref())));
+            if thumbnail_generations.contains(&generation) {
+                thumbnails.push((generation, image.clone()));
+            }
+        }
+        GenerationLoss {
+            label,
+            psnr,
+            thumbnails,
+        }
+    }
+}
+
+/// First and last generation and three evenly spaced between them
+pub fn thumbnail_generations(count: usize) -> Vec<usize> {
+    let mut generations = [1, count / 4, count / 2, count * 3 / 4, count]
+        .iter()
+        .copied()
+        .filter(|generation| *generation >= 1)
+        .collect::<Vec<usize>>();
+    generations.dedup();
+    generations
+}
+
+/// Moves the plane `offset` samples right and down, the new top rows and left columns repeat the edge
+fn shift(plane: &Plane, offset: (usize, usize)) -> Plane {
+    let (offset_x, offset_y) = offset;
+    let width = plane.width + offset_x;
+    let height = plane.height + offset_y;
+    let mut data = Vec::<u8>::with_capacity(width * height);
+    for y in 0..height {
+        let row = y.saturating_sub(offset_y) * plane.width;
+        for x in 0..width {
+            data.push(plane.data[row + x.saturating_sub(offset_x)]);
+        }
+    }
+    Plane::new(data, width, height)
+}
+
+/// Mean squared error of the colour channels of two RGBA images, the alpha is skipped
+pub fn rgb_mse(original: &[u8], recovered: &[u8]) -> f64 {
+    let channels = |rgba: &[u8]| {
+        rgba.chunks(4)
+            .flat_map(|pixel| pixel[..3].to_vec())
+            .collect::<Vec<u8>>()
+    };
+    metrics::mse(&channels(original), &channels(recovered))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{shift, thumbnail_generations, Recompression};
+    use crate::codec::jpeg::huffman::HuffmanTable;
+    use crate::dct::DctAlgorithm;
+    use crate::image::{ChromaSiting, ColorTransform, DownsamplingFilter, Plane, UpsamplingFilter};
+    use crate::quant::{
+        QualityScaling, QuantizationStrategy, Quantizer, CHROMINANCE_QUANTIZATION_TABLE,
+        LUMINANCE_QUANTIZATION_TABLE,
+    };
+    use crate::section::jpeg_visualization::model::SubsamplingPack;
+    use crate::section::jpeg_visualization::test_images::textured_image;
+
+    // integer division, like the quantization before the strategies were added
+    fn truncating_quantizers() -> (Quantizer, Quantizer) {
+        let strategy = QuantizationStrategy::Truncate;
+        (
+            Quantizer::new(strategy, 0.0, &HuffmanTable::luminance_ac()),
+            Quantizer::new(strategy, 0.0, &HuffmanTable::chrominance_ac()),
+        )
+    }
+
+    fn recompression<'a>(
+        subsampling_pack: &'a SubsamplingPack,
+        quantizers: &'a (Quantizer, Quantizer),
+        grid_shift: bool,
+    ) -> Recompression<'a> {
+        let scaling = QualityScaling::Ijg;
+        let tables = (
+            scaling.scale(&LUMINANCE_QUANTIZATION_TABLE, 50),
+            scaling.scale(&CHROMINANCE_QUANTIZATION_TABLE, 50),
+        );
+        Recompression {
+            subsampling_pack,
+            color_transform: ColorTransform::Bt601Full,
+            downsampling_filter: DownsamplingFilter::Point,
+            upsampling_filter: UpsamplingFilter::Nearest,
+            chroma_siting: ChromaSiting::Centered,
+            transform: DctAlgorithm::Reference.transform(),
+            quantizers,
+            quant_tables: [tables, tables],
+            grid_shift,
+        }
+    }
+
+    #[test]
+    pub fn test_thumbnail_generations() {
+        assert_eq!(thumbnail_generations(1), vec![1]);
+        assert_eq!(thumbnail_generations(3), vec![1, 2, 3]);
+        assert_eq!(thumbnail_generations(20), vec![1, 5, 10, 15, 20]);
+    }
+
+    #[test]
+    pub fn test_shift_repeats_edges() {
+        let plane = Plane::new(vec![1, 2, 3, 4], 2, 2);
+
+        assert_eq!(shift(&plane, (1, 1)).data, vec![1, 1, 2, 1, 1, 2, 3, 3, 4]);
+        assert_eq!(shift(&plane, (1, 0)).data, vec![1, 1, 2, 3, 3, 4]);
+        assert_eq!(shift(&plane, (0, 0)).data, plane.data);
+    }
+
+    #[test]
+    pub fn test_generation_loss() {
+        let original = textured_image(20, 12);
+        let subsampling_pack = SubsamplingPack::new(2, 2, 1, 1).unwrap();
+        let quantizers = truncating_quantizers();
+
+        let aligned =
+            recompression(&subsampling_pack, &quantizers, false).run(&original, 6, String::new());
+        assert_eq!(aligned.psnr.len(), 6);
+        assert_eq!(
+            aligned
+                .thumbnails
+                .iter()
+                .map(|(generation, _)| *generation)
+                .collect::<Vec<usize>>(),
+            vec![1, 3, 4, 6]
+        );
+        assert_eq!(aligned.thumbnails[0].1.width(), 20);
+        assert!(aligned.psnr[5] <= aligned.psnr[0]);
+
+        // a moving grid keeps finding new edges to quantize
+        let shifted =
+            recompression(&subsampling_pack, &quantizers, true).run(&original, 6, String::new());
+        assert_eq!(shifted.psnr[0], aligned.psnr[0]);
+        assert!(shifted.psnr[5] < aligned.psnr[5]);
+    }
+}
@@ -0,0 +1,74 @@
+use super::generation_loss::{rgb_mse, Recompression};
+use crate::image::RawImage;
+use crate::metrics;
+use crate::quant::QuantizationTables;
+use std::rc::Rc;
+
+/// Source image recompressed with the block grid at the origin and with the grid moved by `offset`,
+/// a decoded JPEG still carries the blocks of its own grid, which lines up with the first one
+pub struct GridShiftExperiment {
+    pub offset: (usize, usize),
+    pub aligned: Rc<RawImage>,
+    pub shifted: Rc<RawImage>,
+    // PSNR of the whole recompressed images against the source
+    pub aligned_psnr: f64,
+    pub shifted_psnr: f64,
+}
+
+impl GridShiftExperiment {
+    pub fn run(
+        source: &RawImage,
+        recompression: &Recompression,
+        quant_tables: &QuantizationTables,
+        offset: (usize, usize),
+    ) -> GridShiftExperiment {
+        let aligned = recompression.compress(source, quant_tables, (0, 0));
+        let shifted = recompression.compress(source, quant_tables, offset);
+        GridShiftExperiment {
+            offset,
+            aligned_psnr: metrics::psnr(rgb_mse(source.as_ref(), aligned.as_ref())),
+            shifted_psnr: metrics::psnr(rgb_mse(source.as_ref(), shifted.as_ref())),
+            aligned: Rc::new(aligned),
+            shifted: Rc::new(shifted),
+        }
+    }
+}
+
+/// RGBA image of how much more the shifted recompression differs from the source than the aligned one,
+/// red where the shifted grid adds error, blue where it removes it and white where they are equal
+pub fn error_difference(source: &[u8], aligned: &[u8], shifted: &[u8]) -> Vec<u8> {
+    let error = |recovered: &[u8], i: usize| -> i32 {
+        (0..3)
+            .map(|channel| (recovered[i + channel] as i32 - source[i + channel] as i32).abs())
+            .sum()
+    };
+    let mut image = Vec::<u8>::with_capacity(source.len());
+    for i in (0..source.len()).step_by(4) {
+        let difference = error(shifted, i) - error(aligned, i);
+        // the same scale as the difference image, 255 means three channels off by 85
+        let fade = 255 - difference.abs().min(255) as u8;
+        if difference > 0 {
+            image.extend([255, fade, fade, 255]);
+        } else {
+            image.extend([fade, fade, 255, 255]);
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod test {
+    use super::error_difference;
+
+    #[test]
+    pub fn test_error_difference() {
+        let source = [100, 100, 100, 255, 100, 100, 100, 255, 100, 100, 100, 255];
+        let aligned = [100, 100, 100, 255, 110, 100, 100, 255, 104, 100, 100, 255];
+        let shifted = [100, 100, 100, 255, 100, 100, 100, 255, 90, 100, 110, 255];
+
+        assert_eq!(
+            error_difference(&source, &aligned, &shifted),
+            vec![255, 255, 255, 255, 245, 245, 255, 255, 255, 239, 239, 255]
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use crate::block::BlockMatrix;
+use crate::codec::jpeg::entropy::{self, SymbolKind};
+use crate::codec::jpeg::huffman::HuffmanTable;
+use crate::BLOCK_SIZE;
+use strum_macros::EnumIter;
+
+/// Value shown for every 8x8 block of the image window
+#[derive(Debug, PartialEq, Eq, Hash, EnumIter, Clone, Copy)]
+pub enum Heatmap {
+    Error,
+    NonZeroCoefficients,
+    Bits,
+}
+
+impl Heatmap {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Heatmap::Error => "MSE of RGB",
+            Heatmap::NonZeroCoefficients => "Non-zero coefficients",
+            Heatmap::Bits => "Estimated bits",
+        }
+    }
+}
+
+/// Side of the window in 8x8 blocks, the heatmaps hold one value per block, row by row
+const WINDOW_BLOCKS: usize = BLOCK_SIZE as usize / 8;
+
+/// Mean squared error of the RGB channels of every block, both images are RGBA and as large as the window
+pub fn block_errors(original: &[u8], recovered: &[u8]) -> Vec<f64> {
+    let size = BLOCK_SIZE as usize;
+    let mut errors = vec![0.0; WINDOW_BLOCKS * WINDOW_BLOCKS];
+    for y in 0..size {
+        for x in 0..size {
+            let offset = (y * size + x) * 4;
+            let squared_error: f64 = (0..3)
+                .map(|channel| {
+                    (original[offset + channel] as f64 - recovered[offset + channel] as f64).powi(2)
+                })
+                .sum();
+            errors[(y / 8) * WINDOW_BLOCKS + x / 8] += squared_error / (3.0 * 64.0);
+        }
+    }
+    errors
+}
+
+/// Number of non-zero quantized coefficients of every block of the component
+pub fn non_zero_coefficients(blocks: &BlockMatrix) -> Vec<f64> {
+    blocks
+        .blocks
+        .iter()
+        .map(|block| block.0.iter().flatten().filter(|x| **x != 0).count() as f64)
+        .collect()
+}
+
+/// Huffman coded bits of every block of the component, `order` lists the blocks in the order
+/// they are coded, the DC of each block is coded as the difference from the previous one
+pub fn block_bits(
+    blocks: &BlockMatrix,
+    order: &[usize],
+    dc_table: &HuffmanTable,
+    ac_table: &HuffmanTable,
+) -> Vec<f64> {
+    let (dc_codes, ac_codes) = (dc_table.codes(), ac_table.codes());
+    let mut bits = vec![0.0; blocks.blocks.len()];
+    let mut previous_dc = 0;
+    for index in order {
+        let block = &blocks.blocks[*index].0;
+        bits[*index] = entropy::block_symbols(block, previous_dc)
+            .iter()
+            .map(|symbol| {
+                let codes = match symbol.kind {
+                    SymbolKind::Dc => &dc_codes,
+                    _ => &ac_codes,
+                };
+                (codes[symbol.huffman_value() as usize].length + symbol.size) as f64
+            })
+            .sum();
+        previous_dc = block[0][0];
+    }
+    bits
+}
+
+/// Adds the values of the component blocks under the window to the window blocks they cover,
+/// a subsampled block is shared evenly by the `horiz_mult` x `vert_mult` blocks under it
+pub fn add_window_values(
+    heatmap: &mut [f64],
+    values: &[f64],
+    width: usize,
+    start_u: usize,
+    start_v: usize,
+    horiz_mult: usize,
+    vert_mult: usize,
+) {
+    for y in 0..WINDOW_BLOCKS {
+        for x in 0..WINDOW_BLOCKS {
+            let u = start_u / horiz_mult + x / horiz_mult;
+            let v = start_v / vert_mult + y / vert_mult;
+            heatmap[y * WINDOW_BLOCKS + x] +=
+                values[v * width + u] / (horiz_mult * vert_mult) as f64;
+        }
+    }
+}
+
+/// RGBA image as large as the window, every block is coloured by its value relative to the largest one
+pub fn heatmap_image(heatmap: &[f64]) -> Vec<u8> {
+    let size = BLOCK_SIZE as usize;
+    let max = heatmap.iter().copied().fold(0.0, f64::max);
+    let color_map = colorous::INFERNO;
+
+    let mut image = Vec::<u8>::with_capacity(size * size * 4);
+    for y in 0..size {
+        for x in 0..size {
+            let value = heatmap[(y / 8) * WINDOW_BLOCKS + x / 8];
+            let normalized = if max > 0.0 { value / max } else { 0.0 };
+            image.extend(color_map.eval_continuous(normalized).as_array());
+            image.push(255);
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod test {
+    use super::{add_window_values, block_bits, block_errors, non_zero_coefficients};
+    use crate::block::{Block, BlockMatrix};
+    use crate::codec::jpeg::huffman::HuffmanTable;
+    use crate::BLOCK_SIZE;
+
+    #[test]
+    pub fn test_block_errors() {
+        let size = BLOCK_SIZE as usize;
+        let original = vec![100; size * size * 4];
+        let mut recovered = original.clone();
+        // the red channel of the second block of the first row is off by 6
+        for y in 0..8 {
+            for x in 8..16 {
+                recovered[(y * size + x) * 4] = 106;
+            }
+        }
+
+        let errors = block_errors(&original, &recovered);
+        assert_eq!(errors[0], 0.0);
+        assert!((errors[1] - 12.0).abs() < 1e-9);
+        assert_eq!(errors.iter().filter(|error| **error > 0.0).count(), 1);
+    }
+
+    #[test]
+    pub fn test_bits_follow_coding_order() {
+        let mut first = [[0; 8]; 8];
+        first[0][0] = 10;
+        first[0][1] = -3;
+        let second = [[0; 8]; 8];
+        let blocks = BlockMatrix {
+            blocks: vec![Block(first), Block(second)],
+            width: 2,
+            height: 1,
+        };
+        let (dc_table, ac_table) = (HuffmanTable::luminance_dc(), HuffmanTable::luminance_ac());
+
+        assert_eq!(non_zero_coefficients(&blocks), vec![2.0, 0.0]);
+        // DC difference 10 - 3 + 4 bits, AC -3 - 2 + 2 bits, EOB - 4 bits
+        // DC difference -10 after the first block - 3 + 4 bits, EOB - 4 bits
+        assert_eq!(
+            block_bits(&blocks, &[0, 1], &dc_table, &ac_table),
+            vec![15.0, 11.0]
+        );
+        // coded the other way round the empty block has nothing to predict its DC from
+        assert_eq!(
+            block_bits(&blocks, &[1, 0], &dc_table, &ac_table),
+            vec![15.0, 6.0]
+        );
+    }
+
+    #[test]
+    pub fn test_subsampled_values_are_shared() {
+        let mut heatmap = vec![0.0; 64];
+        // 4:2:0 chroma of a window that starts at the second MCU row
+        let values = (0..4 * 8).map(|x| x as f64).collect::<Vec<f64>>();
+        add_window_values(&mut heatmap, &values, 4, 0, 2, 2, 2);
+
+        assert_eq!(heatmap[0], 1.0);
+        assert_eq!(heatmap[9], 1.0);
+        assert_eq!(heatmap[2], 1.25);
+        assert_eq!(heatmap.iter().sum::<f64>(), (4..20).sum::<usize>() as f64);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::codec::jpeg::lossless::{self, Predictor, ResidualCoding};
+use strum::IntoEnumIterator;
+
+/// Residuals above this are drawn with the brightest colour, so the maps of the predictors
+/// share one scale
+const RESIDUAL_MAP_MAX: f64 = 32.0;
+
+/// Red, green and blue of the image window coded losslessly with one predictor
+pub struct LosslessResult {
+    pub predictor: Predictor,
+    // residuals of every channel, row by row
+    pub residuals: [Vec<i16>; 3],
+    pub codings: [ResidualCoding; 3],
+    // entropy coded data of the three channels, the headers are left out
+    pub bits: u64,
+    pub bits_per_pixel: f64,
+}
+
+/// Codes the RGB channels of the RGBA window with every predictor, each channel gets its own
+/// Huffman table built for its residuals
+pub fn compare(window: &[u8], width: usize) -> Vec<LosslessResult> {
+    let channels = [0, 1, 2].map(|channel| {
+        window
+            .iter()
+            .skip(channel)
+            .step_by(4)
+            .copied()
+            .collect::<Vec<u8>>()
+    });
+    let pixels = channels[0].len();
+
+    Predictor::iter()
+        .map(|predictor| {
+            let residuals = channels
+                .each_ref()
+                .map(|channel| lossless::residuals(channel, width, predictor));
+            let codings = residuals
+                .each_ref()
+                .map(|residuals| ResidualCoding::new(residuals));
+            let bits = codings.iter().map(|coding| coding.bits).sum::<u64>();
+            LosslessResult {
+                predictor,
+                residuals,
+                codings,
+                bits,
+                bits_per_pixel: bits as f64 / pixels as f64,
+            }
+        })
+        .collect()
+}
+
+/// RGBA image of the mean absolute residual of the channels at every pixel
+pub fn residual_image(result: &LosslessResult) -> Vec<u8> {
+    let color_map = colorous::INFERNO;
+    let [red, green, blue] = &result.residuals;
+
+    let mut image = Vec::<u8>::with_capacity(red.len() * 4);
+    for ((r, g), b) in red.iter().zip(green.iter()).zip(blue.iter()) {
+        let mean = (r.unsigned_abs() + g.unsigned_abs() + b.unsigned_abs()) as f64 / 3.0;
+        image.extend(
+            color_map
+                .eval_continuous((mean / RESIDUAL_MAP_MAX).min(1.0))
+                .as_array(),
+        );
+        image.push(255);
+    }
+    image
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compare, residual_image};
+    use crate::codec::jpeg::lossless::Predictor;
+
+    #[test]
+    pub fn test_compare_predictors() {
+        // horizontal gradient in red, vertical in green, flat blue
+        let width = 16;
+        let window = (0..width * width)
+            .flat_map(|i| [(i % width * 8) as u8, (i / width * 8) as u8, 50, 255])
+            .collect::<Vec<u8>>();
+        let results = compare(&window, width);
+
+        assert_eq!(results.len(), 7);
+        let best = results
+            .iter()
+            .min_by_key(|result| result.bits)
+            .unwrap()
+            .predictor;
+        // a plane is predicted exactly by Ra + Rb - Rc away from the edges
+        assert_eq!(best, Predictor::Gradient);
+        let gradient = &results[3];
+        assert!(gradient.residuals[0][width..]
+            .iter()
+            .all(|residual| *residual == 0));
+        assert!(gradient.bits_per_pixel < 8.0);
+
+        let image = residual_image(gradient);
+        assert_eq!(image.len(), window.len());
+        assert!(image.iter().skip(3).step_by(4).all(|alpha| *alpha == 255));
+    }
+}
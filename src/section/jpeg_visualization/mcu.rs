@@ -0,0 +1,155 @@
+use super::model::{PlotName, SubsamplingPack};
+use crate::BLOCK_SIZE;
+
+/// Block of one component in the order it is emitted into the interleaved scan
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct McuBlock {
+    // 0 for Y, 1 for Cb and 2 for Cr
+    pub component: usize,
+    // coordinates in the blocks of the component under the image window
+    pub u: usize,
+    pub v: usize,
+    // position inside the MCU, row by row
+    pub index: usize,
+}
+
+impl McuBlock {
+    pub fn name(&self) -> String {
+        format!("{}{}", ["Y", "Cb", "Cr"][self.component], self.index)
+    }
+}
+
+/// MCU of the interleaved scan cut to the image window
+pub struct Mcu {
+    // coordinates in the MCUs under the image window
+    pub x: usize,
+    pub y: usize,
+    // Y blocks followed by the Cb and the Cr blocks
+    pub blocks: Vec<McuBlock>,
+}
+
+// channels of the Y, Cb and Cr components
+const CHANNELS: [PlotName; 3] = [
+    PlotName::YsQuant3d,
+    PlotName::CbsQuant3d,
+    PlotName::CrsQuant3d,
+];
+
+/// Number of blocks of the component under the image window, the luma covers 8x8 blocks
+pub fn window_blocks(subsampling_pack: &SubsamplingPack, component: usize) -> (usize, usize) {
+    let block_count = BLOCK_SIZE as usize / 8;
+    let (horiz_mult, vert_mult) = subsampling_pack.mults(CHANNELS[component]);
+    (
+        block_count.div_ceil(horiz_mult),
+        block_count.div_ceil(vert_mult),
+    )
+}
+
+/// MCUs under the image window in the order they are coded, the window is aligned to the MCU grid,
+/// so only the MCUs at its right and bottom edge can be cut
+pub fn window_mcus(subsampling_pack: &SubsamplingPack) -> Vec<Mcu> {
+    let sampling = CHANNELS.map(|channel| {
+        let (horiz, vert) = subsampling_pack.factors(channel);
+        (horiz as usize, vert as usize)
+    });
+    let block_count = BLOCK_SIZE as usize / 8;
+    let mcus_horiz = block_count.div_ceil(sampling[0].0);
+    let mcus_vert = block_count.div_ceil(sampling[0].1);
+
+    let mut mcus = Vec::<Mcu>::with_capacity(mcus_horiz * mcus_vert);
+    for y in 0..mcus_vert {
+        for x in 0..mcus_horiz {
+            let mut blocks = Vec::<McuBlock>::new();
+            for (component, (horiz, vert)) in sampling.iter().enumerate() {
+                let (width, height) = window_blocks(subsampling_pack, component);
+                for row in 0..*vert {
+                    for column in 0..*horiz {
+                        let u = x * horiz + column;
+                        let v = y * vert + row;
+                        if u < width && v < height {
+                            blocks.push(McuBlock {
+                                component,
+                                u,
+                                v,
+                                index: row * horiz + column,
+                            });
+                        }
+                    }
+                }
+            }
+            mcus.push(Mcu { x, y, blocks });
+        }
+    }
+    mcus
+}
+
+/// MCU under the image window that covers the block of the component
+pub fn mcu_of_block(
+    subsampling_pack: &SubsamplingPack,
+    component: usize,
+    u: usize,
+    v: usize,
+) -> (usize, usize) {
+    let (horiz, vert) = subsampling_pack.factors(CHANNELS[component]);
+    (u / horiz as usize, v / vert as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{mcu_of_block, window_mcus};
+    use crate::section::jpeg_visualization::model::SubsamplingPack;
+
+    fn names(subsampling_pack: &SubsamplingPack, mcu: usize) -> Vec<String> {
+        window_mcus(subsampling_pack)[mcu]
+            .blocks
+            .iter()
+            .map(|block| block.name())
+            .collect()
+    }
+
+    #[test]
+    pub fn test_window_mcus_4_2_0() {
+        let subsampling_pack = SubsamplingPack::new(2, 2, 1, 1).unwrap();
+        let mcus = window_mcus(&subsampling_pack);
+
+        assert_eq!(mcus.len(), 16);
+        assert_eq!(
+            names(&subsampling_pack, 0),
+            vec!["Y0", "Y1", "Y2", "Y3", "Cb0", "Cr0"]
+        );
+        let second_row = &mcus[4];
+        assert_eq!((second_row.x, second_row.y), (0, 1));
+        assert_eq!(
+            second_row
+                .blocks
+                .iter()
+                .map(|block| (block.u, block.v))
+                .collect::<Vec<(usize, usize)>>(),
+            vec![(0, 2), (1, 2), (0, 3), (1, 3), (0, 1), (0, 1)]
+        );
+    }
+
+    #[test]
+    pub fn test_window_mcus_with_chroma_factors() {
+        let subsampling_pack = SubsamplingPack::new(2, 2, 1, 2).unwrap();
+
+        assert_eq!(
+            names(&subsampling_pack, 0),
+            vec!["Y0", "Y1", "Y2", "Y3", "Cb0", "Cb1", "Cr0", "Cr1"]
+        );
+        assert_eq!(mcu_of_block(&subsampling_pack, 1, 3, 5), (3, 2));
+        assert_eq!(mcu_of_block(&subsampling_pack, 0, 3, 5), (1, 2));
+    }
+
+    #[test]
+    pub fn test_window_mcus_cut_at_the_edge() {
+        // 3:1:1 MCUs are 24 pixels wide, so the third one sticks out of the window
+        let subsampling_pack = SubsamplingPack::new(3, 1, 1, 1).unwrap();
+        let mcus = window_mcus(&subsampling_pack);
+
+        assert_eq!(mcus.len(), 3 * 8);
+        assert_eq!(names(&subsampling_pack, 2), vec!["Y0", "Y1", "Cb0", "Cr0"]);
+        let block_count: usize = mcus.iter().map(|mcu| mcu.blocks.len()).sum();
+        assert_eq!(block_count, 64 + 2 * 3 * 8);
+    }
+}
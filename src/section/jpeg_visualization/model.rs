@@ -1,5 +1,22 @@
+use super::generation_loss::GenerationLoss;
+use super::grid_shift::GridShiftExperiment;
+use super::heatmap::Heatmap;
+use super::lossless::LosslessResult;
+use super::quantization_strategies::StrategyResult;
+use super::rate_distortion::RdCurve;
 use crate::block::BlockMatrix;
+use crate::codec::jpeg::decoder::{DecodedJpeg, ScanInfo};
+use crate::codec::jpeg::encoder::{self, HuffmanTables};
+use crate::codec::jpeg::entropy::SymbolFrequencies;
+use crate::codec::jpeg::huffman::HuffmanTable;
+use crate::codec::jpeg::lossless::Predictor;
+use crate::codec::jpeg::segments::Segment;
+use crate::dct::DctAlgorithm;
 use crate::image;
+use crate::metrics::Metrics;
+use crate::quant::{
+    QualityEstimate, QualityScaling, QuantizationPreset, QuantizationStrategy, QuantizationTables,
+};
 use seed::prelude::*;
 use std::collections::HashMap;
 use std::rc::Rc;
@@ -9,18 +26,272 @@ use web_sys::{HtmlCanvasElement, HtmlImageElement};
 pub struct ImagePack {
     pub raw_image: Rc<image::RawImage>,
     pub image_window: image::RawImageWindow,
-    pub ycbcr: image::YCbCrImage,
+    // whole image padded to complete MCUs, the chroma planes are subsampled
+    pub planes: image::YCbCrPlanes,
+    // quantized coefficients of the whole image
+    pub quantized: HashMap<PlotName, BlockMatrix>,
+    // quantized coefficients changed by hand, keyed by the channel, the index of the block
+    // in the whole image and the index of the coefficient in the natural order
+    pub coefficient_edits: HashMap<(PlotName, usize, usize), i16>,
+    // whole image after the dequantization and the inverse DCT
+    pub recovered: image::YCbCrPlanes,
+    // recovered image with the chroma interpolated back to the full resolution
+    pub upsampled: image::YCbCrPlanes,
+    // recovered image with the block edges filtered, None without a deblocking filter
+    pub deblocked: Option<image::YCbCrPlanes>,
+    // error of the recovered image window per channel, chroma is compared at full resolution
+    pub window_metrics: Vec<(&'static str, Metrics)>,
+    // the same for the deblocked image window, empty without a deblocking filter
+    pub deblocked_window_metrics: Vec<(&'static str, Metrics)>,
+    // source recompressed with the aligned and the shifted block grid, None when the experiment is off
+    pub grid_shift_experiment: Option<GridShiftExperiment>,
+    // Annex K and optimal Huffman tables of the whole image, DC and AC of luminance and chrominance
+    pub huffman_comparisons: Vec<HuffmanComparison>,
+    // bits of the amplitudes after the codewords, the same with any tables
+    pub amplitude_bits: u64,
+    // tables the encoded file is written with, the optimal ones when the coding is optimized
+    pub huffman_tables: HuffmanTables,
+    // RGB of the image window coded losslessly with every predictor
+    pub lossless_results: Vec<LosslessResult>,
+    // estimated bits of the DCT coded blocks under the image window
+    pub dct_window_bits: f64,
+    // error of the colour conversion alone over the whole image, for every transform
+    pub color_round_trips: Vec<(image::ColorTransform, ColorRoundTrip)>,
 
+    // blocks of the quantized coefficients under the image window
     pub plot_data: HashMap<PlotName, BlockMatrix>,
+    // baseline JFIF file encoded from the quantized coefficients
+    pub encoded_jpeg: Vec<u8>,
+    // coefficients and tables of the uploaded file, None if it isn't a baseline JPEG we can show
+    pub source_jpeg: Option<Rc<DecodedJpeg>>,
+    pub file_bytes: Vec<u8>,
+    // marker segments of the uploaded file, empty if it isn't a JPEG
+    pub file_segments: Vec<Segment>,
+    // IJG quality closest to the luminance and chrominance tables of the file
+    pub source_quality: Vec<(&'static str, QualityEstimate)>,
+    // offset and length of the segment shown byte by byte
+    pub chosen_segment: Option<(usize, usize)>,
+    // every scan of the file and how many of them are applied to source_jpeg
+    pub source_scans: Vec<ScanInfo>,
+    pub source_scan_count: usize,
 
     pub chosen_block_x: f64,
     pub chosen_block_y: f64,
+    // symbol of the chosen block highlighted in the entropy coding breakdown
+    pub chosen_symbol: Option<usize>,
 }
 
+/// Huffman table of one slot with the codeword bits it takes to code the whole image
+pub struct HuffmanComparison {
+    pub name: &'static str,
+    pub standard: HuffmanTable,
+    pub optimal: HuffmanTable,
+    pub standard_bits: u64,
+    pub optimal_bits: u64,
+}
+
+/// Difference between the image and the same image converted to another colour space and back
+#[derive(Clone, Copy)]
+pub struct ColorRoundTrip {
+    pub max_error: u8,
+    // percentage of the pixels with any channel changed
+    pub changed_pixels: f64,
+    pub psnr: f64,
+}
+
+// an interleaved MCU can't hold more blocks, see A.2.2 of the JPEG standard
+const MAX_BLOCKS_IN_MCU: u8 = 10;
+
+/// Sampling factors of the components as written into SOF, the chroma factors divide the luma ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct SubsamplingPack {
-    pub j: i8,
-    pub a: i8,
-    pub b: i8,
+    pub luma_horiz: u8,
+    pub luma_vert: u8,
+    pub cb_horiz: u8,
+    pub cb_vert: u8,
+    pub cr_horiz: u8,
+    pub cr_vert: u8,
+}
+
+impl SubsamplingPack {
+    /// Layouts offered in the sidebar, the J:a:b notation alone can't tell the 4:2:2 with 2x1 luma
+    /// from the one with 2x2 luma and 1x2 chroma
+    pub const PRESETS: [SubsamplingPack; 8] = [
+        SubsamplingPack::preset(1, 1, 1, 1),
+        SubsamplingPack::preset(2, 1, 1, 1),
+        SubsamplingPack::preset(2, 2, 1, 2),
+        SubsamplingPack::preset(4, 1, 1, 1),
+        SubsamplingPack::preset(3, 1, 1, 1),
+        SubsamplingPack::preset(1, 2, 1, 1),
+        SubsamplingPack::preset(2, 2, 1, 1),
+        SubsamplingPack::preset(4, 2, 1, 1),
+    ];
+
+    const fn preset(luma_horiz: u8, luma_vert: u8, chroma_horiz: u8, chroma_vert: u8) -> Self {
+        SubsamplingPack {
+            luma_horiz,
+            luma_vert,
+            cb_horiz: chroma_horiz,
+            cb_vert: chroma_vert,
+            cr_horiz: chroma_horiz,
+            cr_vert: chroma_vert,
+        }
+    }
+
+    /// Layout with the same factors for Cb and Cr
+    pub fn new(
+        luma_horiz: u8,
+        luma_vert: u8,
+        chroma_horiz: u8,
+        chroma_vert: u8,
+    ) -> Option<SubsamplingPack> {
+        SubsamplingPack::with_factors(
+            (luma_horiz, luma_vert),
+            (chroma_horiz, chroma_vert),
+            (chroma_horiz, chroma_vert),
+        )
+    }
+
+    /// Takes the (H, V) factors of Y, Cb and Cr. Returns None for the layouts a baseline JPEG
+    /// can't describe or the chroma planes can't be derived from the luma by an integer factor
+    pub fn with_factors(
+        (luma_horiz, luma_vert): (u8, u8),
+        (cb_horiz, cb_vert): (u8, u8),
+        (cr_horiz, cr_vert): (u8, u8),
+    ) -> Option<SubsamplingPack> {
+        let pack = SubsamplingPack {
+            luma_horiz,
+            luma_vert,
+            cb_horiz,
+            cb_vert,
+            cr_horiz,
+            cr_vert,
+        };
+        let factors = [
+            (luma_horiz, luma_vert),
+            (cb_horiz, cb_vert),
+            (cr_horiz, cr_vert),
+        ];
+        let is_legal = factors.iter().all(|(horiz, vert)| {
+            (1..=4).contains(horiz)
+                && (1..=4).contains(vert)
+                && luma_horiz.is_multiple_of(*horiz)
+                && luma_vert.is_multiple_of(*vert)
+        }) && factors.iter().map(|(horiz, vert)| horiz * vert).sum::<u8>()
+            <= MAX_BLOCKS_IN_MCU;
+        if is_legal {
+            Some(pack)
+        } else {
+            None
+        }
+    }
+
+    /// Horizontal and vertical sampling factors of the channel
+    pub fn factors(&self, plot_name: PlotName) -> (u8, u8) {
+        match plot_name {
+            PlotName::YsQuant3d => (self.luma_horiz, self.luma_vert),
+            PlotName::CbsQuant3d => (self.cb_horiz, self.cb_vert),
+            PlotName::CrsQuant3d => (self.cr_horiz, self.cr_vert),
+        }
+    }
+
+    /// How many luma samples a sample of the channel covers horizontally and vertically
+    pub fn mults(&self, plot_name: PlotName) -> (usize, usize) {
+        let (horiz, vert) = self.factors(plot_name);
+        (
+            (self.luma_horiz / horiz) as usize,
+            (self.luma_vert / vert) as usize,
+        )
+    }
+
+    pub fn has_shared_chroma_factors(&self) -> bool {
+        (self.cb_horiz, self.cb_vert) == (self.cr_horiz, self.cr_vert)
+    }
+
+    /// The J:a:b notation of the Cb sampling, b is 0 whenever the chroma has fewer rows than the luma
+    pub fn ratio(&self) -> (u8, u8, u8) {
+        let horiz_mult = self.luma_horiz / self.cb_horiz;
+        let j = if 4 % horiz_mult == 0 { 4 } else { horiz_mult };
+        let a = j / horiz_mult;
+        let b = if self.luma_vert == self.cb_vert { a } else { 0 };
+        (j, a, b)
+    }
+
+    /// The J:a:b notation, the factors of both chroma components when they differ
+    pub fn ratio_name(&self) -> String {
+        if self.has_shared_chroma_factors() {
+            let (j, a, b) = self.ratio();
+            format!("{}:{}:{}", j, a, b)
+        } else {
+            format!(
+                "Cb {}x{}, Cr {}x{}",
+                self.cb_horiz, self.cb_vert, self.cr_horiz, self.cr_vert
+            )
+        }
+    }
+
+    pub fn name(&self) -> String {
+        if self.has_shared_chroma_factors() {
+            format!(
+                "{} (Y {}x{}, C {}x{})",
+                self.ratio_name(),
+                self.luma_horiz,
+                self.luma_vert,
+                self.cb_horiz,
+                self.cb_vert
+            )
+        } else {
+            format!(
+                "Y {}x{}, {}",
+                self.luma_horiz,
+                self.luma_vert,
+                self.ratio_name()
+            )
+        }
+    }
+
+    pub fn mcu_width(&self) -> usize {
+        8 * self.luma_horiz as usize
+    }
+
+    pub fn mcu_height(&self) -> usize {
+        8 * self.luma_vert as usize
+    }
+
+    /// Indices of the blocks of the channel, `width` x `height` blocks large, in the order
+    /// the interleaved scan codes them
+    pub fn block_order(&self, plot_name: PlotName, width: usize, height: usize) -> Vec<usize> {
+        let (horiz_sampling, vert_sampling) = self.factors(plot_name);
+        encoder::luminance_block_order(
+            width,
+            height,
+            horiz_sampling as usize,
+            vert_sampling as usize,
+        )
+    }
+
+    /// Symbols of the luminance and of the chrominance of the quantized channels
+    pub fn count_symbols(
+        &self,
+        ys: &BlockMatrix,
+        cbs: &BlockMatrix,
+        crs: &BlockMatrix,
+    ) -> (SymbolFrequencies, SymbolFrequencies) {
+        let sampling = [
+            PlotName::YsQuant3d,
+            PlotName::CbsQuant3d,
+            PlotName::CrsQuant3d,
+        ]
+        .map(|plot_name| self.factors(plot_name))
+        .map(|(horiz, vert)| (horiz as usize, vert as usize));
+        encoder::count_symbols(ys, cbs, crs, sampling)
+    }
+}
+
+impl Default for SubsamplingPack {
+    fn default() -> Self {
+        SubsamplingPack::PRESETS[0]
+    }
 }
 
 pub enum State {
@@ -39,15 +310,56 @@ pub enum Msg {
     FileChooserDragStarted,
     FileChooserDragLeave,
     FileChooserPresetClicked(String),
-    ImageLoaded(image::RawImage),
+    ImageLoaded(image::RawImage, Vec<u8>),
     QualityUpdated(u8),
     ZoomUpdated(u32),
     PostZoomUpdated,
     PreviewCanvasClicked(i32, i32),
-    BlockChosen(i32, i32, i32, i32, bool),
-    SubsamplingRatioChanged(i8, i8, i8),
-    PostSubsamplingRatioChanged,
+    BlockChosen(i32, i32, i32, i32, Option<PlotName>),
+    SubsamplingChanged(SubsamplingPack),
+    PostSubsamplingChanged,
     DiffInfoDisplayChanged,
+    EncodedJpegDownloadClicked,
+    SourceCoefficientsToggled,
+    PostSourceCoefficientsToggled,
+    SourceScanChanged(usize),
+    EntropyChannelChanged(PlotName),
+    EntropySymbolChosen(usize),
+    FileSegmentChosen(usize, usize),
+    DctAlgorithmChanged(DctAlgorithm),
+    QualityScalingChanged(QualityScaling),
+    QuantizationPresetLoaded(QuantizationPreset),
+    QuantizationTableEdited(QuantizationTableName, usize, usize, u8),
+    RdCurveAdded,
+    CoefficientsKeptChanged(usize),
+    CoefficientEdited(PlotName, usize, usize, i16),
+    CoefficientEditsReset,
+    McuChosen(usize, usize),
+    McuAnimationToggled,
+    McuAnimationTicked,
+    ColorTransformChanged(image::ColorTransform),
+    DownsamplingFilterChanged(image::DownsamplingFilter),
+    UpsamplingFilterChanged(image::UpsamplingFilter),
+    ChromaSitingChanged(image::ChromaSiting),
+    DeblockingFilterChanged(Option<image::DeblockingFilter>),
+    GridShiftChanged(Option<(usize, usize)>),
+    OptimizeCodingToggled,
+    RdCurvesCleared,
+    GenerationCountChanged(usize),
+    GenerationGridShiftToggled,
+    GenerationAlternateQualityChanged(Option<u8>),
+    GenerationLossRun,
+    QuantizationStrategyChanged(QuantizationStrategy),
+    TrellisLambdaChanged(u32),
+    QuantizationStrategiesCompared,
+    HeatmapChanged(Option<Heatmap>),
+    LosslessPredictorChanged(Predictor),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum QuantizationTableName {
+    Luminance,
+    Chrominance,
 }
 
 // ------ ------
@@ -66,18 +378,24 @@ pub enum CanvasName {
     CbsRecovered,
     CrsRecovered,
     ImageRecovered,
+    ImageDeblocked,
     Difference,
+    GridAligned,
+    GridShifted,
+    GridErrorDifference,
+    LosslessResiduals,
 }
-pub fn is_canvas_subsampled(canvas_name: &CanvasName) -> bool {
-    return match canvas_name {
-        CanvasName::Cbs
-        | CanvasName::Crs
-        | CanvasName::CbsQuant
-        | CanvasName::CrsQuant
-        | CanvasName::CbsRecovered
-        | CanvasName::CrsRecovered => true,
-        _ => false,
-    };
+/// Chroma channel shown subsampled on the canvas
+pub fn subsampled_channel(canvas_name: &CanvasName) -> Option<PlotName> {
+    match canvas_name {
+        CanvasName::Cbs | CanvasName::CbsQuant | CanvasName::CbsRecovered => {
+            Some(PlotName::CbsQuant3d)
+        }
+        CanvasName::Crs | CanvasName::CrsQuant | CanvasName::CrsRecovered => {
+            Some(PlotName::CrsQuant3d)
+        }
+        _ => None,
+    }
 }
 
 #[derive(PartialEq, Eq, Hash, EnumIter, Clone, Copy)]
@@ -87,6 +405,27 @@ pub enum PlotName {
     CrsQuant3d,
 }
 
+#[derive(Debug, PartialEq, Eq, Hash, EnumIter, Clone, Copy)]
+pub enum FullImageCanvasName {
+    Recovered,
+    Difference,
+}
+
+/// Chosen Y block next to the same block rebuilt from the first coefficients in the zig-zag order
+#[derive(Debug, PartialEq, Eq, Hash, EnumIter, Clone, Copy)]
+pub enum ReconstructionCanvasName {
+    Original,
+    Partial,
+    Error,
+}
+
+/// Heatmaps laid over the original and the recovered window
+#[derive(Debug, PartialEq, Eq, Hash, EnumIter, Clone, Copy)]
+pub enum HeatmapCanvasName {
+    Original,
+    Recovered,
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, EnumIter, Clone, Copy)]
 pub enum PreviewCanvasName {
     Original,
@@ -110,6 +449,16 @@ pub struct Model {
     pub preview_canvas_map: HashMap<PreviewCanvasName, ElRef<HtmlCanvasElement>>,
     pub plot_map: HashMap<PlotName, ElRef<HtmlCanvasElement>>,
     pub chosen_block_plot_map: HashMap<PlotName, ElRef<HtmlCanvasElement>>,
+    // canvases sized to the whole image, they have no overlays
+    pub full_image_canvas_map: HashMap<FullImageCanvasName, ElRef<HtmlCanvasElement>>,
+    pub rd_chart_canvas: ElRef<HtmlCanvasElement>,
+    pub generation_chart_canvas: ElRef<HtmlCanvasElement>,
+    // one for every generation in generation_loss.thumbnails, at most GENERATION_THUMBNAILS
+    pub generation_thumbnail_canvases: Vec<ElRef<HtmlCanvasElement>>,
+    // all 64 DCT basis functions in one grid
+    pub basis_canvas: ElRef<HtmlCanvasElement>,
+    pub reconstruction_canvas_map: HashMap<ReconstructionCanvasName, ElRef<HtmlCanvasElement>>,
+    pub heatmap_canvas_map: HashMap<HeatmapCanvasName, ElRef<HtmlCanvasElement>>,
 
     pub original_image_overlay: ElRef<HtmlImageElement>,
     // overlay_map and preview_overlay_map could be one but lack of inheritance makes it at least difficult
@@ -119,8 +468,54 @@ pub struct Model {
     pub quality: u8,
     pub zoom: u32,
     pub is_diff_info_shown: bool,
+    // channel whose chosen block is broken down into entropy coded symbols
+    pub entropy_channel: PlotName,
     pub subsampling_pack: SubsamplingPack,
+    // how the chroma planes are filtered before the subsampling
+    pub downsampling_filter: image::DownsamplingFilter,
+    // how the recovered chroma is interpolated and where its samples lie
+    pub upsampling_filter: image::UpsamplingFilter,
+    pub chroma_siting: image::ChromaSiting,
+    // post-filter of the recovered image, the unfiltered one is shown as well
+    pub deblocking_filter: Option<image::DeblockingFilter>,
+    // offset of the block grid, 0 to 7 pixels right and down, the source is recompressed with it
+    // and with the aligned grid, None when the experiment is off
+    pub grid_shift: Option<(usize, usize)>,
+    // encode the file with Huffman tables built for its symbols
+    pub optimize_coding: bool,
+    // show the coefficients stored in the uploaded file instead of simulating the compression
+    pub use_source_coefficients: bool,
+    // transform used for the compression, its error against the reference one is shown for the chosen block
+    pub dct_algorithm: DctAlgorithm,
+    // colour space of the compressed planes, the file coefficients are always in the JFIF one
+    pub color_transform: image::ColorTransform,
 
+    pub quality_scaling: QualityScaling,
+    // luminance and chrominance tables typed into the editor or loaded from a preset,
+    // they are used instead of the scaled ones until the quality changes
+    pub custom_quant_tables: Option<QuantizationTables>,
     pub scaled_luminance_quant_table: [[u8; 8]; 8],
     pub scaled_chrominance_quant_table: [[u8; 8]; 8],
+    // quality sweeps of the loaded image, one for every subsampling ratio the user added
+    pub rd_curves: Vec<RdCurve>,
+    // repeated recompression of the loaded image, run on demand
+    pub generation_count: usize,
+    pub generation_grid_shift: bool,
+    // quality of every even generation, the odd ones use the current tables
+    pub generation_alternate_quality: Option<u8>,
+    pub generation_loss: Option<GenerationLoss>,
+    // how the coefficients are rounded, the trellis weighs a bit against lambda of squared error
+    pub quantization_strategy: QuantizationStrategy,
+    pub trellis_lambda: u32,
+    // every strategy applied to the loaded image with the current tables, run on demand
+    pub strategy_results: Vec<StrategyResult>,
+    // number of zig-zag coefficients the chosen block is rebuilt from in the basis explorer
+    pub coefficients_kept: usize,
+    // block of the window MCUs emitted last, the animation steps through them while its stream runs
+    pub mcu_animation_step: Option<usize>,
+    pub mcu_animation: Option<StreamHandle>,
+    // per-block value laid over the compared windows, hidden when None
+    pub heatmap: Option<Heatmap>,
+    // predictor of the residual map in the lossless section
+    pub lossless_predictor: Predictor,
 }
@@ -6,31 +6,50 @@ use strum::IntoEnumIterator;
 use plotters::prelude::*;
 use plotters_canvas::CanvasBackend;
 
+use super::generation_loss::{GenerationLoss, Recompression};
+use super::grid_shift::{self, GridShiftExperiment};
+use super::heatmap::{self, Heatmap};
+use super::lossless;
+use super::mcu;
 use super::model::*;
+use super::quantization_strategies;
+use super::rate_distortion::{self, RdCurve};
 use super::utils;
 use super::view::*;
-use crate::image::pixel::RGB;
-use crate::image::RawImageWindow;
-use crate::quant::scale_quantization_table;
-use crate::{
-    block::{self, BlockMatrix},
-    image, quant, Msg as GMsg, BLOCK_SIZE,
+use crate::codec::jpeg::constants::ZIG_ZAG;
+use crate::codec::jpeg::decoder::{self, DecodedJpeg};
+use crate::codec::jpeg::encoder::{self, HuffmanTables};
+use crate::codec::jpeg::entropy::SymbolFrequencies;
+use crate::codec::jpeg::huffman::HuffmanTable;
+use crate::codec::jpeg::lossless::Predictor;
+use crate::codec::jpeg::segments::{self, QuantizationTableDefinition};
+use crate::dct::{self, DctAlgorithm};
+use crate::image::{
+    ChromaSiting, ColorTransform, DownsamplingFilter, RawImageWindow, UpsamplingFilter,
 };
+use crate::metrics::{self, Metrics};
+use crate::quant::{QualityEstimate, QualityScaling, QuantizationStrategy, Quantizer};
+use crate::{block::BlockMatrix, image, quant, Msg as GMsg, BLOCK_SIZE};
 use std::rc::Rc;
 
 use super::drawing_utils::{
     draw_scaled_image_default, draw_scaled_image_with_image_data_with_w_h_and_scale,
+    draw_scaled_image_with_w_h_and_scale,
 };
 use super::utils::get_image_diff;
 use std::collections::HashMap;
 
 use crate::quant::{CHROMINANCE_QUANTIZATION_TABLE, LUMINANCE_QUANTIZATION_TABLE};
 use crate::section::jpeg_visualization::drawing_utils::clear_canvas;
-use crate::section::jpeg_visualization::utils::{
-    create_tmp_canvas, horiz_mult_from_subsampling, vert_mult_from_subsampling,
-};
+use crate::section::jpeg_visualization::utils::{create_tmp_canvas, subsampled_window_size};
 use web_sys::{Blob, HtmlCanvasElement, HtmlImageElement};
 
+// delay between two blocks of the MCU interleaving animation in milliseconds
+const MCU_ANIMATION_INTERVAL: u32 = 400;
+pub const GENERATION_THUMBNAILS: usize = 5;
+// width of the generation thumbnails, the height keeps the aspect ratio of the image
+pub const GENERATION_THUMBNAIL_WIDTH: u32 = 200;
+
 pub fn init(url: Url) -> Option<Model> {
     let base_url = url.to_base_url();
 
@@ -54,7 +73,21 @@ pub fn init(url: Url) -> Option<Model> {
         chosen_block_plot_map.insert(plot_name, ElRef::<HtmlCanvasElement>::default());
     }
 
-    let subsampling_pack = SubsamplingPack { j: 4, a: 4, b: 4 };
+    let mut reconstruction_canvas_map =
+        HashMap::<ReconstructionCanvasName, ElRef<HtmlCanvasElement>>::new();
+    for canvas_name in ReconstructionCanvasName::iter() {
+        reconstruction_canvas_map.insert(canvas_name, ElRef::<HtmlCanvasElement>::default());
+    }
+
+    let mut heatmap_canvas_map = HashMap::<HeatmapCanvasName, ElRef<HtmlCanvasElement>>::new();
+    for canvas_name in HeatmapCanvasName::iter() {
+        heatmap_canvas_map.insert(canvas_name, ElRef::<HtmlCanvasElement>::default());
+    }
+
+    let mut full_image_canvas_map = HashMap::<FullImageCanvasName, ElRef<HtmlCanvasElement>>::new();
+    for canvas_name in FullImageCanvasName::iter() {
+        full_image_canvas_map.insert(canvas_name, ElRef::<HtmlCanvasElement>::default());
+    }
 
     let quality = 50;
 
@@ -67,21 +100,51 @@ pub fn init(url: Url) -> Option<Model> {
         preview_canvas_map,
         plot_map,
         chosen_block_plot_map,
+        full_image_canvas_map,
+        rd_chart_canvas: ElRef::<HtmlCanvasElement>::default(),
+        generation_chart_canvas: ElRef::<HtmlCanvasElement>::default(),
+        generation_thumbnail_canvases: (0..GENERATION_THUMBNAILS)
+            .map(|_| ElRef::<HtmlCanvasElement>::default())
+            .collect(),
+        basis_canvas: ElRef::<HtmlCanvasElement>::default(),
+        reconstruction_canvas_map,
+        heatmap_canvas_map,
         original_image_overlay: ElRef::<HtmlImageElement>::default(),
         overlay_map,
         preview_overlay_map,
         quality,
         zoom: 7,
         is_diff_info_shown: false,
-        subsampling_pack,
-        scaled_luminance_quant_table: scale_quantization_table(
-            &LUMINANCE_QUANTIZATION_TABLE,
-            quality,
-        ),
-        scaled_chrominance_quant_table: scale_quantization_table(
-            &CHROMINANCE_QUANTIZATION_TABLE,
-            quality,
-        ),
+        entropy_channel: PlotName::YsQuant3d,
+        subsampling_pack: SubsamplingPack::default(),
+        downsampling_filter: DownsamplingFilter::Point,
+        upsampling_filter: UpsamplingFilter::Nearest,
+        chroma_siting: ChromaSiting::Centered,
+        deblocking_filter: None,
+        grid_shift: None,
+        optimize_coding: false,
+        use_source_coefficients: false,
+        dct_algorithm: DctAlgorithm::Reference,
+        color_transform: ColorTransform::Bt601Full,
+        quality_scaling: QualityScaling::Ijg,
+        custom_quant_tables: None,
+        scaled_luminance_quant_table: QualityScaling::Ijg
+            .scale(&LUMINANCE_QUANTIZATION_TABLE, quality),
+        scaled_chrominance_quant_table: QualityScaling::Ijg
+            .scale(&CHROMINANCE_QUANTIZATION_TABLE, quality),
+        rd_curves: Vec::new(),
+        generation_count: 20,
+        generation_grid_shift: false,
+        generation_alternate_quality: None,
+        generation_loss: None,
+        quantization_strategy: QuantizationStrategy::Truncate,
+        trellis_lambda: 50,
+        strategy_results: Vec::new(),
+        coefficients_kept: 10,
+        mcu_animation_step: None,
+        mcu_animation: None,
+        heatmap: None,
+        lossless_predictor: Predictor::Gradient,
     })
 }
 
@@ -143,9 +206,6 @@ fn draw_block_choice_indicators(
     subsampling_pack: &SubsamplingPack,
     zoom: u32,
 ) {
-    let horiz_mult: usize = horiz_mult_from_subsampling(&subsampling_pack);
-    let vert_mult: usize = vert_mult_from_subsampling(&subsampling_pack);
-
     let tmp_canvas = create_tmp_canvas();
     tmp_canvas.set_width(BLOCK_SIZE * zoom);
     tmp_canvas.set_height(BLOCK_SIZE * zoom);
@@ -156,28 +216,31 @@ fn draw_block_choice_indicators(
     // We need to calculate offset of line_width so that all pixels of the image window are inside stroked rect (as in not covered by the lines)
     let line_width: f64 = 2.6 * zoom as f64 / 8.0;
     tmp_ctx.set_line_width(line_width);
-    let actual_start_x = (start_x - (start_x as usize % (8 * horiz_mult)) as f64) * zoom as f64;
-    let actual_start_y = (start_y - (start_y as usize % (8 * vert_mult)) as f64) * zoom as f64;
+    // the whole MCU around the chosen block is outlined
+    let mcu_width = subsampling_pack.mcu_width();
+    let mcu_height = subsampling_pack.mcu_height();
+    let actual_start_x = (start_x - (start_x as usize % mcu_width) as f64) * zoom as f64;
+    let actual_start_y = (start_y - (start_y as usize % mcu_height) as f64) * zoom as f64;
     tmp_ctx.stroke_rect(
         actual_start_x - line_width / 2.0,
         actual_start_y - line_width / 2.0,
-        8.0 * zoom as f64 * horiz_mult as f64 + line_width / 2.0,
-        8.0 * zoom as f64 * vert_mult as f64 + line_width / 2.0,
+        zoom as f64 * mcu_width as f64 + line_width / 2.0,
+        zoom as f64 * mcu_height as f64 + line_width / 2.0,
     );
     tmp_ctx.set_line_width(zoom as f64 / 8.0);
-    for i in 1..horiz_mult {
+    for i in 1..subsampling_pack.luma_horiz {
         let line_start_x = actual_start_x + 8.0 * i as f64 * zoom as f64;
         tmp_ctx.move_to(line_start_x, actual_start_y);
         tmp_ctx.line_to(
             line_start_x,
-            actual_start_y + 8.0 * zoom as f64 * vert_mult as f64,
+            actual_start_y + zoom as f64 * mcu_height as f64,
         );
     }
-    for i in 1..vert_mult {
+    for i in 1..subsampling_pack.luma_vert {
         let line_start_y = actual_start_y + 8.0 * i as f64 * zoom as f64;
         tmp_ctx.move_to(actual_start_x, line_start_y);
         tmp_ctx.line_to(
-            actual_start_x + 8.0 * zoom as f64 * horiz_mult as f64,
+            actual_start_x + zoom as f64 * mcu_width as f64,
             line_start_y,
         );
     }
@@ -189,7 +252,7 @@ fn draw_block_choice_indicators(
         let url = web_sys::Url::create_object_url_with_blob(&blob).unwrap();
 
         for (canvas_name, overlay_image) in overlay_map_cloned {
-            if !is_canvas_subsampled(&canvas_name) {
+            if subsampled_channel(&canvas_name).is_none() {
                 overlay_image.get().unwrap().set_src(&url);
             }
         }
@@ -206,186 +269,700 @@ fn draw_block_choice_indicators(
         )
         .unwrap();
 
-    let tmp_canvas_for_subsampling = create_tmp_canvas();
-
-    tmp_canvas_for_subsampling.set_width(BLOCK_SIZE * zoom / horiz_mult as u32);
-    tmp_canvas_for_subsampling.set_height(BLOCK_SIZE * zoom / vert_mult as u32);
+    // the Cb and the Cr canvases can be subsampled differently, each gets its own overlay
+    for plot_name in [PlotName::CbsQuant3d, PlotName::CrsQuant3d] {
+        let (horiz_mult, vert_mult) = subsampling_pack.mults(plot_name);
+        let tmp_canvas_for_subsampling = create_tmp_canvas();
+
+        tmp_canvas_for_subsampling.set_width(subsampled_window_size(horiz_mult) as u32 * zoom);
+        tmp_canvas_for_subsampling.set_height(subsampled_window_size(vert_mult) as u32 * zoom);
+
+        let tmp_ctx_for_subsampling = canvas_context_2d(&tmp_canvas_for_subsampling);
+
+        tmp_ctx_for_subsampling.begin_path();
+        tmp_ctx_for_subsampling.set_line_width(line_width);
+        tmp_ctx_for_subsampling.stroke_rect(
+            (start_x - (start_x as u32 % (8 * horiz_mult as u32)) as f64) * zoom as f64
+                / horiz_mult as f64
+                - line_width / 2.0,
+            (start_y - (start_y as u32 % (8 * vert_mult as u32)) as f64) * zoom as f64
+                / vert_mult as f64
+                - line_width / 2.0,
+            8.0 * zoom as f64 + line_width / 2.0,
+            8.0 * zoom as f64 + line_width / 2.0,
+        );
+        let overlay_map_cloned_for_subsampling = overlay_map.clone();
+        let f_subsampled = Closure::once_into_js(move |blob: &Blob| {
+            let url = web_sys::Url::create_object_url_with_blob(blob).unwrap();
+
+            for (canvas_name, overlay_image) in overlay_map_cloned_for_subsampling {
+                if subsampled_channel(&canvas_name) == Some(plot_name) {
+                    overlay_image.get().unwrap().set_src(&url);
+                }
+            }
+            // TODO: Consider checking if all images has loaded and after all are loaded revoke no longer needed url
+        });
+        tmp_canvas_for_subsampling
+            .to_blob(f_subsampled.as_ref().unchecked_ref::<js_sys::Function>())
+            .unwrap();
+    }
+}
 
-    let tmp_ctx_for_subsampling = canvas_context_2d(&tmp_canvas_for_subsampling);
+/// Converts values of one channel to RGBA data, the other two channels are set by `to_ycbcr`
+fn channel_image(
+    values: &[u8],
+    color_transform: ColorTransform,
+    to_ycbcr: impl Fn(u8) -> image::pixel::YCbCr,
+) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|x| color_transform.to_rgb(&to_ycbcr(*x)).to_flat_data())
+        .collect::<Vec<u8>>()
+}
 
-    tmp_ctx_for_subsampling.begin_path();
-    tmp_ctx_for_subsampling.set_line_width(line_width);
-    tmp_ctx_for_subsampling.stroke_rect(
-        (start_x - (start_x as u32 % (8 * horiz_mult as u32)) as f64) * zoom as f64
-            / horiz_mult as f64
-            - line_width / 2.0,
-        (start_y - (start_y as u32 % (8 * vert_mult as u32)) as f64) * zoom as f64
-            / vert_mult as f64
-            - line_width / 2.0,
-        8.0 * zoom as f64 + line_width / 2.0,
-        8.0 * zoom as f64 + line_width / 2.0,
-    );
-    let overlay_map_cloned_for_subsampling = overlay_map.clone();
-    let f_subsampled = Closure::once_into_js(move |blob: &Blob| {
-        let url = web_sys::Url::create_object_url_with_blob(&blob).unwrap();
+/// Cuts the part under the image window out of the planes, the window has to be aligned to the MCU grid
+fn window_planes(
+    planes: &image::YCbCrPlanes,
+    image_window: &RawImageWindow,
+    subsampling_pack: &SubsamplingPack,
+) -> image::YCbCrPlanes {
+    let start_x = image_window.start_x as usize;
+    let start_y = image_window.start_y as usize;
+    let size = BLOCK_SIZE as usize;
+
+    let crop_chroma = |plane: &image::Plane, plot_name: PlotName| {
+        let (horiz_mult, vert_mult) = subsampling_pack.mults(plot_name);
+        let width = subsampled_window_size(horiz_mult);
+        let height = subsampled_window_size(vert_mult);
+        // the window rounded up to whole blocks can reach past the padded plane
+        plane.crop(
+            cmp::min(start_x / horiz_mult, plane.width - width),
+            cmp::min(start_y / vert_mult, plane.height - height),
+            width,
+            height,
+        )
+    };
+    image::YCbCrPlanes {
+        ys: planes.ys.crop(start_x, start_y, size, size),
+        cbs: crop_chroma(&planes.cbs, PlotName::CbsQuant3d),
+        crs: crop_chroma(&planes.crs, PlotName::CrsQuant3d),
+        color_transform: planes.color_transform,
+    }
+}
 
-        for (canvas_name, overlay_image) in overlay_map_cloned_for_subsampling {
-            if is_canvas_subsampled(&canvas_name) {
-                overlay_image.get().unwrap().set_src(&url);
-            }
-        }
-        // TODO: Consider checking if all images has loaded and after all are loaded revoke no longer needed url
-    });
-    tmp_canvas_for_subsampling
-        .to_blob(f_subsampled.as_ref().unchecked_ref::<js_sys::Function>())
-        .unwrap();
+/// Compares the recovered image window with the original one, the recovered chroma is upsampled
+/// so the metrics include the loss caused by the subsampling
+fn window_metrics(
+    pack: &ImagePack,
+    upsampled: &image::YCbCrPlanes,
+) -> Vec<(&'static str, Metrics)> {
+    let size = BLOCK_SIZE as usize;
+    let plane = |data: Vec<u8>| image::Plane::new(data, size, size);
+    let rgb_planes = |rgba: &[u8]| {
+        [0, 1, 2].map(|channel| plane(rgba.iter().skip(channel).step_by(4).copied().collect()))
+    };
+
+    let original = pack
+        .image_window
+        .to_rgb_image()
+        .to_ycbcr_image(pack.planes.color_transform);
+    let recovered = window_upsampled_planes(upsampled, &pack.image_window);
+
+    let original_rgb = rgb_planes(&pack.image_window.to_image());
+    let recovered_rgb = rgb_planes(&recovered.to_rgba(1, 1, size, size));
+    let rgb = original_rgb
+        .iter()
+        .zip(recovered_rgb.iter())
+        .map(|(original, recovered)| Metrics::compare(original, recovered))
+        .collect::<Vec<Metrics>>();
+
+    vec![
+        (
+            "Y",
+            Metrics::compare(&plane(original.to_ys_channel()), &recovered.ys),
+        ),
+        (
+            "Cb",
+            Metrics::compare(&plane(original.to_cbs_channel()), &recovered.cbs),
+        ),
+        (
+            "Cr",
+            Metrics::compare(&plane(original.to_crs_channel()), &recovered.crs),
+        ),
+        ("RGB", Metrics::combine(&rgb)),
+    ]
 }
 
 fn draw_ycbcr(
     canvas_map: &HashMap<CanvasName, ElRef<HtmlCanvasElement>>,
-    ycbcr: &image::YCbCrImage,
+    pack: &ImagePack,
     subsampling_pack: &SubsamplingPack,
     zoom: u32,
 ) {
-    let ys = ycbcr.to_ys_channel();
-    let cbs = ycbcr.to_cbs_channel();
-    let crs = ycbcr.to_crs_channel();
+    let planes = window_planes(&pack.planes, &pack.image_window, subsampling_pack);
 
-    let ys_image = ys
-        .iter()
-        .flat_map(|x| {
-            let RGB { r, g, b } = image::pixel::YCbCr {
-                y: *x,
-                cb: 128,
-                cr: 128,
-            }
-            .to_rgb();
-            vec![r, g, b, 255]
-        })
-        .collect::<Vec<u8>>();
-
-    let horiz_mult: usize = horiz_mult_from_subsampling(&subsampling_pack);
-    let vert_mult: usize = vert_mult_from_subsampling(&subsampling_pack);
-
-    assert_eq!(cbs.len(), crs.len());
-    let mut cbs_image = Vec::<u8>::new();
-    let mut crs_image = Vec::<u8>::new();
-    let mut x = 0;
-    while x < cbs.len() {
-        // force skipping horizontal rows if horiz_mult is more than 1
-        if x % (BLOCK_SIZE as usize * horiz_mult) >= BLOCK_SIZE as usize {
-            x += BLOCK_SIZE as usize * (horiz_mult - 1);
-            continue;
-        }
-        let mut cbs_avg: usize = 0;
-        let mut crs_avg: usize = 0;
-        for i in 0..vert_mult {
-            for j in 0..horiz_mult {
-                let curr = x + i + j * BLOCK_SIZE as usize;
-                cbs_avg += cbs[curr] as usize;
-                crs_avg += crs[curr] as usize;
-            }
+    let ys_image = channel_image(&planes.ys.data, planes.color_transform, |y| {
+        image::pixel::YCbCr {
+            y,
+            cb: 128,
+            cr: 128,
         }
-        cbs_avg /= vert_mult * horiz_mult;
-        crs_avg /= vert_mult * horiz_mult;
-
-        let RGB { r, g, b } = image::pixel::YCbCr {
+    });
+    let cbs_image = channel_image(&planes.cbs.data, planes.color_transform, |cb| {
+        image::pixel::YCbCr {
             y: 128,
-            cb: cbs_avg as u8,
+            cb,
             cr: 128,
         }
-        .to_rgb();
-
-        cbs_image.push(r);
-        cbs_image.push(g);
-        cbs_image.push(b);
-        cbs_image.push(255);
-
-        let RGB { r, g, b } = image::pixel::YCbCr {
+    });
+    let crs_image = channel_image(&planes.crs.data, planes.color_transform, |cr| {
+        image::pixel::YCbCr {
             y: 128,
             cb: 128,
-            cr: crs_avg as u8,
+            cr,
         }
-        .to_rgb();
-        crs_image.push(r);
-        crs_image.push(g);
-        crs_image.push(b);
-        crs_image.push(255);
-
-        x += vert_mult;
-    }
+    });
 
     draw_default(&canvas_map, CanvasName::Ys, ys_image, zoom);
     draw_default(&canvas_map, CanvasName::Cbs, cbs_image, zoom);
     draw_default(&canvas_map, CanvasName::Crs, crs_image, zoom);
 }
 
-fn draw_dct_quantized(
-    canvas_map: &HashMap<CanvasName, ElRef<HtmlCanvasElement>>,
-    pack: &mut ImagePack,
+/// Bits of the symbols counted with `encoder::count_symbols` coded with the Annex K tables
+/// and with the tables built for them, Cb and Cr share the chrominance tables like they do in the file
+fn compare_huffman_tables(
+    standard: &HuffmanTables,
+    optimal: &HuffmanTables,
+    luminance: &SymbolFrequencies,
+    chrominance: &SymbolFrequencies,
+) -> Vec<HuffmanComparison> {
+    let comparison =
+        |name, standard: &HuffmanTable, optimal: &HuffmanTable, frequencies| HuffmanComparison {
+            name,
+            standard_bits: standard.coded_bits(frequencies),
+            optimal_bits: optimal.coded_bits(frequencies),
+            standard: standard.clone(),
+            optimal: optimal.clone(),
+        };
+    vec![
+        comparison(
+            "Luminance DC",
+            &standard.luminance_dc,
+            &optimal.luminance_dc,
+            &luminance.dc,
+        ),
+        comparison(
+            "Luminance AC",
+            &standard.luminance_ac,
+            &optimal.luminance_ac,
+            &luminance.ac,
+        ),
+        comparison(
+            "Chrominance DC",
+            &standard.chrominance_dc,
+            &optimal.chrominance_dc,
+            &chrominance.dc,
+        ),
+        comparison(
+            "Chrominance AC",
+            &standard.chrominance_ac,
+            &optimal.chrominance_ac,
+            &chrominance.ac,
+        ),
+    ]
+}
+
+/// Tables the first two frame components are quantized with, the luminance and the chrominance,
+/// a grayscale file has only the luminance one
+fn source_quant_tables<'a>(
+    tables: &'a [QuantizationTableDefinition],
+    component_table_ids: &[u8],
+) -> Vec<&'a QuantizationTableDefinition> {
+    component_table_ids
+        .iter()
+        .take(2)
+        .map_while(|id| tables.iter().find(|table| table.id == *id))
+        .collect()
+}
+
+/// IJG quality of the luminance and the chrominance tables of the file
+fn estimate_source_quality(
+    tables: &[&QuantizationTableDefinition],
+) -> Vec<(&'static str, QualityEstimate)> {
+    [
+        ("Luminance", &LUMINANCE_QUANTIZATION_TABLE),
+        ("Chrominance", &CHROMINANCE_QUANTIZATION_TABLE),
+    ]
+    .iter()
+    .zip(tables.iter())
+    .map(|((name, base_table), table)| {
+        (
+            *name,
+            quant::estimate_ijg_quality(&table.values, base_table),
+        )
+    })
+    .collect()
+}
+
+/// Table of the file limited to the baseline range of the simulation
+fn baseline_quant_table(table: &QuantizationTableDefinition) -> [[u8; 8]; 8] {
+    let mut baseline = [[0; 8]; 8];
+    for (baseline_row, row) in baseline.iter_mut().zip(table.values.iter()) {
+        for (baseline_value, value) in baseline_row.iter_mut().zip(row.iter()) {
+            *baseline_value = (*value).clamp(1, 255) as u8;
+        }
+    }
+    baseline
+}
+
+/// Converts the image to every colour space and back without compressing it
+fn color_round_trips(raw_image: &image::RawImage) -> Vec<(ColorTransform, ColorRoundTrip)> {
+    let pixels = raw_image.to_rgb_image().0;
+    ColorTransform::iter()
+        .map(|color_transform| {
+            let mut max_error = 0;
+            let mut changed_pixels = 0;
+            let mut squared_error = 0;
+            for rgb in pixels.iter() {
+                let recovered = color_transform.to_rgb(&color_transform.to_ycbcr(rgb));
+                let errors = [
+                    rgb.r.abs_diff(recovered.r),
+                    rgb.g.abs_diff(recovered.g),
+                    rgb.b.abs_diff(recovered.b),
+                ];
+                max_error = errors
+                    .iter()
+                    .fold(max_error, |max, error| cmp::max(max, *error));
+                changed_pixels += errors.iter().any(|error| *error > 0) as usize;
+                squared_error += errors
+                    .iter()
+                    .map(|error| (*error as u64).pow(2))
+                    .sum::<u64>();
+            }
+            let round_trip = ColorRoundTrip {
+                max_error,
+                changed_pixels: 100.0 * changed_pixels as f64 / pixels.len() as f64,
+                psnr: metrics::psnr(squared_error as f64 / (3 * pixels.len()) as f64),
+            };
+            (color_transform, round_trip)
+        })
+        .collect()
+}
+
+/// Pads the image to complete MCUs and subsamples the chroma planes
+fn prepare_planes(
+    raw_image: &image::RawImage,
     subsampling_pack: &SubsamplingPack,
-    quality: u8,
-    zoom: u32,
-) {
-    let ycbcr = &pack.ycbcr;
-    let image_window = &pack.image_window;
-
-    let ys = ycbcr.to_ys_channel();
-    let cbs = ycbcr
-        .to_cbs_channel()
-        .into_iter()
-        .enumerate()
-        .filter(|(index, _x)| subsampling_pack.b != 0 || (*index as u32 / BLOCK_SIZE) % 2 == 0)
-        .map(|(_index, x)| x)
-        .step_by((subsampling_pack.j / subsampling_pack.a) as usize)
-        .collect::<Vec<u8>>();
-    let crs = ycbcr
-        .to_crs_channel()
-        .into_iter()
-        .enumerate()
-        .filter(|(index, _x)| subsampling_pack.b != 0 || (*index as u32 / BLOCK_SIZE) % 2 == 0)
-        .map(|(_index, x)| x)
-        .step_by((subsampling_pack.j / subsampling_pack.a) as usize)
-        .collect::<Vec<u8>>();
-
-    let scaled_luminance_quant_table =
-        quant::scale_quantization_table(&quant::LUMINANCE_QUANTIZATION_TABLE, quality);
-    let scaled_chrominance_quant_table =
-        quant::scale_quantization_table(&quant::CHROMINANCE_QUANTIZATION_TABLE, quality);
-
-    let height_width_ratio = (subsampling_pack.j / subsampling_pack.a) as f64
-        / if subsampling_pack.b == 0 {
-            2_f64
+    downsampling_filter: DownsamplingFilter,
+    color_transform: ColorTransform,
+) -> image::YCbCrPlanes {
+    let mcu_width = subsampling_pack.mcu_width();
+    let mcu_height = subsampling_pack.mcu_height();
+    let width = (raw_image.width() as usize).div_ceil(mcu_width) * mcu_width;
+    let height = (raw_image.height() as usize).div_ceil(mcu_height) * mcu_height;
+
+    let planes = raw_image.to_ycbcr_planes(color_transform);
+    let downsample = |plane: &image::Plane, plot_name: PlotName| {
+        let (horiz_mult, vert_mult) = subsampling_pack.mults(plot_name);
+        plane
+            .pad(width, height)
+            .downsample(horiz_mult, vert_mult, downsampling_filter)
+    };
+    image::YCbCrPlanes {
+        ys: planes.ys.pad(width, height),
+        cbs: downsample(&planes.cbs, PlotName::CbsQuant3d),
+        crs: downsample(&planes.crs, PlotName::CrsQuant3d),
+        color_transform,
+    }
+}
+
+/// Runs the whole image through the compression, the sections below show the part under the image window
+fn compress_image(model: &mut Model) {
+    if let State::ImageView(ref mut pack) = model.state {
+        let subsampling_pack = &model.subsampling_pack;
+        let luminance_quant_table = &model.scaled_luminance_quant_table;
+        let chrominance_quant_table = &model.scaled_chrominance_quant_table;
+        let transform = model.dct_algorithm.transform();
+
+        // the coefficients of a JFIF file are always in the full range BT.601
+        let color_transform = if pack.source_jpeg.is_some() && model.use_source_coefficients {
+            ColorTransform::Bt601Full
+        } else {
+            model.color_transform
+        };
+
+        pack.planes = prepare_planes(
+            &pack.raw_image,
+            subsampling_pack,
+            model.downsampling_filter,
+            color_transform,
+        );
+        let quantizers = (
+            Quantizer::new(
+                model.quantization_strategy,
+                model.trellis_lambda as f64,
+                &HuffmanTable::luminance_ac(),
+            ),
+            Quantizer::new(
+                model.quantization_strategy,
+                model.trellis_lambda as f64,
+                &HuffmanTable::chrominance_ac(),
+            ),
+        );
+        let (mut ys_quantized, mut cbs_quantized, mut crs_quantized) = match &pack.source_jpeg {
+            // the file is padded to complete MCUs the same way, so its blocks line up with the planes
+            Some(source_jpeg) if model.use_source_coefficients => (
+                source_jpeg.components[0].coefficients.clone(),
+                source_jpeg.components[1].coefficients.clone(),
+                source_jpeg.components[2].coefficients.clone(),
+            ),
+            _ => (
+                BlockMatrix::from_plane(&pack.planes.ys).quantize(
+                    luminance_quant_table,
+                    transform,
+                    &quantizers.0,
+                ),
+                BlockMatrix::from_plane(&pack.planes.cbs).quantize(
+                    chrominance_quant_table,
+                    transform,
+                    &quantizers.1,
+                ),
+                BlockMatrix::from_plane(&pack.planes.crs).quantize(
+                    chrominance_quant_table,
+                    transform,
+                    &quantizers.1,
+                ),
+            ),
+        };
+
+        for ((plot_name, block_index, coefficient_index), value) in pack.coefficient_edits.iter() {
+            let (quantized, quant_table) = match plot_name {
+                PlotName::YsQuant3d => (&mut ys_quantized, luminance_quant_table),
+                PlotName::CbsQuant3d => (&mut cbs_quantized, chrominance_quant_table),
+                PlotName::CrsQuant3d => (&mut crs_quantized, chrominance_quant_table),
+            };
+            let (row, col) = (coefficient_index / 8, coefficient_index % 8);
+            // the edits are kept when the tables change, so a coarser step may need a lower limit
+            let limit =
+                quant::editable_coefficient_limit(*coefficient_index, quant_table[row][col]);
+            quantized.blocks[*block_index].0[row][col] = (*value).clamp(-limit, limit);
+        }
+
+        let (luminance, chrominance) =
+            subsampling_pack.count_symbols(&ys_quantized, &cbs_quantized, &crs_quantized);
+        let standard = HuffmanTables::standard();
+        let optimal = HuffmanTables::optimal(&luminance, &chrominance);
+        pack.huffman_comparisons =
+            compare_huffman_tables(&standard, &optimal, &luminance, &chrominance);
+        pack.amplitude_bits = luminance.amplitude_bits + chrominance.amplitude_bits;
+        pack.huffman_tables = if model.optimize_coding {
+            optimal
         } else {
-            1_f64
+            standard
         };
 
-    let ys_block_matrix = block::split_to_block_matrix(&ys, 1_f64);
-    let cbs_block_matrix = block::split_to_block_matrix(&cbs, height_width_ratio);
-    let crs_block_matrix = block::split_to_block_matrix(&crs, height_width_ratio);
+        pack.encoded_jpeg = encoder::encode(&encoder::EncoderInput {
+            width: pack.raw_image.width() as u16,
+            height: pack.raw_image.height() as u16,
+            ys: &ys_quantized,
+            cbs: &cbs_quantized,
+            crs: &crs_quantized,
+            horiz_sampling: subsampling_pack.luma_horiz,
+            vert_sampling: subsampling_pack.luma_vert,
+            cb_horiz_sampling: subsampling_pack.cb_horiz,
+            cb_vert_sampling: subsampling_pack.cb_vert,
+            cr_horiz_sampling: subsampling_pack.cr_horiz,
+            cr_vert_sampling: subsampling_pack.cr_vert,
+            luminance_quant_table,
+            chrominance_quant_table,
+            huffman_tables: &pack.huffman_tables,
+        });
+
+        pack.recovered = image::YCbCrPlanes {
+            ys: ys_quantized
+                .undo_quantization(luminance_quant_table, transform)
+                .to_plane(),
+            cbs: cbs_quantized
+                .undo_quantization(chrominance_quant_table, transform)
+                .to_plane(),
+            crs: crs_quantized
+                .undo_quantization(chrominance_quant_table, transform)
+                .to_plane(),
+            color_transform,
+        };
+        let (width, height) = (pack.recovered.ys.width, pack.recovered.ys.height);
+        let (upsampling_filter, chroma_siting) = (model.upsampling_filter, model.chroma_siting);
+        let upsample = |plane: &image::Plane, plot_name: PlotName| {
+            let (horiz_mult, vert_mult) = subsampling_pack.mults(plot_name);
+            plane.upsample_filtered(
+                horiz_mult,
+                vert_mult,
+                width,
+                height,
+                upsampling_filter,
+                chroma_siting,
+            )
+        };
+        let upsampled = image::YCbCrPlanes {
+            ys: pack.recovered.ys.clone(),
+            cbs: upsample(&pack.recovered.cbs, PlotName::CbsQuant3d),
+            crs: upsample(&pack.recovered.crs, PlotName::CrsQuant3d),
+            color_transform,
+        };
+        // the chroma is filtered before the interpolation, where its block edges are still 8 samples apart
+        let deblocked = model.deblocking_filter.map(|filter| {
+            let luminance_step = luminance_quant_table[0][0];
+            let chrominance_step = chrominance_quant_table[0][0];
+            image::YCbCrPlanes {
+                ys: pack.recovered.ys.deblock(filter, luminance_step),
+                cbs: upsample(
+                    &pack.recovered.cbs.deblock(filter, chrominance_step),
+                    PlotName::CbsQuant3d,
+                ),
+                crs: upsample(
+                    &pack.recovered.crs.deblock(filter, chrominance_step),
+                    PlotName::CrsQuant3d,
+                ),
+                color_transform,
+            }
+        });
+        pack.upsampled = upsampled;
+        pack.deblocked = deblocked;
+
+        let downsampling_filter = model.downsampling_filter;
+        pack.grid_shift_experiment = model.grid_shift.map(|offset| {
+            let quant_tables = (*luminance_quant_table, *chrominance_quant_table);
+            let recompression = Recompression {
+                subsampling_pack,
+                color_transform,
+                downsampling_filter,
+                upsampling_filter,
+                chroma_siting,
+                transform,
+                quantizers: &quantizers,
+                quant_tables: [quant_tables; 2],
+                grid_shift: false,
+            };
+            GridShiftExperiment::run(&pack.raw_image, &recompression, &quant_tables, offset)
+        });
 
-    let ys_quantized = ys_block_matrix.apply_quantization(&scaled_luminance_quant_table);
-    let cbs_quantized = cbs_block_matrix.apply_quantization(&scaled_chrominance_quant_table);
-    let crs_quantized = crs_block_matrix.apply_quantization(&scaled_chrominance_quant_table);
+        pack.quantized.insert(PlotName::YsQuant3d, ys_quantized);
+        pack.quantized.insert(PlotName::CbsQuant3d, cbs_quantized);
+        pack.quantized.insert(PlotName::CrsQuant3d, crs_quantized);
+    }
+}
 
-    draw_spatial_channel(&ys_quantized, canvas_map, CanvasName::YsQuant, zoom);
-    draw_spatial_channel(&cbs_quantized, canvas_map, CanvasName::CbsQuant, zoom);
-    draw_spatial_channel(&crs_quantized, canvas_map, CanvasName::CrsQuant, zoom);
+fn draw_dct_quantized(
+    canvas_map: &HashMap<CanvasName, ElRef<HtmlCanvasElement>>,
+    pack: &mut ImagePack,
+    subsampling_pack: &SubsamplingPack,
+    zoom: u32,
+) {
+    let block_count = BLOCK_SIZE as usize / 8;
+    let start_u = pack.image_window.start_x as usize / 8;
+    let start_v = pack.image_window.start_y as usize / 8;
 
-    pack.plot_data.insert(PlotName::YsQuant3d, ys_quantized);
-    pack.plot_data.insert(PlotName::CbsQuant3d, cbs_quantized);
-    pack.plot_data.insert(PlotName::CrsQuant3d, crs_quantized);
+    for plot_name in PlotName::iter() {
+        let (horiz_mult, vert_mult) = subsampling_pack.mults(plot_name);
+        let window_blocks = pack.quantized.get(&plot_name).unwrap().crop(
+            start_u / horiz_mult,
+            start_v / vert_mult,
+            block_count.div_ceil(horiz_mult),
+            block_count.div_ceil(vert_mult),
+        );
+        pack.plot_data.insert(plot_name, window_blocks);
+    }
+    pack.window_metrics = window_metrics(pack, &pack.upsampled);
+    pack.deblocked_window_metrics = match &pack.deblocked {
+        Some(deblocked) => window_metrics(pack, deblocked),
+        None => Vec::new(),
+    };
+    // the same window coded losslessly, next to the estimated bits of its DCT blocks
+    pack.lossless_results = lossless::compare(&pack.image_window.to_image(), BLOCK_SIZE as usize);
+    pack.dct_window_bits = window_heatmap(pack, Heatmap::Bits, subsampling_pack)
+        .iter()
+        .sum();
 
-    draw_ycbcr_recovered(
-        &canvas_map,
+    draw_spatial_channel(
         &pack.plot_data.get(&PlotName::YsQuant3d).unwrap(),
+        canvas_map,
+        CanvasName::YsQuant,
+        zoom,
+    );
+    draw_spatial_channel(
         &pack.plot_data.get(&PlotName::CbsQuant3d).unwrap(),
+        canvas_map,
+        CanvasName::CbsQuant,
+        zoom,
+    );
+    draw_spatial_channel(
         &pack.plot_data.get(&PlotName::CrsQuant3d).unwrap(),
-        &subsampling_pack,
-        image_window,
-        quality,
+        canvas_map,
+        CanvasName::CrsQuant,
         zoom,
     );
+
+    draw_ycbcr_recovered(canvas_map, pack, subsampling_pack, zoom);
+}
+
+/// The file coefficients can be shown only if they line up with the image decoded by the browser
+/// and both chroma components share the quantization table
+fn is_source_jpeg_supported(source_jpeg: &DecodedJpeg, raw_image: &image::RawImage) -> bool {
+    let components = &source_jpeg.components;
+    components.len() == 3
+        && source_jpeg.width as u32 == raw_image.width()
+        && source_jpeg.height as u32 == raw_image.height()
+        && components[1].quant_table == components[2].quant_table
+        && source_subsampling(source_jpeg).is_some()
+}
+
+fn source_subsampling(source_jpeg: &DecodedJpeg) -> Option<SubsamplingPack> {
+    let components = &source_jpeg.components;
+    SubsamplingPack::with_factors(
+        (components[0].horiz_sampling, components[0].vert_sampling),
+        (components[1].horiz_sampling, components[1].vert_sampling),
+        (components[2].horiz_sampling, components[2].vert_sampling),
+    )
+}
+
+/// Takes the sampling layout and quantization tables from the file when its coefficients are shown,
+/// otherwise the edited tables are used or the Annex K ones are scaled according to the quality
+fn update_compression_settings(model: &mut Model) {
+    let source_jpeg = match &model.state {
+        State::ImageView(pack) if model.use_source_coefficients => pack.source_jpeg.clone(),
+        _ => None,
+    };
+    match source_jpeg {
+        Some(source_jpeg) => {
+            model.subsampling_pack = source_subsampling(&source_jpeg).unwrap();
+            model.scaled_luminance_quant_table = source_jpeg.components[0].quant_table;
+            model.scaled_chrominance_quant_table = source_jpeg.components[1].quant_table;
+        }
+        None => match model.custom_quant_tables {
+            Some((luminance, chrominance)) => {
+                model.scaled_luminance_quant_table = luminance;
+                model.scaled_chrominance_quant_table = chrominance;
+            }
+            None => {
+                model.scaled_luminance_quant_table = model
+                    .quality_scaling
+                    .scale(&quant::LUMINANCE_QUANTIZATION_TABLE, model.quality);
+                model.scaled_chrominance_quant_table = model
+                    .quality_scaling
+                    .scale(&quant::CHROMINANCE_QUANTIZATION_TABLE, model.quality);
+            }
+        },
+    }
+}
+
+/// Moves the image window onto the MCU grid so that it covers whole blocks of the file
+fn snap_image_window_to_mcu_grid(
+    image_window: &mut RawImageWindow,
+    subsampling_pack: &SubsamplingPack,
+) {
+    let mcu_width = subsampling_pack.mcu_width() as u32;
+    let mcu_height = subsampling_pack.mcu_height() as u32;
+    image_window.start_x -= image_window.start_x % mcu_width;
+    image_window.start_y -= image_window.start_y % mcu_height;
+}
+
+/// Index of the chosen block of the channel in the window blocks of `plot_data`
+/// and in the blocks of the whole image
+pub fn chosen_block_indices(
+    pack: &ImagePack,
+    plot_name: PlotName,
+    subsampling_pack: &SubsamplingPack,
+) -> (usize, usize) {
+    let (horiz_mult, vert_mult) = subsampling_pack.mults(plot_name);
+    let x = pack.chosen_block_x as usize / 8 / horiz_mult;
+    let y = pack.chosen_block_y as usize / 8 / vert_mult;
+    let u = pack.image_window.start_x as usize / 8 / horiz_mult + x;
+    let v = pack.image_window.start_y as usize / 8 / vert_mult + y;
+    (
+        x + y * pack.plot_data.get(&plot_name).unwrap().width,
+        u + v * pack.quantized.get(&plot_name).unwrap().width,
+    )
+}
+
+/// Samples of the chosen Y block before the compression
+pub fn chosen_block_samples(pack: &ImagePack) -> [[i16; 8]; 8] {
+    let samples_plane = pack.planes.ys.crop(
+        pack.image_window.start_x as usize + pack.chosen_block_x as usize,
+        pack.image_window.start_y as usize + pack.chosen_block_y as usize,
+        8,
+        8,
+    );
+    let mut samples = [[0_i16; 8]; 8];
+    for (i, sample) in samples_plane.data.iter().enumerate() {
+        samples[i / 8][i % 8] = *sample as i16;
+    }
+    samples
+}
+
+/// Draws the basis functions, the ones left out of the reconstruction faded, and the chosen Y block
+/// rebuilt from its first `coefficients_kept` coefficients in the zig-zag order
+fn draw_basis_explorer(
+    basis_canvas: &ElRef<HtmlCanvasElement>,
+    reconstruction_canvas_map: &HashMap<ReconstructionCanvasName, ElRef<HtmlCanvasElement>>,
+    pack: &ImagePack,
+    coefficients_kept: usize,
+) {
+    // the basis images are separated by a line of one pixel
+    let cell = 9;
+    let size = 8 * cell - 1;
+    let mut basis_data = vec![48_u8; size * size * 4];
+    for (i, natural) in ZIG_ZAG.iter().enumerate() {
+        let image = dct::basis_image(natural % 8, natural / 8);
+        for (y, row) in image.iter().enumerate() {
+            for (x, sample) in row.iter().enumerate() {
+                let value = if i < coefficients_kept {
+                    *sample as u8
+                } else {
+                    (*sample / 4 + 32) as u8
+                };
+                let offset = ((natural / 8 * cell + y) * size + natural % 8 * cell + x) * 4;
+                basis_data[offset..offset + 3].copy_from_slice(&[value; 3]);
+            }
+        }
+    }
+    for alpha in basis_data.iter_mut().skip(3).step_by(4) {
+        *alpha = 255;
+    }
+    turn_antialising_off_for_specific_canvas(basis_canvas);
+    let scale = basis_canvas.get().unwrap().width() as f64 / size as f64;
+    draw_scaled_image_with_w_h_and_scale(
+        basis_canvas,
+        &basis_data,
+        size as u32,
+        size as u32,
+        scale,
+        scale,
+    );
+
+    let original = chosen_block_samples(pack);
+    let partial = dct::partial_freq_to_spatial(&dct::spatial_to_freq(&original), coefficients_kept);
+    let mut error = [[0_i16; 8]; 8];
+    for (y, row) in error.iter_mut().enumerate() {
+        for (x, value) in row.iter_mut().enumerate() {
+            // amplified four times around the middle gray
+            *value = (128 + 4 * (partial[y][x] - original[y][x])).clamp(0, 255);
+        }
+    }
+    for (canvas_name, block) in [
+        (ReconstructionCanvasName::Original, original),
+        (ReconstructionCanvasName::Partial, partial),
+        (ReconstructionCanvasName::Error, error),
+    ] {
+        let canvas = reconstruction_canvas_map.get(&canvas_name).unwrap();
+        turn_antialising_off_for_specific_canvas(canvas);
+        let data = block
+            .iter()
+            .flatten()
+            .flat_map(|sample| [*sample as u8, *sample as u8, *sample as u8, 255])
+            .collect::<Vec<u8>>();
+        let scale = canvas.get().unwrap().width() as f64 / 8.0;
+        draw_scaled_image_with_w_h_and_scale(canvas, &data, 8, 8, scale, scale);
+    }
 }
 
 fn draw_dct_quantized_plots(
@@ -397,8 +974,11 @@ fn draw_dct_quantized_plots(
     let selected_x = pack.chosen_block_x as usize / 8;
     let selected_y = pack.chosen_block_y as usize / 8;
 
-    let horiz_mult: usize = horiz_mult_from_subsampling(&subsampling_pack);
-    let vert_mult: usize = vert_mult_from_subsampling(&subsampling_pack);
+    // the luma blocks under one block of the more subsampled chroma, the mults divide each other
+    let (cb_horiz_mult, cb_vert_mult) = subsampling_pack.mults(PlotName::CbsQuant3d);
+    let (cr_horiz_mult, cr_vert_mult) = subsampling_pack.mults(PlotName::CrsQuant3d);
+    let horiz_mult: usize = cb_horiz_mult.max(cr_horiz_mult);
+    let vert_mult: usize = cb_vert_mult.max(cr_vert_mult);
 
     draw_dct_quantized_plot(
         &pack.plot_data.get(&PlotName::YsQuant3d).unwrap(),
@@ -410,28 +990,19 @@ fn draw_dct_quantized_plots(
         horiz_mult,
         vert_mult,
     );
-    let subsampled_x: usize = selected_x / horiz_mult;
-    let subsampled_y: usize = selected_y / vert_mult;
-    draw_dct_quantized_plot(
-        &pack.plot_data.get(&PlotName::CbsQuant3d).unwrap(),
-        subsampled_x,
-        subsampled_y,
-        plot_map,
-        chosen_block_plot_map,
-        PlotName::CbsQuant3d,
-        1,
-        1,
-    );
-    draw_dct_quantized_plot(
-        &pack.plot_data.get(&PlotName::CrsQuant3d).unwrap(),
-        subsampled_x,
-        subsampled_y,
-        plot_map,
-        chosen_block_plot_map,
-        PlotName::CrsQuant3d,
-        1,
-        1,
-    );
+    for plot_name in [PlotName::CbsQuant3d, PlotName::CrsQuant3d] {
+        let (horiz_mult, vert_mult) = subsampling_pack.mults(plot_name);
+        draw_dct_quantized_plot(
+            pack.plot_data.get(&plot_name).unwrap(),
+            selected_x / horiz_mult,
+            selected_y / vert_mult,
+            plot_map,
+            chosen_block_plot_map,
+            plot_name,
+            1,
+            1,
+        );
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -554,13 +1125,17 @@ fn draw_dct_quantized_plot(
     chart.configure_axes().draw().unwrap();
 
     let block_x = selected_x - selected_x % horiz_blocks;
-    let block_z = selected_z - selected_z % horiz_blocks;
+    let block_z = selected_z - selected_z % vert_blocks;
 
     chart
         .draw_series(
             (0i32..(8 * horiz_blocks) as i32)
                 .map(|x| std::iter::repeat(x).zip(0i32..(8 * vert_blocks) as i32))
                 .flatten()
+                // an MCU at the edge of the window can stick out of it
+                .filter(|(x, z)| {
+                    block_x + *x as usize / 8 < width && block_z + *z as usize / 8 < height
+                })
                 .map(|(x, z)| {
                     let block =
                         blocks[block_x + x as usize / 8 + (block_z + z as usize / 8) * width].0;
@@ -607,68 +1182,35 @@ fn draw_spatial_channel(
     draw_default(&canvas_map, canvas_name, image_data, zoom);
 }
 
-#[allow(clippy::too_many_arguments)]
 fn draw_ycbcr_recovered(
     canvas_map: &HashMap<CanvasName, ElRef<HtmlCanvasElement>>,
-    ys_quantized: &BlockMatrix,
-    cbs_quantized: &BlockMatrix,
-    crs_quantized: &BlockMatrix,
+    pack: &ImagePack,
     subsampling_pack: &SubsamplingPack,
-    image_window: &image::RawImageWindow,
-    quality: u8,
     zoom: u32,
 ) {
-    let scaled_luminance_quant_table =
-        quant::scale_quantization_table(&quant::LUMINANCE_QUANTIZATION_TABLE, quality);
-    let scaled_chrominance_quant_table =
-        quant::scale_quantization_table(&quant::CHROMINANCE_QUANTIZATION_TABLE, quality);
-
-    let ys_dequantized = ys_quantized.undo_quantization(&scaled_luminance_quant_table);
-    let cbs_dequantized = cbs_quantized.undo_quantization(&scaled_chrominance_quant_table);
-    let crs_dequantized = crs_quantized.undo_quantization(&scaled_chrominance_quant_table);
+    let planes = window_planes(&pack.recovered, &pack.image_window, subsampling_pack);
 
-    let ys = ys_dequantized.flatten();
-    let cbs = cbs_dequantized.flatten();
-    let crs = crs_dequantized.flatten();
-
-    let ys_image = ys
-        .iter()
-        .flat_map(|x| {
-            let RGB { r, g, b } = image::pixel::YCbCr {
-                y: *x,
-                cb: 128,
-                cr: 128,
-            }
-            .to_rgb();
-            vec![r, g, b, 255]
-        })
-        .collect::<Vec<u8>>();
-
-    let cbs_image = cbs
-        .iter()
-        .flat_map(|x| {
-            let RGB { r, g, b } = image::pixel::YCbCr {
-                y: 128,
-                cb: *x,
-                cr: 128,
-            }
-            .to_rgb();
-            vec![r, g, b, 255]
-        })
-        .collect::<Vec<u8>>();
-
-    let crs_image = crs
-        .iter()
-        .flat_map(|x| {
-            let RGB { r, g, b } = image::pixel::YCbCr {
-                y: 128,
-                cb: 128,
-                cr: *x,
-            }
-            .to_rgb();
-            vec![r, g, b, 255]
-        })
-        .collect::<Vec<u8>>();
+    let ys_image = channel_image(&planes.ys.data, planes.color_transform, |y| {
+        image::pixel::YCbCr {
+            y,
+            cb: 128,
+            cr: 128,
+        }
+    });
+    let cbs_image = channel_image(&planes.cbs.data, planes.color_transform, |cb| {
+        image::pixel::YCbCr {
+            y: 128,
+            cb,
+            cr: 128,
+        }
+    });
+    let crs_image = channel_image(&planes.crs.data, planes.color_transform, |cr| {
+        image::pixel::YCbCr {
+            y: 128,
+            cb: 128,
+            cr,
+        }
+    });
 
     draw_default(&canvas_map, CanvasName::YsRecovered, ys_image, zoom);
     draw_default(&canvas_map, CanvasName::CbsRecovered, cbs_image, zoom);
@@ -676,41 +1218,102 @@ fn draw_ycbcr_recovered(
 
     draw_image_recovered(
         canvas_map,
-        ys,
-        cbs,
-        crs,
-        &subsampling_pack,
-        image_window,
+        &window_upsampled_planes(&pack.upsampled, &pack.image_window),
+        &pack.image_window,
+        zoom,
+    );
+    if let Some(deblocked) = &pack.deblocked {
+        let size = BLOCK_SIZE as usize;
+        let deblocked_image =
+            window_upsampled_planes(deblocked, &pack.image_window).to_rgba(1, 1, size, size);
+        draw_default(
+            canvas_map,
+            CanvasName::ImageDeblocked,
+            deblocked_image,
+            zoom,
+        );
+    }
+    if let Some(experiment) = &pack.grid_shift_experiment {
+        draw_grid_shift_experiment(canvas_map, experiment, &pack.image_window, zoom);
+    }
+}
+
+fn draw_grid_shift_experiment(
+    canvas_map: &HashMap<CanvasName, ElRef<HtmlCanvasElement>>,
+    experiment: &GridShiftExperiment,
+    image_window: &RawImageWindow,
+    zoom: u32,
+) {
+    let window = |image: &Rc<image::RawImage>| {
+        RawImageWindow::new(
+            image.clone(),
+            image_window.start_x,
+            image_window.start_y,
+            BLOCK_SIZE,
+            BLOCK_SIZE,
+        )
+        .to_image()
+    };
+    let aligned = window(&experiment.aligned);
+    let shifted = window(&experiment.shifted);
+    let error_difference =
+        grid_shift::error_difference(&image_window.to_image(), &aligned, &shifted);
+
+    draw_default(canvas_map, CanvasName::GridAligned, aligned, zoom);
+    draw_default(canvas_map, CanvasName::GridShifted, shifted, zoom);
+    draw_default(
+        canvas_map,
+        CanvasName::GridErrorDifference,
+        error_difference,
         zoom,
     );
 }
 
+fn draw_lossless_residuals(
+    canvas_map: &HashMap<CanvasName, ElRef<HtmlCanvasElement>>,
+    pack: &ImagePack,
+    predictor: Predictor,
+    zoom: u32,
+) {
+    if let Some(result) = pack
+        .lossless_results
+        .iter()
+        .find(|result| result.predictor == predictor)
+    {
+        draw_default(
+            canvas_map,
+            CanvasName::LosslessResiduals,
+            lossless::residual_image(result),
+            zoom,
+        );
+    }
+}
+
+/// Part of the recovered image with the interpolated chroma under the image window
+fn window_upsampled_planes(
+    upsampled: &image::YCbCrPlanes,
+    image_window: &RawImageWindow,
+) -> image::YCbCrPlanes {
+    let start_x = image_window.start_x as usize;
+    let start_y = image_window.start_y as usize;
+    let size = BLOCK_SIZE as usize;
+    let crop = |plane: &image::Plane| plane.crop(start_x, start_y, size, size);
+    image::YCbCrPlanes {
+        ys: crop(&upsampled.ys),
+        cbs: crop(&upsampled.cbs),
+        crs: crop(&upsampled.crs),
+        color_transform: upsampled.color_transform,
+    }
+}
+
 fn draw_image_recovered(
     canvas_map: &HashMap<CanvasName, ElRef<HtmlCanvasElement>>,
-    ys: Vec<u8>,
-    cbs: Vec<u8>,
-    crs: Vec<u8>,
-    subsampling_pack: &SubsamplingPack,
+    planes: &image::YCbCrPlanes,
     image_window: &image::RawImageWindow,
     zoom: u32,
 ) {
-    let horiz_mult: usize = horiz_mult_from_subsampling(&subsampling_pack);
-    let vert_mult: usize = vert_mult_from_subsampling(&subsampling_pack);
-
-    let mut output_image = Vec::<u8>::new();
-    for i in 0..ys.len() {
-        let curr_cb_cr: usize = subsampled_index_for_recovery(i, horiz_mult, vert_mult);
-        let RGB { r, g, b } = image::pixel::YCbCr {
-            y: ys[i],
-            cb: cbs[curr_cb_cr],
-            cr: crs[curr_cb_cr],
-        }
-        .to_rgb();
-        output_image.push(r);
-        output_image.push(g);
-        output_image.push(b);
-        output_image.push(255);
-    }
+    let size = BLOCK_SIZE as usize;
+    let output_image = planes.to_rgba(1, 1, size, size);
 
     let input_image = image_window.to_image();
     let image_diff = get_image_diff(&output_image, &input_image);
@@ -718,10 +1321,103 @@ fn draw_image_recovered(
 
     draw_default(&canvas_map, CanvasName::Difference, image_diff, zoom);
 }
+/// Per-block values of the window for the heatmap, the subsampled components are spread
+/// over the luma blocks they cover
+fn window_heatmap(
+    pack: &ImagePack,
+    heatmap: Heatmap,
+    subsampling_pack: &SubsamplingPack,
+) -> Vec<f64> {
+    let size = BLOCK_SIZE as usize;
+    if heatmap == Heatmap::Error {
+        let recovered =
+            window_upsampled_planes(&pack.upsampled, &pack.image_window).to_rgba(1, 1, size, size);
+        return heatmap::block_errors(&pack.image_window.to_image(), &recovered);
+    }
+
+    let block_count = size / 8;
+    let mut values = vec![0.0; block_count * block_count];
+    for plot_name in PlotName::iter() {
+        let quantized = pack.quantized.get(&plot_name).unwrap();
+        let (horiz_mult, vert_mult) = subsampling_pack.mults(plot_name);
+        let (dc_table, ac_table) = match plot_name {
+            PlotName::YsQuant3d => pack.huffman_tables.luminance(),
+            _ => pack.huffman_tables.chrominance(),
+        };
+        let block_values = match heatmap {
+            Heatmap::NonZeroCoefficients => heatmap::non_zero_coefficients(quantized),
+            _ => {
+                let order =
+                    subsampling_pack.block_order(plot_name, quantized.width, quantized.height);
+                heatmap::block_bits(quantized, &order, dc_table, ac_table)
+            }
+        };
+        heatmap::add_window_values(
+            &mut values,
+            &block_values,
+            quantized.width,
+            pack.image_window.start_x as usize / 8,
+            pack.image_window.start_y as usize / 8,
+            horiz_mult,
+            vert_mult,
+        );
+    }
+    values
+}
+
+fn draw_heatmaps(
+    heatmap_canvas_map: &HashMap<HeatmapCanvasName, ElRef<HtmlCanvasElement>>,
+    pack: &ImagePack,
+    heatmap: Option<Heatmap>,
+    subsampling_pack: &SubsamplingPack,
+    zoom: u32,
+) {
+    let heatmap = match heatmap {
+        Some(heatmap) => heatmap,
+        None => return,
+    };
+    let image = heatmap::heatmap_image(&window_heatmap(pack, heatmap, subsampling_pack));
+    for canvas in heatmap_canvas_map.values() {
+        turn_antialising_off_for_specific_canvas(canvas);
+        draw_scaled_image_default(canvas, &image, zoom);
+    }
+}
+
+/// Draws the recovered image and its difference from the original at full size
+fn draw_full_image(
+    full_image_canvas_map: &HashMap<FullImageCanvasName, ElRef<HtmlCanvasElement>>,
+    pack: &ImagePack,
+) {
+    let width = pack.raw_image.width();
+    let height = pack.raw_image.height();
+    let recovered_image = pack
+        .upsampled
+        .to_rgba(1, 1, width as usize, height as usize);
+    let image_diff = get_image_diff(&recovered_image, &pack.raw_image[..].to_vec());
+
+    for (canvas_name, image_data) in [
+        (FullImageCanvasName::Recovered, recovered_image),
+        (FullImageCanvasName::Difference, image_diff),
+    ] {
+        let canvas = full_image_canvas_map.get(&canvas_name).unwrap();
+        canvas.get().unwrap().set_width(width);
+        canvas.get().unwrap().set_height(height);
+        draw_scaled_image_with_w_h_and_scale(canvas, &image_data, width, height, 1.0, 1.0);
+    }
+}
+
+/// Number of blocks the window MCUs emit, the length of the interleaving animation
+fn window_block_count(subsampling_pack: &SubsamplingPack) -> usize {
+    mcu::window_mcus(subsampling_pack)
+        .iter()
+        .map(|mcu| mcu.blocks.len())
+        .sum()
+}
+
 pub fn subsampled_index_for_recovery(i: usize, horiz_mult: usize, vert_mult: usize) -> usize {
-    return ((i / (BLOCK_SIZE as usize * vert_mult)) * BLOCK_SIZE as usize
-        + i % (BLOCK_SIZE as usize))
-        / horiz_mult;
+    let x = i % BLOCK_SIZE as usize;
+    let y = i / BLOCK_SIZE as usize;
+    (y / vert_mult) * subsampled_window_size(horiz_mult) + x / horiz_mult
 }
 
 fn draw_default(
@@ -851,6 +1547,315 @@ impl<'a> Urls<'a> {
 //    Update
 // ------ ------
 
+/// Compresses the image again after the settings changed and redraws everything that depends on it
+fn redraw_compression(model: &mut Model) {
+    compress_image(model);
+    if let State::ImageView(ref mut pack) = model.state {
+        draw_dct_quantized(&model.canvas_map, pack, &model.subsampling_pack, model.zoom);
+        draw_lossless_residuals(
+            &model.canvas_map,
+            pack,
+            model.lossless_predictor,
+            model.zoom,
+        );
+        draw_heatmaps(
+            &model.heatmap_canvas_map,
+            pack,
+            model.heatmap,
+            &model.subsampling_pack,
+            model.zoom,
+        );
+        draw_dct_quantized_plots(
+            pack,
+            &model.plot_map,
+            &model.chosen_block_plot_map,
+            &model.subsampling_pack,
+        );
+        draw_basis_explorer(
+            &model.basis_canvas,
+            &model.reconstruction_canvas_map,
+            pack,
+            model.coefficients_kept,
+        );
+        draw_full_image(&model.full_image_canvas_map, pack);
+    }
+    draw_rd_chart(&model.rd_chart_canvas, &model.rd_curves, model.quality);
+}
+
+/// Sweeps the quality for the current subsampling ratio, a curve with the same settings is replaced
+fn add_rd_curve(model: &mut Model) {
+    if let State::ImageView(ref pack) = model.state {
+        let subsampling_pack = &model.subsampling_pack;
+        let width = pack.planes.ys.width;
+        let height = pack.planes.ys.height;
+
+        let full = pack.raw_image.to_ycbcr_planes(pack.planes.color_transform);
+        let original = image::YCbCrPlanes {
+            ys: full.ys.pad(width, height),
+            cbs: full.cbs.pad(width, height),
+            crs: full.crs.pad(width, height),
+            color_transform: full.color_transform,
+        };
+        let quantizers = (
+            Quantizer::new(
+                model.quantization_strategy,
+                model.trellis_lambda as f64,
+                &HuffmanTable::luminance_ac(),
+            ),
+            Quantizer::new(
+                model.quantization_strategy,
+                model.trellis_lambda as f64,
+                &HuffmanTable::chrominance_ac(),
+            ),
+        );
+        let points = rate_distortion::sweep(
+            &original,
+            &pack.planes,
+            subsampling_pack,
+            model.dct_algorithm.transform(),
+            model.quality_scaling,
+            &quantizers,
+        );
+
+        let label = format!(
+            "{}, {}, {}, {}",
+            subsampling_pack.name(),
+            model.downsampling_filter.name(),
+            model.quality_scaling.name(),
+            model.quantization_strategy.name()
+        );
+        model.rd_curves.retain(|curve| curve.label != label);
+        model.rd_curves.push(RdCurve { label, points });
+    }
+}
+
+/// Recompresses the image with the current settings as many times as the user chose
+fn run_generation_loss(model: &mut Model) {
+    if let State::ImageView(ref pack) = model.state {
+        let tables = (
+            model.scaled_luminance_quant_table,
+            model.scaled_chrominance_quant_table,
+        );
+        let alternate_tables = match model.generation_alternate_quality {
+            Some(quality) => (
+                model
+                    .quality_scaling
+                    .scale(&LUMINANCE_QUANTIZATION_TABLE, quality),
+                model
+                    .quality_scaling
+                    .scale(&CHROMINANCE_QUANTIZATION_TABLE, quality),
+            ),
+            None => tables,
+        };
+        let quantizers = (
+            Quantizer::new(
+                model.quantization_strategy,
+                model.trellis_lambda as f64,
+                &HuffmanTable::luminance_ac(),
+            ),
+            Quantizer::new(
+                model.quantization_strategy,
+                model.trellis_lambda as f64,
+                &HuffmanTable::chrominance_ac(),
+            ),
+        );
+        let recompression = Recompression {
+            subsampling_pack: &model.subsampling_pack,
+            color_transform: model.color_transform,
+            downsampling_filter: model.downsampling_filter,
+            upsampling_filter: model.upsampling_filter,
+            chroma_siting: model.chroma_siting,
+            transform: model.dct_algorithm.transform(),
+            quantizers: &quantizers,
+            quant_tables: [tables, alternate_tables],
+            grid_shift: model.generation_grid_shift,
+        };
+
+        let mut label = format!(
+            "quality {}, {}",
+            model.quality,
+            model.subsampling_pack.ratio_name()
+        );
+        if let Some(quality) = model.generation_alternate_quality {
+            label += &format!(", every other generation quality {}", quality);
+        }
+        if model.generation_grid_shift {
+            label += ", grid shifted by 1 pixel every generation";
+        }
+        let generation_loss = recompression.run(&pack.raw_image, model.generation_count, label);
+        model.generation_loss = Some(generation_loss);
+    }
+}
+
+fn draw_generation_chart(
+    canvas: &ElRef<HtmlCanvasElement>,
+    generation_loss: Option<&GenerationLoss>,
+) {
+    let canvas = match canvas.get() {
+        Some(canvas) => canvas,
+        None => return,
+    };
+    let area = CanvasBackend::with_canvas_object(canvas)
+        .unwrap()
+        .into_drawing_area();
+    area.fill(&RGBColor(150, 150, 150)).unwrap();
+    let generation_loss = match generation_loss {
+        Some(generation_loss) => generation_loss,
+        None => return,
+    };
+
+    let points = || {
+        generation_loss
+            .psnr
+            .iter()
+            .enumerate()
+            .map(|(i, psnr)| ((i + 1) as f64, *psnr))
+            .filter(|(_, psnr)| psnr.is_finite())
+    };
+    let min_psnr = points().map(|(_, psnr)| psnr).fold(f64::MAX, f64::min);
+    let max_psnr = points().map(|(_, psnr)| psnr).fold(f64::MIN, f64::max);
+    if min_psnr > max_psnr {
+        return;
+    }
+
+    let mut chart = ChartBuilder::on(&area)
+        .margin(30)
+        .caption(&generation_loss.label, ("sans-serif", 20))
+        .set_label_area_size(LabelAreaPosition::Left, (8).percent())
+        .set_label_area_size(LabelAreaPosition::Bottom, (8).percent())
+        .build_cartesian_2d(
+            0.0..(generation_loss.psnr.len() + 1) as f64,
+            min_psnr.floor() - 1.0..max_psnr.ceil() + 1.0,
+        )
+        .unwrap();
+
+    chart
+        .configure_mesh()
+        .x_desc("generation")
+        .y_desc("PSNR [dB]")
+        .draw()
+        .unwrap();
+
+    chart
+        .draw_series(LineSeries::new(
+            points(),
+            Palette99::pick(0).stroke_width(3),
+        ))
+        .unwrap();
+    chart
+        .draw_series(
+            generation_loss
+                .thumbnails
+                .iter()
+                .map(|(generation, _)| (*generation as f64, generation_loss.psnr[generation - 1]))
+                .filter(|(_, psnr)| psnr.is_finite())
+                .map(|point| Circle::new(point, 6, Palette99::pick(0).filled())),
+        )
+        .unwrap();
+}
+
+fn draw_generation_thumbnails(
+    canvases: &[ElRef<HtmlCanvasElement>],
+    generation_loss: &GenerationLoss,
+) {
+    for (canvas, (_, image)) in canvases.iter().zip(generation_loss.thumbnails.iter()) {
+        if canvas.get().is_none() {
+            continue;
+        }
+        let scale = GENERATION_THUMBNAIL_WIDTH as f64 / image.width() as f64;
+        draw_scaled_image_with_w_h_and_scale(
+            canvas,
+            &image.as_ref().to_vec(),
+            image.width(),
+            image.height(),
+            scale,
+            scale,
+        );
+    }
+}
+
+/// Bits per pixel against PSNR for every curve, the points of the current quality are marked
+fn draw_rd_chart(canvas: &ElRef<HtmlCanvasElement>, rd_curves: &[RdCurve], quality: u8) {
+    let canvas = match canvas.get() {
+        Some(canvas) => canvas,
+        None => return,
+    };
+    let area = CanvasBackend::with_canvas_object(canvas)
+        .unwrap()
+        .into_drawing_area();
+    area.fill(&RGBColor(150, 150, 150)).unwrap();
+    if rd_curves.is_empty() {
+        return;
+    }
+
+    let points = || {
+        rd_curves
+            .iter()
+            .flat_map(|curve| curve.points.iter())
+            .filter(|point| point.psnr.is_finite())
+    };
+    let max_bits_per_pixel = points()
+        .map(|point| point.bits_per_pixel)
+        .fold(0.0, f64::max);
+    let min_psnr = points().map(|point| point.psnr).fold(f64::MAX, f64::min);
+    let max_psnr = points().map(|point| point.psnr).fold(f64::MIN, f64::max);
+
+    let mut chart = ChartBuilder::on(&area)
+        .margin(30)
+        .caption(
+            format!("Rate-distortion, quality {} marked", quality),
+            ("sans-serif", 24),
+        )
+        .set_label_area_size(LabelAreaPosition::Left, (8).percent())
+        .set_label_area_size(LabelAreaPosition::Bottom, (8).percent())
+        .build_cartesian_2d(
+            0.0..max_bits_per_pixel * 1.05,
+            min_psnr.floor()..max_psnr.ceil() + 1.0,
+        )
+        .unwrap();
+
+    chart
+        .configure_mesh()
+        .x_desc("bits per pixel")
+        .y_desc("PSNR [dB]")
+        .draw()
+        .unwrap();
+
+    for (i, curve) in rd_curves.iter().enumerate() {
+        chart
+            .draw_series(LineSeries::new(
+                curve
+                    .points
+                    .iter()
+                    .filter(|point| point.psnr.is_finite())
+                    .map(|point| (point.bits_per_pixel, point.psnr)),
+                Palette99::pick(i).stroke_width(3),
+            ))
+            .unwrap()
+            .label(curve.label.as_str())
+            .legend(move |(x, y)| {
+                Rectangle::new([(x - 5, y - 5), (x + 5, y + 5)], Palette99::pick(i))
+            });
+        if let Some(point) = curve.point(quality).filter(|point| point.psnr.is_finite()) {
+            chart
+                .draw_series(std::iter::once(Circle::new(
+                    (point.bits_per_pixel, point.psnr),
+                    6,
+                    Palette99::pick(i).filled(),
+                )))
+                .unwrap();
+        }
+    }
+
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::LowerRight)
+        .border_style(BLACK)
+        .background_style(WHITE.mix(0.5))
+        .draw()
+        .unwrap();
+}
+
 fn draw_all(model: &mut Model) {
     if let State::ImageView(ref mut pack) = model.state {
         turn_antialiasing_off(&model.canvas_map, &model.preview_canvas_map);
@@ -869,17 +1874,19 @@ fn draw_all(model: &mut Model) {
         draw_original_image_preview(&model.original_image_canvas, &pack.raw_image);
 
         draw_input_previews(&model.preview_canvas_map, &pack.image_window, model.zoom);
-        draw_ycbcr(
+        draw_ycbcr(&model.canvas_map, pack, &model.subsampling_pack, model.zoom);
+        draw_dct_quantized(&model.canvas_map, pack, &model.subsampling_pack, model.zoom);
+        draw_lossless_residuals(
             &model.canvas_map,
-            &pack.ycbcr,
-            &model.subsampling_pack,
+            pack,
+            model.lossless_predictor,
             model.zoom,
         );
-        draw_dct_quantized(
-            &model.canvas_map,
+        draw_heatmaps(
+            &model.heatmap_canvas_map,
             pack,
+            model.heatmap,
             &model.subsampling_pack,
-            model.quality,
             model.zoom,
         );
         draw_dct_quantized_plots(
@@ -888,6 +1895,13 @@ fn draw_all(model: &mut Model) {
             &model.chosen_block_plot_map,
             &model.subsampling_pack,
         );
+        draw_basis_explorer(
+            &model.basis_canvas,
+            &model.reconstruction_canvas_map,
+            pack,
+            model.coefficients_kept,
+        );
+        draw_full_image(&model.full_image_canvas_map, pack);
         draw_block_choice_indicators(
             &model.overlay_map,
             &model.preview_overlay_map,
@@ -903,8 +1917,9 @@ pub(crate) fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>)
         Msg::FileChooserLoadImage(file) => {
             let file_blob = gloo_file::Blob::from(file);
             orders.perform_cmd(async move {
+                let file_bytes = gloo_file::futures::read_as_bytes(&file_blob).await.unwrap();
                 let raw_image = utils::load_image(file_blob).await;
-                Msg::ImageLoaded(raw_image)
+                Msg::ImageLoaded(raw_image, file_bytes)
             });
             model.quality = 50;
             model.zoom = 7;
@@ -929,30 +1944,148 @@ pub(crate) fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>)
                 let file_blob = gloo_file::Blob::new(img_bytes.as_slice());
 
                 let raw_image = utils::load_image(file_blob).await;
-                Msg::ImageLoaded(raw_image)
+                Msg::ImageLoaded(raw_image, img_bytes)
             });
             model.quality = 50;
             model.zoom = 7;
             model.state = State::PreImageView
         }
-        Msg::ImageLoaded(raw_image) => {
+        Msg::ImageLoaded(raw_image, file_bytes) => {
+            let source_jpeg = decoder::decode(&file_bytes)
+                .ok()
+                .filter(|source_jpeg| is_source_jpeg_supported(source_jpeg, &raw_image))
+                .map(Rc::new);
+            model.use_source_coefficients = source_jpeg.is_some();
+            let source_scans = match &source_jpeg {
+                Some(source_jpeg) => source_jpeg.scans.clone(),
+                None => Vec::new(),
+            };
+
+            let file_segments = segments::parse_segments(&file_bytes).unwrap_or_default();
+            let file_quant_tables = segments::quantization_tables(&file_segments);
+            let component_table_ids = segments::frame_quant_table_ids(&file_bytes, &file_segments);
+            let file_quant_tables = source_quant_tables(&file_quant_tables, &component_table_ids);
+            let source_quality = estimate_source_quality(&file_quant_tables);
+            // the slider and the custom tables start from the ones the file was saved with,
+            // a file without tables drops the ones left from the previous file
+            model.quality_scaling = QualityScaling::Ijg;
+            if let Some(luminance) = file_quant_tables.first() {
+                let chrominance = file_quant_tables.get(1).unwrap_or(luminance);
+                model.quality = source_quality[0].1.quality;
+                model.custom_quant_tables = Some((
+                    baseline_quant_table(luminance),
+                    baseline_quant_table(chrominance),
+                ));
+            } else {
+                model.custom_quant_tables = None;
+            }
+
+            let color_round_trips = color_round_trips(&raw_image);
             let raw_image_rc = Rc::new(raw_image);
             let image_window =
                 RawImageWindow::new(raw_image_rc.clone(), 0, 0, BLOCK_SIZE, BLOCK_SIZE);
 
-            let ycbcr = image_window.to_rgb_image().to_ycbcr_image();
             let pack: ImagePack = ImagePack {
                 raw_image: raw_image_rc,
                 image_window,
-                ycbcr,
+                planes: image::YCbCrPlanes::default(),
+                quantized: HashMap::<PlotName, BlockMatrix>::new(),
+                coefficient_edits: HashMap::new(),
+                recovered: image::YCbCrPlanes::default(),
+                upsampled: image::YCbCrPlanes::default(),
+                deblocked: None,
+                window_metrics: Vec::new(),
+                deblocked_window_metrics: Vec::new(),
+                grid_shift_experiment: None,
+                huffman_comparisons: Vec::new(),
+                amplitude_bits: 0,
+                huffman_tables: HuffmanTables::standard(),
+                lossless_results: Vec::new(),
+                dct_window_bits: 0.0,
+                color_round_trips,
                 plot_data: HashMap::<PlotName, BlockMatrix>::new(),
+                encoded_jpeg: Vec::new(),
+                source_jpeg,
+                file_segments,
+                source_quality,
+                chosen_segment: None,
+                file_bytes,
+                source_scan_count: source_scans.len(),
+                source_scans,
                 chosen_block_x: 0.0,
                 chosen_block_y: 0.0,
+                chosen_symbol: None,
             };
             model.state = State::ImageView(pack);
-
+            model.rd_curves.clear();
+            draw_rd_chart(&model.rd_chart_canvas, &model.rd_curves, model.quality);
+            model.generation_loss = None;
+            draw_generation_chart(&model.generation_chart_canvas, None);
+            model.strategy_results.clear();
+            update_compression_settings(model);
+
+            if model.use_source_coefficients {
+                // the canvas sizes depend on the subsampling of the file
+                orders.after_next_render(|_| Msg::PostSourceCoefficientsToggled);
+            } else {
+                compress_image(model);
+                draw_all(model);
+            }
+        }
+        Msg::SourceCoefficientsToggled => {
+            if let State::ImageView(ref mut pack) = model.state {
+                if pack.source_jpeg.is_some() {
+                    // the edits were made to other coefficients
+                    pack.coefficient_edits.clear();
+                    model.use_source_coefficients = !model.use_source_coefficients;
+                    update_compression_settings(model);
+                    orders.after_next_render(|_| Msg::PostSourceCoefficientsToggled);
+                }
+            }
+        }
+        Msg::SourceScanChanged(scan_count) => {
+            if let State::ImageView(ref mut pack) = model.state {
+                if scan_count == 0 || scan_count > pack.source_scans.len() {
+                    return;
+                }
+                // decoding is repeated from the start as the scans only add to the coefficients
+                if let Ok(source_jpeg) = decoder::decode_scans(&pack.file_bytes, scan_count) {
+                    pack.source_jpeg = Some(Rc::new(source_jpeg));
+                    pack.source_scan_count = scan_count;
+                } else {
+                    return;
+                }
+            }
+            redraw_compression(model);
+        }
+        Msg::PostSourceCoefficientsToggled => {
+            if let State::ImageView(ref mut pack) = model.state {
+                snap_image_window_to_mcu_grid(&mut pack.image_window, &model.subsampling_pack);
+            }
+            compress_image(model);
             draw_all(model);
         }
+        Msg::EncodedJpegDownloadClicked => {
+            if let State::ImageView(ref pack) = model.state {
+                utils::download_bytes(&pack.encoded_jpeg, "image/jpeg", "encoded.jpg");
+            }
+        }
+        Msg::EntropyChannelChanged(plot_name) => {
+            model.entropy_channel = plot_name;
+            if let State::ImageView(ref mut pack) = model.state {
+                pack.chosen_symbol = None;
+            }
+        }
+        Msg::EntropySymbolChosen(index) => {
+            if let State::ImageView(ref mut pack) = model.state {
+                pack.chosen_symbol = Some(index);
+            }
+        }
+        Msg::FileSegmentChosen(offset, length) => {
+            if let State::ImageView(ref mut pack) = model.state {
+                pack.chosen_segment = Some((offset, length));
+            }
+        }
         Msg::DiffInfoDisplayChanged => {
             model.is_diff_info_shown = !model.is_diff_info_shown;
         }
@@ -964,30 +2097,228 @@ pub(crate) fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>)
             draw_all(model);
         }
         Msg::QualityUpdated(quality) => {
+            model.quality = quality;
+            model.custom_quant_tables = None;
+            update_compression_settings(model);
+            redraw_compression(model);
+        }
+        Msg::QualityScalingChanged(quality_scaling) => {
+            model.quality_scaling = quality_scaling;
+            model.custom_quant_tables = None;
+            update_compression_settings(model);
+            redraw_compression(model);
+        }
+        Msg::QuantizationPresetLoaded(preset) => {
+            model.custom_quant_tables = Some(preset.tables());
+            update_compression_settings(model);
+            redraw_compression(model);
+        }
+        Msg::QuantizationTableEdited(table_name, row, col, value) => {
+            let (mut luminance, mut chrominance) = (
+                model.scaled_luminance_quant_table,
+                model.scaled_chrominance_quant_table,
+            );
+            match table_name {
+                QuantizationTableName::Luminance => luminance[row][col] = value.max(1),
+                QuantizationTableName::Chrominance => chrominance[row][col] = value.max(1),
+            }
+            model.custom_quant_tables = Some((luminance, chrominance));
+            update_compression_settings(model);
+            redraw_compression(model);
+        }
+        Msg::DctAlgorithmChanged(dct_algorithm) => {
+            model.dct_algorithm = dct_algorithm;
+            redraw_compression(model);
+        }
+        Msg::ColorTransformChanged(color_transform) => {
+            model.color_transform = color_transform;
+            redraw_compression(model);
+            if let State::ImageView(ref pack) = model.state {
+                draw_ycbcr(&model.canvas_map, pack, &model.subsampling_pack, model.zoom);
+            }
+        }
+        Msg::DownsamplingFilterChanged(downsampling_filter) => {
+            model.downsampling_filter = downsampling_filter;
+            redraw_compression(model);
+            if let State::ImageView(ref pack) = model.state {
+                draw_ycbcr(&model.canvas_map, pack, &model.subsampling_pack, model.zoom);
+            }
+        }
+        Msg::UpsamplingFilterChanged(upsampling_filter) => {
+            model.upsampling_filter = upsampling_filter;
+            redraw_compression(model);
+        }
+        Msg::ChromaSitingChanged(chroma_siting) => {
+            model.chroma_siting = chroma_siting;
+            redraw_compression(model);
+        }
+        Msg::DeblockingFilterChanged(deblocking_filter) => {
+            model.deblocking_filter = deblocking_filter;
+            redraw_compression(model);
+        }
+        Msg::GridShiftChanged(grid_shift) => {
+            model.grid_shift = grid_shift;
+            redraw_compression(model);
+        }
+        Msg::OptimizeCodingToggled => {
+            model.optimize_coding = !model.optimize_coding;
+            redraw_compression(model);
+        }
+        Msg::RdCurveAdded => {
+            add_rd_curve(model);
+            draw_rd_chart(&model.rd_chart_canvas, &model.rd_curves, model.quality);
+        }
+        Msg::RdCurvesCleared => {
+            model.rd_curves.clear();
+            draw_rd_chart(&model.rd_chart_canvas, &model.rd_curves, model.quality);
+        }
+        Msg::GenerationCountChanged(generation_count) => {
+            model.generation_count = generation_count;
+        }
+        Msg::GenerationGridShiftToggled => {
+            model.generation_grid_shift = !model.generation_grid_shift;
+        }
+        Msg::GenerationAlternateQualityChanged(quality) => {
+            model.generation_alternate_quality = quality;
+        }
+        Msg::QuantizationStrategyChanged(quantization_strategy) => {
+            model.quantization_strategy = quantization_strategy;
+            redraw_compression(model);
+        }
+        Msg::TrellisLambdaChanged(trellis_lambda) => {
+            model.trellis_lambda = trellis_lambda;
+            redraw_compression(model);
+        }
+        Msg::QuantizationStrategiesCompared => {
+            if let State::ImageView(ref pack) = model.state {
+                model.strategy_results = quantization_strategies::compare(
+                    &pack.planes,
+                    &model.subsampling_pack,
+                    &(
+                        model.scaled_luminance_quant_table,
+                        model.scaled_chrominance_quant_table,
+                    ),
+                    model.dct_algorithm.transform(),
+                    model.trellis_lambda as f64,
+                );
+            }
+        }
+        Msg::GenerationLossRun => {
+            run_generation_loss(model);
+            draw_generation_chart(
+                &model.generation_chart_canvas,
+                model.generation_loss.as_ref(),
+            );
+            if let Some(generation_loss) = &model.generation_loss {
+                draw_generation_thumbnails(&model.generation_thumbnail_canvases, generation_loss);
+            }
+        }
+        Msg::CoefficientEdited(plot_name, block_index, coefficient_index, value) => {
             if let State::ImageView(ref mut pack) = model.state {
-                model.quality = quality;
-                draw_dct_quantized(
-                    &model.canvas_map,
+                let quant_table = match plot_name {
+                    PlotName::YsQuant3d => &model.scaled_luminance_quant_table,
+                    _ => &model.scaled_chrominance_quant_table,
+                };
+                // the tables may have changed since the editor was drawn
+                let limit = quant::editable_coefficient_limit(
+                    coefficient_index,
+                    quant_table[coefficient_index / 8][coefficient_index % 8],
+                );
+                let value = value.clamp(-limit, limit);
+                pack.coefficient_edits
+                    .insert((plot_name, block_index, coefficient_index), value);
+            }
+            redraw_compression(model);
+        }
+        Msg::CoefficientEditsReset => {
+            if let State::ImageView(ref mut pack) = model.state {
+                pack.coefficient_edits.clear();
+            }
+            redraw_compression(model);
+        }
+        Msg::HeatmapChanged(heatmap) => {
+            model.heatmap = heatmap;
+            if let State::ImageView(ref pack) = model.state {
+                draw_heatmaps(
+                    &model.heatmap_canvas_map,
                     pack,
+                    model.heatmap,
+                    &model.subsampling_pack,
+                    model.zoom,
+                );
+            }
+        }
+        Msg::LosslessPredictorChanged(predictor) => {
+            model.lossless_predictor = predictor;
+            if let State::ImageView(ref pack) = model.state {
+                draw_lossless_residuals(&model.canvas_map, pack, predictor, model.zoom);
+            }
+        }
+        Msg::CoefficientsKeptChanged(coefficients_kept) => {
+            model.coefficients_kept = coefficients_kept;
+            if let State::ImageView(ref pack) = model.state {
+                draw_basis_explorer(
+                    &model.basis_canvas,
+                    &model.reconstruction_canvas_map,
+                    pack,
+                    model.coefficients_kept,
+                );
+            }
+        }
+        Msg::McuChosen(mcu_x, mcu_y) => {
+            if let State::ImageView(ref mut pack) = model.state {
+                // the first Y block of the MCU stands for the whole MCU in the other sections
+                pack.chosen_block_x = (mcu_x * model.subsampling_pack.mcu_width()) as f64;
+                pack.chosen_block_y = (mcu_y * model.subsampling_pack.mcu_height()) as f64;
+                pack.chosen_symbol = None;
+
+                draw_block_choice_indicators(
+                    &model.overlay_map,
+                    &model.preview_overlay_map,
+                    pack.chosen_block_x,
+                    pack.chosen_block_y,
                     &model.subsampling_pack,
-                    quality,
                     model.zoom,
                 );
                 draw_dct_quantized_plots(
-                    &pack,
+                    pack,
                     &model.plot_map,
                     &model.chosen_block_plot_map,
                     &model.subsampling_pack,
                 );
-
-                model.scaled_luminance_quant_table =
-                    quant::scale_quantization_table(&quant::LUMINANCE_QUANTIZATION_TABLE, quality);
-                model.scaled_chrominance_quant_table = quant::scale_quantization_table(
-                    &quant::CHROMINANCE_QUANTIZATION_TABLE,
-                    quality,
+                draw_basis_explorer(
+                    &model.basis_canvas,
+                    &model.reconstruction_canvas_map,
+                    pack,
+                    model.coefficients_kept,
+                );
+            }
+        }
+        Msg::McuAnimationToggled => {
+            if model.mcu_animation.take().is_none() {
+                let block_count = window_block_count(&model.subsampling_pack);
+                if model
+                    .mcu_animation_step
+                    .is_none_or(|step| step + 1 >= block_count)
+                {
+                    model.mcu_animation_step = Some(0);
+                }
+                model.mcu_animation = Some(
+                    orders.stream_with_handle(streams::interval(MCU_ANIMATION_INTERVAL, || {
+                        Msg::McuAnimationTicked
+                    })),
                 );
             }
         }
+        Msg::McuAnimationTicked => {
+            let block_count = window_block_count(&model.subsampling_pack);
+            let step = model.mcu_animation_step.map_or(0, |step| step + 1);
+            if step + 1 >= block_count {
+                // dropping the handle stops the stream
+                model.mcu_animation = None;
+            }
+            model.mcu_animation_step = Some(cmp::min(step, block_count - 1));
+        }
         Msg::PreviewCanvasClicked(x, y) => {
             if let State::ImageView(ref mut pack) = model.state {
                 let preview_canvas_ref = &model.original_image_canvas;
@@ -1013,8 +2344,8 @@ pub(crate) fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>)
 
                 pack.image_window.start_x = image_x;
                 pack.image_window.start_y = image_y;
-
-                pack.ycbcr = pack.image_window.to_rgb_image().to_ycbcr_image();
+                // the blocks under the window are cut out of the whole image
+                snap_image_window_to_mcu_grid(&mut pack.image_window, &model.subsampling_pack);
 
                 draw_input_selection_indicator(
                     &model.original_image_overlay,
@@ -1024,17 +2355,19 @@ pub(crate) fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>)
                 );
 
                 draw_input_previews(&model.preview_canvas_map, &pack.image_window, model.zoom);
-                draw_ycbcr(
+                draw_ycbcr(&model.canvas_map, pack, &model.subsampling_pack, model.zoom);
+                draw_dct_quantized(&model.canvas_map, pack, &model.subsampling_pack, model.zoom);
+                draw_lossless_residuals(
                     &model.canvas_map,
-                    &pack.ycbcr,
-                    &model.subsampling_pack,
+                    pack,
+                    model.lossless_predictor,
                     model.zoom,
                 );
-                draw_dct_quantized(
-                    &model.canvas_map,
+                draw_heatmaps(
+                    &model.heatmap_canvas_map,
                     pack,
+                    model.heatmap,
                     &model.subsampling_pack,
-                    model.quality,
                     model.zoom,
                 );
                 draw_dct_quantized_plots(
@@ -1043,37 +2376,34 @@ pub(crate) fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>)
                     &model.chosen_block_plot_map,
                     &model.subsampling_pack,
                 );
+                draw_basis_explorer(
+                    &model.basis_canvas,
+                    &model.reconstruction_canvas_map,
+                    pack,
+                    model.coefficients_kept,
+                );
             }
         }
-        Msg::BlockChosen(x, y, rect_x, rect_y, is_resizable_canvas) => {
+        Msg::BlockChosen(x, y, rect_x, rect_y, subsampled_channel) => {
             if let State::ImageView(ref mut pack) = model.state {
-                let vert_mult: usize = if !is_resizable_canvas {
-                    1_usize
-                } else {
-                    vert_mult_from_subsampling(&model.subsampling_pack)
-                };
-                let horiz_mult: usize = if !is_resizable_canvas {
-                    1_usize
-                } else {
-                    horiz_mult_from_subsampling(&model.subsampling_pack)
+                let (horiz_mult, vert_mult) = match subsampled_channel {
+                    Some(plot_name) => model.subsampling_pack.mults(plot_name),
+                    None => (1, 1),
                 };
 
-                let start_x: f64 = cmp::min(
-                    (x - rect_x) - (x - rect_x) % (8 * model.zoom as i32),
-                    (((BLOCK_SIZE as usize - 8 * horiz_mult) * model.zoom as usize) / horiz_mult)
-                        as i32,
-                ) as f64
-                    * horiz_mult as f64;
-                let start_y: f64 = cmp::min(
-                    (y - rect_y) - (y - rect_y) % (8 * model.zoom as i32),
-                    (((BLOCK_SIZE as usize - 8 * vert_mult) * model.zoom as usize) / vert_mult)
-                        as i32,
-                ) as f64
-                    * vert_mult as f64;
+                // the canvas shows a block of every MCU, the chosen one is clamped to the last
+                // block of the canvas and then scaled back to the luma block in the window
+                let block_size = 8 * model.zoom as i32;
+                let chosen_block = |click: i32, mult: usize| {
+                    let blocks = (BLOCK_SIZE as usize / 8).div_ceil(mult);
+                    let index = ((click.max(0) / block_size) as usize).min(blocks - 1);
+                    (index * 8 * mult) as f64
+                };
 
                 // chosen_block_x_y are coords if zoom was equal 1
-                pack.chosen_block_x = start_x / model.zoom as f64;
-                pack.chosen_block_y = start_y / model.zoom as f64;
+                pack.chosen_block_x = chosen_block(x - rect_x, horiz_mult);
+                pack.chosen_block_y = chosen_block(y - rect_y, vert_mult);
+                pack.chosen_symbol = None;
 
                 draw_block_choice_indicators(
                     &model.overlay_map,
@@ -1090,32 +2420,53 @@ pub(crate) fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>)
                     &model.chosen_block_plot_map,
                     &model.subsampling_pack,
                 );
+                draw_basis_explorer(
+                    &model.basis_canvas,
+                    &model.reconstruction_canvas_map,
+                    pack,
+                    model.coefficients_kept,
+                );
             }
         }
-        Msg::SubsamplingRatioChanged(y_ratio, cb_ratio, cr_ratio) => {
-            if let State::ImageView(_) = model.state {
-                model.subsampling_pack.j = y_ratio;
-                model.subsampling_pack.a = cb_ratio;
-                model.subsampling_pack.b = cr_ratio;
+        Msg::SubsamplingChanged(subsampling_pack) => {
+            if let State::ImageView(ref mut pack) = model.state {
+                // the chroma blocks are laid out differently
+                pack.coefficient_edits.clear();
+                model.subsampling_pack = subsampling_pack;
+                model.mcu_animation = None;
+                model.mcu_animation_step = None;
 
-                orders.after_next_render(|_| Msg::PostSubsamplingRatioChanged);
+                orders.after_next_render(|_| Msg::PostSubsamplingChanged);
             }
         }
-        Msg::PostSubsamplingRatioChanged => {
+        Msg::PostSubsamplingChanged => {
+            if let State::ImageView(ref mut pack) = model.state {
+                snap_image_window_to_mcu_grid(&mut pack.image_window, &model.subsampling_pack);
+            }
+            compress_image(model);
             if let State::ImageView(ref mut pack) = model.state {
                 turn_antialiasing_off_for_ordinary(&model.canvas_map);
 
-                draw_ycbcr(
-                    &model.canvas_map,
-                    &pack.ycbcr,
-                    &model.subsampling_pack,
+                draw_input_selection_indicator(
+                    &model.original_image_overlay,
+                    &pack.image_window,
+                    &pack.raw_image,
                     model.zoom,
                 );
-                draw_dct_quantized(
+                draw_input_previews(&model.preview_canvas_map, &pack.image_window, model.zoom);
+                draw_ycbcr(&model.canvas_map, pack, &model.subsampling_pack, model.zoom);
+                draw_dct_quantized(&model.canvas_map, pack, &model.subsampling_pack, model.zoom);
+                draw_lossless_residuals(
                     &model.canvas_map,
                     pack,
+                    model.lossless_predictor,
+                    model.zoom,
+                );
+                draw_heatmaps(
+                    &model.heatmap_canvas_map,
+                    pack,
+                    model.heatmap,
                     &model.subsampling_pack,
-                    model.quality,
                     model.zoom,
                 );
                 draw_block_choice_indicators(
@@ -1132,6 +2483,13 @@ pub(crate) fn update(msg: Msg, model: &mut Model, orders: &mut impl Orders<Msg>)
                     &model.chosen_block_plot_map,
                     &model.subsampling_pack,
                 );
+                draw_basis_explorer(
+                    &model.basis_canvas,
+                    &model.reconstruction_canvas_map,
+                    pack,
+                    model.coefficients_kept,
+                );
+                draw_full_image(&model.full_image_canvas_map, pack);
             }
         }
     }
@@ -0,0 +1,117 @@
+use super::model::{PlotName, SubsamplingPack};
+use crate::block::BlockMatrix;
+use crate::codec::jpeg::encoder::HuffmanTables;
+use crate::dct::Transform;
+use crate::image::{Plane, YCbCrPlanes};
+use crate::metrics;
+use crate::quant::{QuantizationStrategy, QuantizationTables, Quantizer};
+use strum::IntoEnumIterator;
+
+/// Size and error of the whole image quantized with one strategy
+#[derive(Clone, Copy, Debug)]
+pub struct StrategyResult {
+    pub strategy: QuantizationStrategy,
+    // entropy coded data with the Annex K tables
+    pub bits: u64,
+    pub bits_per_pixel: f64,
+    // PSNR of the Y, Cb and Cr planes taken together, the subsampling loss is left out
+    pub psnr: f64,
+}
+
+/// Quantizes the planes with every strategy and the same tables, the chroma error is weighted
+/// by the number of pixels a sample covers
+pub fn compare(
+    planes: &YCbCrPlanes,
+    subsampling_pack: &SubsamplingPack,
+    quant_tables: &QuantizationTables,
+    transform: &dyn Transform,
+    lambda: f64,
+) -> Vec<StrategyResult> {
+    let (luminance_table, chrominance_table) = quant_tables;
+    let pixels = planes.ys.width * planes.ys.height;
+    let sample_area = |plot_name: PlotName| {
+        let (horiz_mult, vert_mult) = subsampling_pack.mults(plot_name);
+        (horiz_mult * vert_mult) as f64
+    };
+    let tables = HuffmanTables::standard();
+
+    // squared error of the plane summed over the samples
+    let error = |plane: &Plane, quantized: &BlockMatrix, table: &[[u8; 8]; 8]| {
+        let recovered = quantized.undo_quantization(table, transform).to_plane();
+        metrics::mse(&plane.data, &recovered.data) * plane.data.len() as f64
+    };
+
+    QuantizationStrategy::iter()
+        .map(|strategy| {
+            let luminance_quantizer = Quantizer::new(strategy, lambda, &tables.luminance_ac);
+            let chrominance_quantizer = Quantizer::new(strategy, lambda, &tables.chrominance_ac);
+
+            let ys = BlockMatrix::from_plane(&planes.ys).quantize(
+                luminance_table,
+                transform,
+                &luminance_quantizer,
+            );
+            let [cbs, crs] = [&planes.cbs, &planes.crs].map(|plane| {
+                BlockMatrix::from_plane(plane).quantize(
+                    chrominance_table,
+                    transform,
+                    &chrominance_quantizer,
+                )
+            });
+            let squared_error = error(&planes.ys, &ys, luminance_table)
+                + error(&planes.cbs, &cbs, chrominance_table) * sample_area(PlotName::CbsQuant3d)
+                + error(&planes.crs, &crs, chrominance_table) * sample_area(PlotName::CrsQuant3d);
+
+            let (luminance, chrominance) = subsampling_pack.count_symbols(&ys, &cbs, &crs);
+            let bits = tables.coded_bits(&luminance, &chrominance);
+            StrategyResult {
+                strategy,
+                bits,
+                bits_per_pixel: bits as f64 / pixels as f64,
+                psnr: metrics::psnr(squared_error / (3 * pixels) as f64),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::compare;
+    use crate::dct::Reference;
+    use crate::image::{ColorTransform, YCbCrPlanes};
+    use crate::quant::{
+        QualityScaling, CHROMINANCE_QUANTIZATION_TABLE, LUMINANCE_QUANTIZATION_TABLE,
+    };
+    use crate::section::jpeg_visualization::model::SubsamplingPack;
+    use crate::section::jpeg_visualization::test_images::textured_plane;
+
+    #[test]
+    pub fn test_compare_strategies() {
+        let planes = YCbCrPlanes {
+            ys: textured_plane(32, 32, 1),
+            cbs: textured_plane(16, 16, 2),
+            crs: textured_plane(16, 16, 3),
+            color_transform: ColorTransform::Bt601Full,
+        };
+        let scaling = QualityScaling::Ijg;
+        let tables = (
+            scaling.scale(&LUMINANCE_QUANTIZATION_TABLE, 50),
+            scaling.scale(&CHROMINANCE_QUANTIZATION_TABLE, 50),
+        );
+        let results = compare(
+            &planes,
+            &SubsamplingPack::new(2, 2, 1, 1).unwrap(),
+            &tables,
+            &Reference,
+            40.0,
+        );
+        let [truncate, round, deadzone, trellis] = [0, 1, 2, 3].map(|i| results[i]);
+
+        assert_eq!(results.len(), 4);
+        // rounding halves the largest error of every coefficient
+        assert!(round.psnr > truncate.psnr);
+        // both give up some of that error for fewer bits
+        assert!(deadzone.bits < round.bits && deadzone.psnr < round.psnr);
+        assert!(trellis.bits < round.bits && trellis.psnr < round.psnr);
+    }
+}
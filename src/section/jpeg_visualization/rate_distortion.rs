@@ -0,0 +1,309 @@
+use super::model::{PlotName, SubsamplingPack};
+use crate::block::BlockMatrix;
+use crate::codec::jpeg::constants::{END_OF_BLOCK, ZERO_RUN};
+use crate::codec::jpeg::encoder::HuffmanTables;
+use crate::codec::jpeg::entropy;
+use crate::codec::jpeg::huffman::{HuffmanCode, HuffmanTable};
+use crate::dct::Transform;
+use crate::image::{Plane, YCbCrPlanes};
+use crate::metrics;
+use crate::quant::{
+    QualityScaling, Quantizer, CHROMINANCE_QUANTIZATION_TABLE, LUMINANCE_QUANTIZATION_TABLE,
+};
+
+/// Size and distortion of the image compressed with a single quality setting
+#[derive(Clone, Copy, Debug)]
+pub struct RdPoint {
+    pub quality: u8,
+    // entropy coded data only, without the headers and the stuffed bytes
+    pub bits: usize,
+    pub bits_per_pixel: f64,
+    // PSNR of the Y, Cb and Cr channels taken together
+    pub psnr: f64,
+}
+
+/// Qualities from 1 to 100 swept with one subsampling ratio
+pub struct RdCurve {
+    pub label: String,
+    pub points: Vec<RdPoint>,
+}
+
+impl RdCurve {
+    pub fn point(&self, quality: u8) -> Option<&RdPoint> {
+        self.points.iter().find(|point| point.quality == quality)
+    }
+}
+
+/// DCT coefficients of one component in the order they are coded
+struct Component {
+    coefficients: Vec<[[i16; 8]; 8]>,
+    // number of pixels covered by a single sample of the component
+    sample_area: usize,
+    dc_codes: [HuffmanCode; 256],
+    ac_codes: [HuffmanCode; 256],
+}
+
+impl Component {
+    fn new(
+        plane: &Plane,
+        order: &[usize],
+        transform: &dyn Transform,
+        sample_area: usize,
+        (dc_table, ac_table): (&HuffmanTable, &HuffmanTable),
+    ) -> Component {
+        let transformed =
+            BlockMatrix::from_plane(plane).apply_quantization(&[[1; 8]; 8], transform);
+        Component {
+            coefficients: order
+                .iter()
+                .map(|index| transformed.blocks[*index].0)
+                .collect(),
+            sample_area,
+            dc_codes: dc_table.codes(),
+            ac_codes: ac_table.codes(),
+        }
+    }
+
+    /// Coded bits and the squared error summed over the pixels, the DCT is orthonormal,
+    /// so the error of the coefficients equals the error of the samples.
+    /// Counts the same symbols as `entropy::block_symbols` without building them,
+    /// the sweep codes the whole image a hundred times
+    fn code(&self, quantization: &[[u8; 8]; 8], quantizer: &Quantizer) -> (usize, f64) {
+        let steps = entropy::zig_zag(&quantization.map(|row| row.map(|step| step as i16)));
+        let mut bits = 0;
+        let mut error: i64 = 0;
+        let mut previous_dc = 0;
+        for block in self.coefficients.iter() {
+            let coefficients = entropy::zig_zag(block);
+            let quantized_block = entropy::zig_zag(&quantizer.quantize(block, quantization));
+            let mut run = 0;
+            for (i, ((coefficient, quantized), step)) in coefficients
+                .iter()
+                .zip(quantized_block.iter())
+                .zip(steps.iter())
+                .enumerate()
+            {
+                let quantized = *quantized;
+                error += (*coefficient as i64 - quantized as i64 * *step as i64).pow(2);
+
+                if i == 0 {
+                    let size = entropy::magnitude_category(quantized - previous_dc);
+                    bits += (self.dc_codes[size as usize].length + size) as usize;
+                    previous_dc = quantized;
+                } else if quantized == 0 {
+                    run += 1;
+                } else {
+                    bits += (run / 16) * self.ac_codes[ZERO_RUN].length as usize;
+                    let size = entropy::magnitude_category(quantized);
+                    let value = ((run % 16) << 4) | size as usize;
+                    bits += (self.ac_codes[value].length + size) as usize;
+                    run = 0;
+                }
+            }
+            if run > 0 {
+                bits += self.ac_codes[END_OF_BLOCK].length as usize;
+            }
+        }
+        (bits, (error * self.sample_area as i64) as f64)
+    }
+}
+
+/// Estimates the size and PSNR for every quality without running the inverse DCT, `original` holds
+/// the full resolution channels and `planes` the subsampled ones that are compressed.
+/// `quantizers` round the luminance and the chrominance blocks
+pub fn sweep(
+    original: &YCbCrPlanes,
+    planes: &YCbCrPlanes,
+    subsampling_pack: &SubsamplingPack,
+    transform: &dyn Transform,
+    quality_scaling: QualityScaling,
+    quantizers: &(Quantizer, Quantizer),
+) -> Vec<RdPoint> {
+    let width = planes.ys.width;
+    let height = planes.ys.height;
+    let pixels = width * height;
+
+    let tables = HuffmanTables::standard();
+    let luminance_order = subsampling_pack.block_order(PlotName::YsQuant3d, width / 8, height / 8);
+    let chrominance_orders = [
+        (&planes.cbs, PlotName::CbsQuant3d),
+        (&planes.crs, PlotName::CrsQuant3d),
+    ]
+    .map(|(plane, plot_name)| {
+        subsampling_pack.block_order(plot_name, plane.width / 8, plane.height / 8)
+    });
+    let luminance = Component::new(
+        &planes.ys,
+        &luminance_order,
+        transform,
+        1,
+        tables.luminance(),
+    );
+    let chrominance = [
+        (&planes.cbs, PlotName::CbsQuant3d, &chrominance_orders[0]),
+        (&planes.crs, PlotName::CrsQuant3d, &chrominance_orders[1]),
+    ]
+    .map(|(plane, plot_name, order)| {
+        let (horiz_mult, vert_mult) = subsampling_pack.mults(plot_name);
+        Component::new(
+            plane,
+            order,
+            transform,
+            horiz_mult * vert_mult,
+            tables.chrominance(),
+        )
+    });
+
+    // the subsampling loss doesn't depend on the quality
+    let subsampling_error = [
+        (&original.cbs, &planes.cbs, PlotName::CbsQuant3d),
+        (&original.crs, &planes.crs, PlotName::CrsQuant3d),
+    ]
+    .iter()
+    .map(|(original, subsampled, plot_name)| {
+        let (horiz_mult, vert_mult) = subsampling_pack.mults(*plot_name);
+        let upsampled = subsampled.upsample(horiz_mult, vert_mult, width, height);
+        metrics::mse(&original.data, &upsampled.data) * pixels as f64
+    })
+    .sum::<f64>();
+
+    (1..=100)
+        .map(|quality| {
+            let luminance_table = quality_scaling.scale(&LUMINANCE_QUANTIZATION_TABLE, quality);
+            let chrominance_table = quality_scaling.scale(&CHROMINANCE_QUANTIZATION_TABLE, quality);
+
+            let (mut bits, mut error) = luminance.code(&luminance_table, &quantizers.0);
+            for component in chrominance.iter() {
+                let (component_bits, component_error) =
+                    component.code(&chrominance_table, &quantizers.1);
+                bits += component_bits;
+                error += component_error;
+            }
+            RdPoint {
+                quality,
+                bits,
+                bits_per_pixel: bits as f64 / pixels as f64,
+                psnr: metrics::psnr((error + subsampling_error) / (3 * pixels) as f64),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{sweep, Component};
+    use crate::block::BlockMatrix;
+    use crate::codec::jpeg::entropy::{self, SymbolKind};
+    use crate::codec::jpeg::huffman::HuffmanTable;
+    use crate::dct::Reference;
+    use crate::image::{ColorTransform, Plane, YCbCrPlanes};
+    use crate::metrics;
+    use crate::quant::{
+        QualityScaling, QuantizationStrategy, Quantizer, LUMINANCE_QUANTIZATION_TABLE,
+    };
+    use crate::section::jpeg_visualization::model::SubsamplingPack;
+    use crate::section::jpeg_visualization::test_images::textured_plane;
+
+    fn textured_planes() -> YCbCrPlanes {
+        YCbCrPlanes {
+            ys: textured_plane(32, 32, 1),
+            cbs: textured_plane(32, 32, 2),
+            crs: textured_plane(32, 32, 3),
+            color_transform: ColorTransform::Bt601Full,
+        }
+    }
+
+    fn quantizers(strategy: QuantizationStrategy) -> (Quantizer, Quantizer) {
+        (
+            Quantizer::new(strategy, 50.0, &HuffmanTable::luminance_ac()),
+            Quantizer::new(strategy, 50.0, &HuffmanTable::chrominance_ac()),
+        )
+    }
+
+    #[test]
+    pub fn test_higher_quality_costs_more_bits() {
+        let planes = textured_planes();
+        let points = sweep(
+            &planes,
+            &planes,
+            &SubsamplingPack::default(),
+            &Reference,
+            QualityScaling::Ijg,
+            &quantizers(QuantizationStrategy::Truncate),
+        );
+
+        assert_eq!(points.len(), 100);
+        assert!(points[9].bits < points[49].bits && points[49].bits < points[89].bits);
+        assert!(points[9].psnr < points[49].psnr && points[49].psnr < points[89].psnr);
+
+        // rounding halves the largest error of every coefficient
+        let rounded = sweep(
+            &planes,
+            &planes,
+            &SubsamplingPack::default(),
+            &Reference,
+            QualityScaling::Ijg,
+            &quantizers(QuantizationStrategy::Round),
+        );
+        assert!(rounded[49].psnr > points[49].psnr);
+    }
+
+    #[test]
+    pub fn test_bits_match_block_symbols() {
+        let plane = textured_plane(32, 32, 1);
+        let order = (0..16).collect::<Vec<usize>>();
+        let (dc_table, ac_table) = (HuffmanTable::luminance_dc(), HuffmanTable::luminance_ac());
+        let component = Component::new(&plane, &order, &Reference, 1, (&dc_table, &ac_table));
+        let table = QualityScaling::Ijg.scale(&LUMINANCE_QUANTIZATION_TABLE, 75);
+        let quantizer = Quantizer::new(QuantizationStrategy::Trellis, 50.0, &ac_table);
+
+        let (dc_codes, ac_codes) = (dc_table.codes(), ac_table.codes());
+        let mut expected = 0;
+        let mut previous_dc = 0;
+        for block in BlockMatrix::from_plane(&plane)
+            .quantize(&table, &Reference, &quantizer)
+            .blocks
+            .iter()
+        {
+            for symbol in entropy::block_symbols(&block.0, previous_dc) {
+                let codes = match symbol.kind {
+                    SymbolKind::Dc => &dc_codes,
+                    _ => &ac_codes,
+                };
+                expected += (codes[symbol.huffman_value() as usize].length + symbol.size) as usize;
+            }
+            previous_dc = block.0[0][0];
+        }
+
+        assert_eq!(component.code(&table, &quantizer).0, expected);
+    }
+
+    #[test]
+    pub fn test_psnr_estimate_matches_recovered_image() {
+        // flat chroma is recovered exactly, so the whole estimated error comes from the luma
+        let flat = Plane::new(vec![128; 32 * 32], 32, 32);
+        let planes = YCbCrPlanes {
+            ys: textured_plane(32, 32, 1),
+            cbs: flat.clone(),
+            crs: flat,
+            color_transform: ColorTransform::Bt601Full,
+        };
+        let points = sweep(
+            &planes,
+            &planes,
+            &SubsamplingPack::default(),
+            &Reference,
+            QualityScaling::Ijg,
+            &quantizers(QuantizationStrategy::Truncate),
+        );
+
+        let table = QualityScaling::Ijg.scale(&LUMINANCE_QUANTIZATION_TABLE, 50);
+        let recovered = BlockMatrix::from_plane(&planes.ys)
+            .apply_quantization(&table, &Reference)
+            .undo_quantization(&table, &Reference)
+            .to_plane();
+        let mse = metrics::mse(&planes.ys.data, &recovered.data);
+
+        assert!((points[49].psnr - metrics::psnr(mse / 3.0)).abs() < 0.5);
+    }
+}
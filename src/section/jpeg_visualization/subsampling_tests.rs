@@ -2,31 +2,101 @@
 mod subsampling_test {
     use std::convert::TryFrom;
 
-    use crate::section::jpeg_visualization::model::SubsamplingPack;
+    use crate::section::jpeg_visualization::model::{PlotName, SubsamplingPack};
     use crate::section::jpeg_visualization::page::subsampled_index_for_recovery;
+    use crate::section::jpeg_visualization::utils::subsampled_window_size;
     use crate::BLOCK_SIZE;
 
     #[test]
     pub fn test_vert_horiz_mult_calc() {
-        let mut subsampling_pack: SubsamplingPack;
-
-        test_mults_calculated_properly(SubsamplingPack { j: 4, a: 4, b: 4 }, 1, 1);
-        test_mults_calculated_properly(SubsamplingPack { j: 4, a: 4, b: 0 }, 1, 2);
-        test_mults_calculated_properly(SubsamplingPack { j: 4, a: 2, b: 2 }, 2, 1);
-        test_mults_calculated_properly(SubsamplingPack { j: 4, a: 2, b: 0 }, 2, 2);
-        test_mults_calculated_properly(SubsamplingPack { j: 4, a: 1, b: 1 }, 4, 1);
+        test_mults_calculated_properly(SubsamplingPack::new(1, 1, 1, 1).unwrap(), 1, 1);
+        test_mults_calculated_properly(SubsamplingPack::new(1, 2, 1, 1).unwrap(), 1, 2);
+        test_mults_calculated_properly(SubsamplingPack::new(2, 1, 1, 1).unwrap(), 2, 1);
+        test_mults_calculated_properly(SubsamplingPack::new(2, 2, 1, 1).unwrap(), 2, 2);
+        test_mults_calculated_properly(SubsamplingPack::new(4, 1, 1, 1).unwrap(), 4, 1);
+        test_mults_calculated_properly(SubsamplingPack::new(4, 2, 1, 1).unwrap(), 4, 2);
+        test_mults_calculated_properly(SubsamplingPack::new(3, 1, 1, 1).unwrap(), 3, 1);
+        test_mults_calculated_properly(SubsamplingPack::new(1, 3, 1, 1).unwrap(), 1, 3);
+        // chroma sampled more than once per MCU
+        test_mults_calculated_properly(SubsamplingPack::new(2, 2, 1, 2).unwrap(), 2, 1);
+        test_mults_calculated_properly(SubsamplingPack::new(4, 1, 2, 1).unwrap(), 2, 1);
+        test_mults_calculated_properly(SubsamplingPack::new(1, 2, 1, 2).unwrap(), 1, 1);
 
         fn test_mults_calculated_properly(
             subsampling_pack: SubsamplingPack,
-            horiz_mult_expected: i8,
-            vert_mult_expected: i8,
+            horiz_mult_expected: usize,
+            vert_mult_expected: usize,
         ) {
-            let horiz_mult: i8 = (subsampling_pack.j / subsampling_pack.a);
-            let vert_mult: i8 = if subsampling_pack.b == 0 { 2_i8 } else { 1_i8 };
+            let expected = (horiz_mult_expected, vert_mult_expected);
+            assert_eq!(subsampling_pack.mults(PlotName::YsQuant3d), (1, 1));
+            assert_eq!(subsampling_pack.mults(PlotName::CbsQuant3d), expected);
+            assert_eq!(subsampling_pack.mults(PlotName::CrsQuant3d), expected);
+        }
+    }
+
+    #[test]
+    pub fn test_separate_chroma_factors() {
+        let subsampling_pack = SubsamplingPack::with_factors((2, 2), (1, 1), (2, 1)).unwrap();
+        assert!(!subsampling_pack.has_shared_chroma_factors());
+        assert_eq!(subsampling_pack.mults(PlotName::CbsQuant3d), (2, 2));
+        assert_eq!(subsampling_pack.mults(PlotName::CrsQuant3d), (1, 2));
+        assert_eq!(subsampling_pack.mcu_width(), 16);
+        assert_eq!(subsampling_pack.mcu_height(), 16);
+
+        let subsampling_pack = SubsamplingPack::with_factors((4, 1), (1, 1), (2, 1)).unwrap();
+        assert_eq!(subsampling_pack.mults(PlotName::CbsQuant3d), (4, 1));
+        assert_eq!(subsampling_pack.mults(PlotName::CrsQuant3d), (2, 1));
+
+        // every chroma component has to divide the luma
+        assert!(SubsamplingPack::with_factors((2, 2), (1, 1), (3, 1)).is_none());
+        assert!(SubsamplingPack::with_factors((4, 2), (1, 1), (1, 4)).is_none());
+        // more than 10 blocks in an MCU
+        assert!(SubsamplingPack::with_factors((4, 2), (1, 1), (2, 1)).is_none());
+        assert!(SubsamplingPack::with_factors((4, 2), (1, 1), (1, 1)).is_some());
+    }
 
-            assert_eq!(horiz_mult, horiz_mult_expected);
-            assert_eq!(vert_mult, vert_mult_expected);
+    #[test]
+    pub fn test_ratio_notation() {
+        let ratio = |luma_horiz, luma_vert, chroma_horiz, chroma_vert| {
+            SubsamplingPack::new(luma_horiz, luma_vert, chroma_horiz, chroma_vert)
+                .unwrap()
+                .ratio()
+        };
+
+        assert_eq!(ratio(1, 1, 1, 1), (4, 4, 4));
+        assert_eq!(ratio(2, 1, 1, 1), (4, 2, 2));
+        assert_eq!(ratio(2, 2, 1, 2), (4, 2, 2));
+        assert_eq!(ratio(1, 2, 1, 1), (4, 4, 0));
+        assert_eq!(ratio(2, 2, 1, 1), (4, 2, 0));
+        assert_eq!(ratio(4, 1, 2, 1), (4, 2, 2));
+        assert_eq!(ratio(4, 1, 1, 1), (4, 1, 1));
+        assert_eq!(ratio(4, 2, 1, 1), (4, 1, 0));
+        assert_eq!(ratio(3, 1, 1, 1), (3, 1, 1));
+    }
+
+    #[test]
+    pub fn test_illegal_sampling_factors() {
+        for preset in SubsamplingPack::PRESETS.iter() {
+            assert_eq!(
+                SubsamplingPack::new(
+                    preset.luma_horiz,
+                    preset.luma_vert,
+                    preset.cb_horiz,
+                    preset.cb_vert
+                ),
+                Some(*preset)
+            );
         }
+        // factors out of range
+        assert!(SubsamplingPack::new(0, 1, 1, 1).is_none());
+        assert!(SubsamplingPack::new(5, 1, 1, 1).is_none());
+        // chroma not derivable from luma by an integer factor
+        assert!(SubsamplingPack::new(3, 1, 2, 1).is_none());
+        assert!(SubsamplingPack::new(1, 1, 2, 1).is_none());
+        // more than 10 blocks in an MCU
+        assert!(SubsamplingPack::new(4, 4, 1, 1).is_none());
+        assert!(SubsamplingPack::new(4, 2, 2, 2).is_none());
+        assert!(SubsamplingPack::new(4, 2, 1, 1).is_some());
     }
 
     #[test]
@@ -218,4 +288,73 @@ mod subsampling_test {
             blk
         );
     }
+
+    #[test]
+    pub fn test_recovery_index_3_1_1() {
+        let horiz_mult: usize = 3;
+        let vert_mult: usize = 1;
+
+        let blk: usize = BLOCK_SIZE as usize;
+        // the subsampled window is rounded up to three whole blocks
+        let row = subsampled_window_size(horiz_mult);
+        assert_eq!(row, 24);
+
+        assert_eq!(subsampled_index_for_recovery(0, horiz_mult, vert_mult), 0);
+        assert_eq!(subsampled_index_for_recovery(2, horiz_mult, vert_mult), 0);
+        assert_eq!(subsampled_index_for_recovery(3, horiz_mult, vert_mult), 1);
+        assert_eq!(
+            subsampled_index_for_recovery(blk - 1, horiz_mult, vert_mult),
+            21
+        );
+        assert_eq!(
+            subsampled_index_for_recovery(blk, horiz_mult, vert_mult),
+            row
+        );
+        assert_eq!(
+            subsampled_index_for_recovery(2 * blk + 4, horiz_mult, vert_mult),
+            2 * row + 1
+        );
+    }
+
+    #[test]
+    pub fn test_recovery_index_4_1_0() {
+        let horiz_mult: usize = 4;
+        let vert_mult: usize = 2;
+
+        let blk: usize = BLOCK_SIZE as usize;
+
+        assert_eq!(subsampled_index_for_recovery(3, horiz_mult, vert_mult), 0);
+        assert_eq!(subsampled_index_for_recovery(4, horiz_mult, vert_mult), 1);
+        assert_eq!(
+            subsampled_index_for_recovery(blk + 4, horiz_mult, vert_mult),
+            1
+        );
+        assert_eq!(
+            subsampled_index_for_recovery(2 * blk, horiz_mult, vert_mult),
+            blk / 4
+        );
+        assert_eq!(
+            subsampled_index_for_recovery(3 * blk + 8, horiz_mult, vert_mult),
+            blk / 4 + 2
+        );
+    }
+
+    #[test]
+    pub fn test_recovery_index_1_3() {
+        let horiz_mult: usize = 1;
+        let vert_mult: usize = 3;
+
+        let blk: usize = BLOCK_SIZE as usize;
+
+        assert_eq!(subsampled_index_for_recovery(5, horiz_mult, vert_mult), 5);
+        assert_eq!(
+            subsampled_index_for_recovery(2 * blk + 5, horiz_mult, vert_mult),
+            5
+        );
+        assert_eq!(
+            subsampled_index_for_recovery(3 * blk + 5, horiz_mult, vert_mult),
+            blk + 5
+        );
+        assert_eq!(subsampled_window_size(vert_mult), 24);
+    }
 }
@@ -0,0 +1,23 @@
+use crate::image::{Plane, RawImage};
+
+/// Gradient with noise from a linear congruential generator, so the blocks have energy in
+/// both the low and the high frequencies
+pub fn textured_plane(width: usize, height: usize, seed: u32) -> Plane {
+    let mut state = seed;
+    let data = (0..width * height)
+        .map(|i| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (((i % width) * 3 + (i / width) * 2) as u32 + (state >> 16) % 64) as u8
+        })
+        .collect::<Vec<u8>>();
+    Plane::new(data, width, height)
+}
+
+/// Opaque RGBA image with a differently seeded textured plane in each channel
+pub fn textured_image(width: usize, height: usize) -> RawImage {
+    let [red, green, blue] = [1, 2, 3].map(|seed| textured_plane(width, height, seed));
+    let data = (0..width * height)
+        .flat_map(|i| [red.data[i], green.data[i], blue.data[i], 255])
+        .collect::<Vec<u8>>();
+    RawImage::new(data, width as u32, height as u32)
+}
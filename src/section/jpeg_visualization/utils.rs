@@ -1,11 +1,16 @@
 use seed::JsFuture;
+use wasm_bindgen::closure::Closure;
 use wasm_bindgen::JsCast;
 
 use crate::image;
-use crate::section::jpeg_visualization::model::SubsamplingPack;
+use crate::BLOCK_SIZE;
 use std::cmp;
 use web_sys::HtmlCanvasElement;
 
+// delay before the object URL of a download is revoked in milliseconds, the browser reads it
+// only after the click has been handled
+const DOWNLOAD_URL_LIFETIME: i32 = 1000;
+
 pub(super) async fn load_image(file_blob: gloo_file::Blob) -> image::RawImage {
     let url_data = gloo_file::futures::read_as_data_url(&file_blob)
         .await
@@ -74,13 +79,32 @@ pub fn create_tmp_canvas() -> HtmlCanvasElement {
         .unwrap();
 }
 
-pub fn horiz_mult_from_subsampling(subsampling_pack: &SubsamplingPack) -> usize {
-    (subsampling_pack.j / subsampling_pack.a) as usize
+pub fn download_bytes(bytes: &[u8], mime_type: &str, file_name: &str) {
+    let blob = gloo_file::Blob::new_with_options(bytes, Some(mime_type));
+    let url = web_sys::Url::create_object_url_with_blob(blob.as_ref()).unwrap();
+    let anchor = web_sys::window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .create_element("a")
+        .unwrap()
+        .dyn_into::<web_sys::HtmlElement>()
+        .unwrap();
+    anchor.set_attribute("href", &url).unwrap();
+    anchor.set_attribute("download", file_name).unwrap();
+    anchor.click();
+    let revoke = Closure::once_into_js(move || web_sys::Url::revoke_object_url(&url).unwrap());
+    web_sys::window()
+        .unwrap()
+        .set_timeout_with_callback_and_timeout_and_arguments_0(
+            revoke.unchecked_ref(),
+            DOWNLOAD_URL_LIFETIME,
+        )
+        .unwrap();
 }
-pub fn vert_mult_from_subsampling(subsampling_pack: &SubsamplingPack) -> usize {
-    if subsampling_pack.b == 0 {
-        return 2_usize;
-    } else {
-        return 1_usize;
-    }
+
+/// Side of the subsampled image window, rounded up to whole blocks when the multiplier
+/// doesn't divide the window
+pub fn subsampled_window_size(mult: usize) -> usize {
+    8 * (BLOCK_SIZE as usize / 8).div_ceil(mult)
 }
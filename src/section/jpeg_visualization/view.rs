@@ -2,11 +2,30 @@ use seed::prelude::web_sys::MouseEvent;
 use seed::prelude::*;
 use seed::*;
 
-use super::model::{CanvasName, Model, Msg, PreviewCanvasName, State};
-use super::page::wrap;
+use super::heatmap::Heatmap;
+use super::mcu::{self, McuBlock};
+use super::model::{
+    CanvasName, FullImageCanvasName, HeatmapCanvasName, HuffmanComparison, ImagePack, Model, Msg,
+    PreviewCanvasName, QuantizationTableName, ReconstructionCanvasName, State,
+};
+use super::page::{chosen_block_indices, chosen_block_samples, wrap, GENERATION_THUMBNAIL_WIDTH};
+use super::utils::subsampled_window_size;
+use crate::block::Block;
+use crate::codec::jpeg::constants::ZIG_ZAG;
+use crate::codec::jpeg::entropy::{self, SymbolKind};
+use crate::codec::jpeg::huffman::{HuffmanCode, HuffmanTable};
+use crate::codec::jpeg::lossless::Predictor;
+use crate::codec::jpeg::segments::{Segment, SegmentContent};
+use crate::dct::{self, DctAlgorithm};
 use crate::graphic_helpers::drag_n_drop::*;
+use crate::image::{
+    ChromaSiting, ColorTransform, DeblockingFilter, DownsamplingFilter, UpsamplingFilter,
+};
+use crate::metrics;
+use crate::quant::{self, QualityScaling, QuantizationPreset, QuantizationStrategy};
 use crate::section::jpeg_visualization::model::{PlotName, SubsamplingPack};
 use crate::{Msg as GMsg, BLOCK_SIZE};
+use strum::IntoEnumIterator;
 use web_sys::{Event, HtmlCanvasElement, HtmlImageElement};
 
 macro_rules! stop_and_prevent {
@@ -69,6 +88,47 @@ pub fn view_image_preview(model: &Model) -> Node<GMsg> {
     ]
 }
 
+/// Error of the colour conversion alone, the image is converted with every transform and back
+fn view_color_round_trip(model: &Model) -> Node<GMsg> {
+    let pack = match &model.state {
+        State::ImageView(pack) => pack,
+        _ => return empty![],
+    };
+
+    div![
+        C!["image_view color_conversion"],
+        details![
+            summary!["Colour conversion round trip"],
+            table![
+                C!["block-content color_round_trip"],
+                caption!["RGB converted to the colour space and back, without subsampling and quantization"],
+                tr![
+                    th!["Transform"],
+                    th!["Max error"],
+                    th!["Changed pixels"],
+                    th!["PSNR"],
+                ],
+                pack.color_round_trips
+                    .iter()
+                    .map(|(color_transform, round_trip)| {
+                        tr![
+                            IF!(*color_transform == model.color_transform => C!["active"]),
+                            th![color_transform.name()],
+                            td![round_trip.max_error.to_string()],
+                            td![format!("{:.2}%", round_trip.changed_pixels)],
+                            td![if round_trip.psnr.is_finite() {
+                                format!("{:.2} dB", round_trip.psnr)
+                            } else {
+                                "lossless".to_string()
+                            }],
+                        ]
+                    })
+            ],
+            p!["YCoCg-R needs 9 bits for its chroma, with 8 the values wrap around, so it can't be chosen for the compression"],
+        ]
+    ]
+}
+
 pub fn view_ycbcr(model: &Model) -> Node<GMsg> {
     div![
         C!["image_view"],
@@ -98,20 +158,781 @@ pub fn view_ycbcr(model: &Model) -> Node<GMsg> {
                 "CB",
                 &model.canvas_map.get(&CanvasName::Cbs).unwrap(),
                 &model.overlay_map.get(&CanvasName::Cbs).unwrap(),
-                Some(&model.subsampling_pack),
+                Some((&model.subsampling_pack, PlotName::CbsQuant3d)),
                 model.zoom
             ),
             canvas_labeled_div_with_overlay(
                 "CR",
                 &model.canvas_map.get(&CanvasName::Crs).unwrap(),
                 &model.overlay_map.get(&CanvasName::Crs).unwrap(),
-                Some(&model.subsampling_pack),
+                Some((&model.subsampling_pack, PlotName::CrsQuant3d)),
                 model.zoom
             ),
         ]
     ]
 }
 
+pub fn view_scan_stepper(model: &Model) -> Node<GMsg> {
+    let pack = match &model.state {
+        State::ImageView(pack) if model.use_source_coefficients && pack.source_scans.len() > 1 => {
+            pack
+        }
+        _ => return empty![],
+    };
+    let scan_count = pack.source_scan_count;
+    let scan = &pack.source_scans[scan_count - 1];
+    let component_names = scan
+        .components
+        .iter()
+        .map(|index| ["Y", "Cb", "Cr"][*index])
+        .collect::<Vec<&str>>()
+        .join(", ");
+    let bit_planes = if scan.is_refinement() {
+        format!("bit {} (refinement)", scan.successive_low)
+    } else if scan.successive_low > 0 {
+        format!("bits {} and above (first pass)", scan.successive_low)
+    } else {
+        "all bits".to_string()
+    };
+    let band = scan.spectral_start as usize..=scan.spectral_end as usize;
+
+    div![
+        C!["image_view"],
+        details![
+            summary!["Progressive scans"],
+            attrs![At::Open => AtValue::None],
+            div![
+                C!["scan_stepper"],
+                button![
+                    "Previous scan",
+                    attrs! {At::Disabled => (scan_count <= 1).as_at_value()},
+                    ev(Ev::Click, move |_| wrap(Msg::SourceScanChanged(
+                        scan_count - 1
+                    ))),
+                ],
+                label![format!(
+                    "Scan {} of {}",
+                    scan_count,
+                    pack.source_scans.len()
+                )],
+                button![
+                    "Next scan",
+                    attrs! {At::Disabled => (scan_count >= pack.source_scans.len()).as_at_value()},
+                    ev(Ev::Click, move |_| wrap(Msg::SourceScanChanged(
+                        scan_count + 1
+                    ))),
+                ],
+            ],
+            div![
+                C!["scan_info"],
+                p![format!("Components: {}", component_names)],
+                p![format!(
+                    "Coefficients: {} to {} in zig-zag order",
+                    scan.spectral_start, scan.spectral_end
+                )],
+                p![format!("Bit planes: {}", bit_planes)],
+                table![
+                    C!["block-content"],
+                    caption!["Coefficients refined by the scan"],
+                    (0..8).map(|row| {
+                        tr![(0..8).map(|col| {
+                            let zig_zag_index = ZIG_ZAG
+                                .iter()
+                                .position(|natural| *natural == row * 8 + col)
+                                .unwrap();
+                            td![
+                                IF!(band.contains(&zig_zag_index) => C!["refined"]),
+                                zig_zag_index.to_string()
+                            ]
+                        })]
+                    })
+                ],
+            ],
+        ]
+    ]
+}
+
+/// Returns the chosen block of the channel and the block coded right before it in the whole image,
+/// the DC coefficient of the latter predicts the DC of the chosen block
+fn chosen_block_with_predecessor<'a>(
+    model: &Model,
+    pack: &'a ImagePack,
+) -> Option<(&'a Block, Option<&'a Block>)> {
+    let block_matrix = pack.quantized.get(&model.entropy_channel)?;
+    let subsampling_pack = &model.subsampling_pack;
+    let (_, index) = chosen_block_indices(pack, model.entropy_channel, subsampling_pack);
+
+    let order = subsampling_pack.block_order(
+        model.entropy_channel,
+        block_matrix.width,
+        block_matrix.height,
+    );
+    let position = order.iter().position(|i| *i == index)?;
+    let predecessor = position
+        .checked_sub(1)
+        .map(|previous| &block_matrix.blocks[order[previous]]);
+    Some((&block_matrix.blocks[index], predecessor))
+}
+
+fn format_bits(bits: u16, length: u8) -> String {
+    if length == 0 {
+        String::new()
+    } else {
+        format!("{:0width$b}", bits, width = length as usize)
+    }
+}
+
+pub fn view_entropy_coding(model: &Model) -> Node<GMsg> {
+    let pack = match &model.state {
+        State::ImageView(pack) => pack,
+        _ => return empty![],
+    };
+    let (block, predecessor) = match chosen_block_with_predecessor(model, pack) {
+        Some(blocks) => blocks,
+        None => return empty![],
+    };
+    // the tables the downloaded file is written with
+    let tables = &pack.huffman_tables;
+    let (dc_table, ac_table) = match model.entropy_channel {
+        PlotName::YsQuant3d => tables.luminance(),
+        _ => tables.chrominance(),
+    };
+    let (dc_codes, ac_codes) = (dc_table.codes(), ac_table.codes());
+
+    let previous_dc = predecessor.map_or(0, |previous| previous.0[0][0]);
+    let coefficients = entropy::zig_zag(&block.0);
+    let symbols = entropy::block_symbols(&block.0, previous_dc);
+    let codes = symbols
+        .iter()
+        .map(|symbol| match symbol.kind {
+            SymbolKind::Dc => dc_codes[symbol.huffman_value() as usize],
+            _ => ac_codes[symbol.huffman_value() as usize],
+        })
+        .collect::<Vec<HuffmanCode>>();
+    let total_bits: usize = symbols
+        .iter()
+        .zip(codes.iter())
+        .map(|(symbol, code)| (code.length + symbol.size) as usize)
+        .sum();
+
+    // the chosen symbol covers the zero run before its coefficient
+    let chosen_symbol = pack.chosen_symbol.filter(|index| *index < symbols.len());
+    let (covered, coded) = match chosen_symbol {
+        Some(index) => {
+            let symbol = &symbols[index];
+            let first = if index == 0 {
+                0
+            } else {
+                symbols[index - 1].zig_zag_index + 1
+            };
+            let coded = match symbol.kind {
+                SymbolKind::Dc | SymbolKind::Ac => Some(symbol.zig_zag_index),
+                _ => None,
+            };
+            (Some(first..=symbol.zig_zag_index), coded)
+        }
+        None => (None, None),
+    };
+    let coefficient_class = |zig_zag_index: usize| {
+        if coded == Some(zig_zag_index) {
+            "coded"
+        } else if covered
+            .as_ref()
+            .is_some_and(|covered| covered.contains(&zig_zag_index))
+        {
+            "skipped"
+        } else {
+            ""
+        }
+    };
+
+    div![
+        C!["image_view"],
+        details![
+            summary!["Entropy coding of the chosen block"],
+            div![
+                C!["entropy_coding"],
+                div![
+                    C!["entropy_channels"],
+                    [
+                        ("Y", PlotName::YsQuant3d),
+                        ("Cb", PlotName::CbsQuant3d),
+                        ("Cr", PlotName::CrsQuant3d)
+                    ]
+                    .iter()
+                    .map(|(name, plot_name)| {
+                        let plot_name = *plot_name;
+                        button![
+                            name,
+                            IF!(plot_name == model.entropy_channel => C!["active"]),
+                            ev(Ev::Click, move |_| wrap(Msg::EntropyChannelChanged(
+                                plot_name
+                            ))),
+                        ]
+                    }),
+                ],
+                div![
+                    C!["entropy_steps"],
+                    h4!["1. Zig-zag sequence"],
+                    div![
+                        C!["zig_zag_sequence"],
+                        coefficients.iter().enumerate().map(|(i, coefficient)| {
+                            span![C![coefficient_class(i)], coefficient.to_string()]
+                        }),
+                    ],
+                    h4!["2. DC difference"],
+                    p![format!(
+                        "{} - {} (DC of the previous block) = {}",
+                        coefficients[0],
+                        previous_dc,
+                        coefficients[0] - previous_dc
+                    )],
+                    h4!["3. Symbols and Huffman codewords"],
+                    table![
+                        C!["entropy_symbols"],
+                        tr![
+                            th!["Symbol"],
+                            th!["(run, size)"],
+                            th!["Amplitude"],
+                            th!["Codeword"],
+                            th!["Amplitude bits"],
+                            th!["Bits"],
+                        ],
+                        symbols
+                            .iter()
+                            .zip(codes.iter())
+                            .enumerate()
+                            .map(|(i, (symbol, code))| {
+                                tr![
+                                    IF!(chosen_symbol == Some(i) => C!["chosen"]),
+                                    td![match symbol.kind {
+                                        SymbolKind::Dc => "DC",
+                                        SymbolKind::Ac => "AC",
+                                        SymbolKind::ZeroRun => "ZRL",
+                                        SymbolKind::EndOfBlock => "EOB",
+                                    }],
+                                    td![format!("({}, {})", symbol.run, symbol.size)],
+                                    td![symbol.amplitude.to_string()],
+                                    td![format_bits(code.code, code.length)],
+                                    td![format_bits(symbol.amplitude_bits(), symbol.size)],
+                                    td![(code.length + symbol.size).to_string()],
+                                    ev(Ev::Click, move |_| wrap(Msg::EntropySymbolChosen(i))),
+                                ]
+                            }),
+                    ],
+                    h4!["4. Total"],
+                    p![format!(
+                        "{} symbols, {} bits (the block holds {} bits before compression)",
+                        symbols.len(),
+                        total_bits,
+                        64 * 8
+                    )],
+                ],
+                table![
+                    C!["block-content"],
+                    caption!["Quantized coefficients"],
+                    (0..8).map(|row| {
+                        tr![(0..8).map(|col| {
+                            let zig_zag_index = ZIG_ZAG
+                                .iter()
+                                .position(|natural| *natural == row * 8 + col)
+                                .unwrap();
+                            td![
+                                C![coefficient_class(zig_zag_index)],
+                                block.0[row][col].to_string()
+                            ]
+                        })]
+                    })
+                ],
+            ],
+        ]
+    ]
+}
+
+/// Bits of the whole scan with the Annex K tables and with the tables built for the image
+fn view_huffman_optimization(model: &Model) -> Node<GMsg> {
+    let pack = match &model.state {
+        State::ImageView(pack) if !pack.huffman_comparisons.is_empty() => pack,
+        _ => return empty![],
+    };
+    let saving = |standard: u64, optimal: u64| {
+        format!(
+            "{} ({:.1}%)",
+            standard as i64 - optimal as i64,
+            100.0 * (standard as f64 - optimal as f64) / standard.max(1) as f64
+        )
+    };
+    // every table is written with its class and id, 16 counts and its values
+    let definition_bits =
+        |table: &HuffmanTable| -> u64 { 8 * (1 + 16 + table.values.len() as u64) };
+    let comparisons = &pack.huffman_comparisons;
+    let standard_total = pack.amplitude_bits
+        + comparisons
+            .iter()
+            .map(|comparison| comparison.standard_bits + definition_bits(&comparison.standard))
+            .sum::<u64>();
+    let optimal_total = pack.amplitude_bits
+        + comparisons
+            .iter()
+            .map(|comparison| comparison.optimal_bits + definition_bits(&comparison.optimal))
+            .sum::<u64>();
+
+    div![
+        C!["image_view"],
+        details![
+            summary!["Huffman table optimization"],
+            table![
+                C!["block-content huffman_comparison"],
+                tr![
+                    th!["Table"],
+                    th!["Annex K bits"],
+                    th!["Optimal bits"],
+                    th!["Saved bits"]
+                ],
+                comparisons.iter().map(|comparison| {
+                    tr![
+                        th![comparison.name],
+                        td![comparison.standard_bits.to_string()],
+                        td![comparison.optimal_bits.to_string()],
+                        td![saving(comparison.standard_bits, comparison.optimal_bits)],
+                    ]
+                }),
+                tr![
+                    th!["Amplitude bits"],
+                    td![pack.amplitude_bits.to_string()],
+                    td![pack.amplitude_bits.to_string()],
+                    td!["0"],
+                ],
+                tr![
+                    th!["DHT segment"],
+                    td![comparisons
+                        .iter()
+                        .map(|comparison| definition_bits(&comparison.standard))
+                        .sum::<u64>()
+                        .to_string()],
+                    td![comparisons
+                        .iter()
+                        .map(|comparison| definition_bits(&comparison.optimal))
+                        .sum::<u64>()
+                        .to_string()],
+                    td![],
+                ],
+                tr![
+                    C!["total"],
+                    th!["Total"],
+                    td![format!(
+                        "{} ({} B)",
+                        standard_total,
+                        standard_total.div_ceil(8)
+                    )],
+                    td![format!(
+                        "{} ({} B)",
+                        optimal_total,
+                        optimal_total.div_ceil(8)
+                    )],
+                    td![saving(standard_total, optimal_total)],
+                ],
+            ],
+            div![
+                C!["code_length_histograms"],
+                comparisons.iter().map(view_code_length_histogram),
+            ],
+        ]
+    ]
+}
+
+/// Number of codes of every length in both tables, the bars share one scale
+fn view_code_length_histogram(comparison: &HuffmanComparison) -> Node<GMsg> {
+    let max_count = comparison
+        .standard
+        .bits
+        .iter()
+        .chain(comparison.optimal.bits.iter())
+        .copied()
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let bar = |class: &str, count: u8| {
+        td![
+            C!["histogram_bar"],
+            div![
+                C![class],
+                style![St::Width => format!("{:.1}%", 100.0 * count as f64 / max_count as f64)],
+            ],
+            span![count.to_string()],
+        ]
+    };
+
+    table![
+        C!["code_length_histogram"],
+        caption![comparison.name],
+        tr![th!["Length"], th!["Annex K"], th!["Optimal"]],
+        (0..16).map(|i| {
+            tr![
+                th![(i + 1).to_string()],
+                bar("standard", comparison.standard.bits[i]),
+                bar("optimal", comparison.optimal.bits[i]),
+            ]
+        }),
+    ]
+}
+
+/// 8x8 table of the differences between the result of the chosen transform and the reference one
+fn transform_error_table(
+    caption: &str,
+    result: &[[i16; 8]; 8],
+    reference: &[[i16; 8]; 8],
+) -> Node<GMsg> {
+    let max_error = result
+        .iter()
+        .flatten()
+        .zip(reference.iter().flatten())
+        .map(|(value, reference)| (value - reference).abs())
+        .max()
+        .unwrap_or(0);
+
+    table![
+        C!["block-content transform_errors"],
+        caption![format!("{} (max error {})", caption, max_error)],
+        (0..8).map(|row| {
+            tr![(0..8).map(|col| {
+                let error = result[row][col] - reference[row][col];
+                td![IF!(error != 0 => C!["error"]), error.to_string()]
+            })]
+        })
+    ]
+}
+
+/// The 64 basis functions and the chosen Y block rebuilt from the first coefficients
+/// in the zig-zag order, the canvases are drawn by the page
+pub fn view_basis_explorer(model: &Model) -> Node<GMsg> {
+    let pack = match &model.state {
+        State::ImageView(pack) => pack,
+        _ => return empty![],
+    };
+    let kept = model.coefficients_kept;
+    let original = chosen_block_samples(pack);
+    let coefficients = dct::spatial_to_freq(&original);
+    let partial = dct::partial_freq_to_spatial(&coefficients, kept);
+    let mse = metrics::mse(
+        &original
+            .iter()
+            .flatten()
+            .map(|x| *x as u8)
+            .collect::<Vec<u8>>(),
+        &partial
+            .iter()
+            .flatten()
+            .map(|x| *x as u8)
+            .collect::<Vec<u8>>(),
+    );
+    let last_added = match kept {
+        0 => "No coefficients, the block is flat middle gray".to_string(),
+        _ => {
+            let natural = ZIG_ZAG[kept - 1];
+            format!(
+                "Last added: coefficient {} in the zig-zag order, u = {}, v = {}, value {}",
+                kept - 1,
+                natural % 8,
+                natural / 8,
+                coefficients[natural / 8][natural % 8]
+            )
+        }
+    };
+    let reconstruction_canvas = |label: &str, canvas_name: ReconstructionCanvasName| {
+        div![
+            C!["labeled_canvas_wrapper"],
+            label![C!["canvas_label"], label],
+            canvas![
+                el_ref(model.reconstruction_canvas_map.get(&canvas_name).unwrap()),
+                attrs! {
+                    At::Width => px(128),
+                    At::Height => px(128),
+                },
+            ],
+        ]
+    };
+
+    div![
+        C!["image_view"],
+        details![
+            summary!["DCT basis explorer"],
+            div![
+                C!["basis_explorer"],
+                div![
+                    label![C!["canvas_label"], "BASIS FUNCTIONS"],
+                    canvas![
+                        el_ref(&model.basis_canvas),
+                        attrs! {
+                            At::Width => px(355),
+                            At::Height => px(355),
+                        },
+                    ],
+                ],
+                div![
+                    C!["basis_reconstruction"],
+                    label![
+                        attrs! {At::For => "coefficients_kept"},
+                        format!("Coefficients kept: {}", kept)
+                    ],
+                    input![
+                        attrs! {
+                            At::Type => "range",
+                            At::Min => 0,
+                            At::Max => 64,
+                            At::Value => kept,
+                            At::Id => "coefficients_kept",
+                        },
+                        input_ev(Ev::Input, |value| {
+                            wrap(Msg::CoefficientsKeptChanged(
+                                value.parse::<usize>().unwrap(),
+                            ))
+                        })
+                    ],
+                    p![last_added],
+                    p![if mse > 0.0 {
+                        format!("MSE {:.2}, PSNR {:.2} dB", mse, metrics::psnr(mse))
+                    } else {
+                        "The block is rebuilt exactly".to_string()
+                    }],
+                    div![
+                        C!["block-canvas-list"],
+                        reconstruction_canvas("ORIGINAL", ReconstructionCanvasName::Original),
+                        reconstruction_canvas("PARTIAL", ReconstructionCanvasName::Partial),
+                        reconstruction_canvas("ERROR (x4)", ReconstructionCanvasName::Error),
+                    ],
+                ],
+            ],
+        ]
+    ]
+}
+
+/// Block grids of the components under the image window with their MCUs, the animation
+/// marks the blocks in the order they are written into the scan
+pub fn view_mcu_interleaving(model: &Model) -> Node<GMsg> {
+    let pack = match &model.state {
+        State::ImageView(pack) => pack,
+        _ => return empty![],
+    };
+    let subsampling_pack = &model.subsampling_pack;
+    let mcus = mcu::window_mcus(subsampling_pack);
+    let chosen_mcu = mcu::mcu_of_block(
+        subsampling_pack,
+        0,
+        pack.chosen_block_x as usize / 8,
+        pack.chosen_block_y as usize / 8,
+    );
+
+    // every block with its MCU and its position in the scan
+    let mut emitted = Vec::<(usize, usize, McuBlock)>::new();
+    for mcu in mcus.iter() {
+        for block in mcu.blocks.iter() {
+            emitted.push((mcu.x, mcu.y, *block));
+        }
+    }
+    let block_count = emitted.len();
+    let current_step = model.mcu_animation_step.filter(|step| *step < block_count);
+    let block_class = |step: usize, mcu_x: usize, mcu_y: usize| {
+        C![
+            IF!((mcu_x + mcu_y) % 2 == 1 => "mcu_shade"),
+            IF!((mcu_x, mcu_y) == chosen_mcu => "chosen"),
+            IF!(current_step.is_some_and(|current| step < current) => "emitted"),
+            IF!(current_step == Some(step) => "current"),
+        ]
+    };
+
+    let grid = |component: usize| {
+        let (width, height) = mcu::window_blocks(subsampling_pack, component);
+        let mut cells = vec![None; width * height];
+        for (step, (mcu_x, mcu_y, block)) in emitted.iter().enumerate() {
+            if block.component == component {
+                cells[block.u + block.v * width] = Some((step, *mcu_x, *mcu_y, *block));
+            }
+        }
+        div![
+            label![["Y", "Cb", "Cr"][component]],
+            table![
+                C!["mcu_grid"],
+                cells.chunks(width).map(|row| {
+                    tr![row.iter().flatten().map(|(step, mcu_x, mcu_y, block)| {
+                        let (mcu_x, mcu_y) = (*mcu_x, *mcu_y);
+                        td![
+                            block_class(*step, mcu_x, mcu_y),
+                            block.name(),
+                            ev(Ev::Click, move |_| wrap(Msg::McuChosen(mcu_x, mcu_y))),
+                        ]
+                    })]
+                })
+            ]
+        ]
+    };
+
+    div![
+        C!["image_view"],
+        details![
+            summary!["MCU interleaving"],
+            div![
+                C!["mcu_controls"],
+                button![
+                    if model.mcu_animation.is_some() {
+                        "Pause"
+                    } else {
+                        "Play"
+                    },
+                    ev(Ev::Click, |_| wrap(Msg::McuAnimationToggled)),
+                ],
+                span![match current_step {
+                    Some(step) => format!("Block {} of {} in the scan", step + 1, block_count),
+                    None => format!(
+                        "{} Y, {} Cb and {} Cr blocks in every MCU, click an MCU to choose its blocks",
+                        subsampling_pack.luma_horiz * subsampling_pack.luma_vert,
+                        subsampling_pack.cb_horiz * subsampling_pack.cb_vert,
+                        subsampling_pack.cr_horiz * subsampling_pack.cr_vert
+                    ),
+                }],
+            ],
+            div![C!["mcu_grids"], grid(0), grid(1), grid(2)],
+            div![
+                C!["mcu_sequence"],
+                emitted
+                    .iter()
+                    .enumerate()
+                    .map(|(step, (mcu_x, mcu_y, block))| {
+                        span![
+                            block_class(step, *mcu_x, *mcu_y),
+                            IF!(block.component == 0 && block.index == 0 => C!["mcu_start"]),
+                            block.name()
+                        ]
+                    }),
+            ],
+        ]
+    ]
+}
+
+/// Compares the chosen transform with the reference one on the chosen luminance block,
+/// the forward DCT gets the input samples and the inverse DCT the dequantized coefficients
+pub fn view_dct_accuracy(model: &Model) -> Node<GMsg> {
+    let pack = match &model.state {
+        State::ImageView(pack) => pack,
+        _ => return empty![],
+    };
+    let quantized = match pack.plot_data.get(&PlotName::YsQuant3d) {
+        Some(quantized) => quantized,
+        None => return empty![],
+    };
+    let x = pack.chosen_block_x as usize;
+    let y = pack.chosen_block_y as usize;
+
+    let samples = chosen_block_samples(pack);
+    let mut dequantized = quantized.blocks[x / 8 + (y / 8) * quantized.width].0;
+    quant::undo_quantization(&mut dequantized, &model.scaled_luminance_quant_table);
+
+    let transform = model.dct_algorithm.transform();
+    let reference = DctAlgorithm::Reference.transform();
+
+    div![
+        C!["image_view"],
+        details![
+            summary!["DCT accuracy"],
+            div![
+                C!["dct_accuracy"],
+                p![format!(
+                    "{} compared with the reference transform on the chosen Y block",
+                    model.dct_algorithm.name()
+                )],
+                transform_error_table(
+                    "Forward DCT coefficients",
+                    &transform.spatial_to_freq(&samples),
+                    &reference.spatial_to_freq(&samples),
+                ),
+                transform_error_table(
+                    "Inverse DCT samples",
+                    &transform.freq_to_spatial(&dequantized),
+                    &reference.freq_to_spatial(&dequantized),
+                ),
+            ],
+        ]
+    ]
+}
+
+pub fn view_coefficient_editor(model: &Model) -> Node<GMsg> {
+    let pack = match &model.state {
+        State::ImageView(pack) if !pack.plot_data.is_empty() => pack,
+        _ => return empty![],
+    };
+    let edit_count = pack.coefficient_edits.len();
+
+    div![
+        C!["image_view"],
+        details![
+            summary!["Coefficient editing"],
+            p!["Quantized coefficients of the chosen block, a changed value is used for the whole image"],
+            div![
+                C!["row_of_columns"],
+                view_block_coefficients_editor(model, pack, "Y", PlotName::YsQuant3d),
+                view_block_coefficients_editor(model, pack, "Cb", PlotName::CbsQuant3d),
+                view_block_coefficients_editor(model, pack, "Cr", PlotName::CrsQuant3d),
+            ],
+            button![
+                format!("Reset edits ({})", edit_count),
+                attrs! {At::Disabled => (edit_count == 0).as_at_value()},
+                ev(Ev::Click, |_| wrap(Msg::CoefficientEditsReset)),
+            ],
+        ]
+    ]
+}
+
+fn view_block_coefficients_editor(
+    model: &Model,
+    pack: &ImagePack,
+    caption: &str,
+    plot_name: PlotName,
+) -> Node<GMsg> {
+    let (window_index, block_index) =
+        chosen_block_indices(pack, plot_name, &model.subsampling_pack);
+    let block = pack.plot_data.get(&plot_name).unwrap().blocks[window_index].0;
+    let quant_table = match plot_name {
+        PlotName::YsQuant3d => model.scaled_luminance_quant_table,
+        _ => model.scaled_chrominance_quant_table,
+    };
+
+    table![
+        C!["block-content coefficient_editor"],
+        caption![caption],
+        (0..8).map(|row| {
+            let coefficient_index = row * 8;
+            tr![(0..8).map(|col| {
+                let coefficient_index = coefficient_index + col;
+                let limit =
+                    quant::editable_coefficient_limit(coefficient_index, quant_table[row][col]);
+                let edited = pack.coefficient_edits.contains_key(&(
+                    plot_name,
+                    block_index,
+                    coefficient_index,
+                ));
+                td![input![
+                    C![IF!(edited => "edited")],
+                    attrs! {
+                        At::Type => "number",
+                        At::Min => -limit,
+                        At::Max => limit,
+                        At::Value => block[row][col],
+                    },
+                    input_ev("change", move |value| {
+                        let value = value.parse::<i16>().ok()?.clamp(-limit, limit);
+                        Some(wrap(Msg::CoefficientEdited(
+                            plot_name,
+                            block_index,
+                            coefficient_index,
+                            value,
+                        )))
+                    })
+                ]]
+            })]
+        })
+    ]
+}
+
 pub fn view_dct_quantized(model: &Model) -> Node<GMsg> {
     div![
         C!["image_view"],
@@ -162,13 +983,13 @@ pub fn view_dct_quantized(model: &Model) -> Node<GMsg> {
                         "CB QUANTIZED",
                         &model.canvas_map.get(&CanvasName::CbsQuant).unwrap(),
                         &model.overlay_map.get(&CanvasName::CbsQuant).unwrap(),
-                        Some(&model.subsampling_pack),
+                        Some((&model.subsampling_pack, PlotName::CbsQuant3d)),
                         model.zoom
                     ),
                     plot_labeled_div(
                         "CB QUANTIZED 3D",
                         &model.plot_map.get(&PlotName::CbsQuant3d).unwrap(),
-                        Some(&model.subsampling_pack),
+                        Some((&model.subsampling_pack, PlotName::CbsQuant3d)),
                         model.zoom
                     ),
                     plot_labeled_div(
@@ -177,7 +998,7 @@ pub fn view_dct_quantized(model: &Model) -> Node<GMsg> {
                             .chosen_block_plot_map
                             .get(&PlotName::CbsQuant3d)
                             .unwrap(),
-                        Some(&model.subsampling_pack),
+                        Some((&model.subsampling_pack, PlotName::CbsQuant3d)),
                         model.zoom
                     ),
                     BLOCK_SIZE * model.zoom + 20
@@ -187,13 +1008,13 @@ pub fn view_dct_quantized(model: &Model) -> Node<GMsg> {
                         "CR QUANTIZED",
                         &model.canvas_map.get(&CanvasName::CrsQuant).unwrap(),
                         &model.overlay_map.get(&CanvasName::CrsQuant).unwrap(),
-                        Some(&model.subsampling_pack),
+                        Some((&model.subsampling_pack, PlotName::CrsQuant3d)),
                         model.zoom
                     ),
                     plot_labeled_div(
                         "CR QUANTIZED 3D",
                         &model.plot_map.get(&PlotName::CrsQuant3d).unwrap(),
-                        Some(&model.subsampling_pack),
+                        Some((&model.subsampling_pack, PlotName::CrsQuant3d)),
                         model.zoom
                     ),
                     plot_labeled_div(
@@ -202,7 +1023,7 @@ pub fn view_dct_quantized(model: &Model) -> Node<GMsg> {
                             .chosen_block_plot_map
                             .get(&PlotName::CrsQuant3d)
                             .unwrap(),
-                        Some(&model.subsampling_pack),
+                        Some((&model.subsampling_pack, PlotName::CrsQuant3d)),
                         model.zoom
                     ),
                     BLOCK_SIZE * model.zoom + 20
@@ -241,14 +1062,14 @@ fn view_ycbcr_recovered(model: &Model) -> Node<GMsg> {
                 "CB RECOVERED",
                 &model.canvas_map.get(&CanvasName::CbsRecovered).unwrap(),
                 &model.overlay_map.get(&CanvasName::CbsRecovered).unwrap(),
-                Some(&model.subsampling_pack),
+                Some((&model.subsampling_pack, PlotName::CbsQuant3d)),
                 model.zoom
             ),
             canvas_labeled_div_with_overlay(
                 "CR RECOVERED",
                 &model.canvas_map.get(&CanvasName::CrsRecovered).unwrap(),
                 &model.overlay_map.get(&CanvasName::CrsRecovered).unwrap(),
-                Some(&model.subsampling_pack),
+                Some((&model.subsampling_pack, PlotName::CrsQuant3d)),
                 model.zoom
             ),
         ]
@@ -260,7 +1081,8 @@ fn view_image_recovered(model: &Model) -> Node<GMsg> {
         C!["image_view image_recov_and_diff"],
         details![
             summary!["Recovered image and comparison"],
-            canvas_labeled_div_with_overlay(
+            view_heatmap_choice(model),
+            canvas_labeled_div_with_heatmap(
                 "INPUT",
                 &model
                     .preview_canvas_map
@@ -270,16 +1092,36 @@ fn view_image_recovered(model: &Model) -> Node<GMsg> {
                     .preview_overlay_map
                     .get(&PreviewCanvasName::ForComparison)
                     .unwrap(),
-                None,
+                model
+                    .heatmap_canvas_map
+                    .get(&HeatmapCanvasName::Original)
+                    .unwrap(),
+                model.heatmap.is_some(),
                 model.zoom
             ),
-            canvas_labeled_div_with_overlay(
+            canvas_labeled_div_with_heatmap(
                 "OUTPUT",
                 &model.canvas_map.get(&CanvasName::ImageRecovered).unwrap(),
                 &model.overlay_map.get(&CanvasName::ImageRecovered).unwrap(),
-                None,
+                model
+                    .heatmap_canvas_map
+                    .get(&HeatmapCanvasName::Recovered)
+                    .unwrap(),
+                model.heatmap.is_some(),
                 model.zoom
             ),
+            div![
+                style![
+                    St::Display => if model.deblocking_filter.is_some() { "inline-block" } else { "none" },
+                ],
+                canvas_labeled_div_with_overlay(
+                    "DEBLOCKED OUTPUT",
+                    model.canvas_map.get(&CanvasName::ImageDeblocked).unwrap(),
+                    model.overlay_map.get(&CanvasName::ImageDeblocked).unwrap(),
+                    None,
+                    model.zoom
+                ),
+            ],
             labeled_canvas_wrapper(
                 BLOCK_SIZE * model.zoom,
                 div![
@@ -344,71 +1186,810 @@ fn view_image_recovered(model: &Model) -> Node<GMsg> {
                         ]
                     ]
                 ],
-            ]
+            ],
+            view_quality_metrics(model),
+            view_deblocking_metrics(model),
         ]
     ]
 }
 
-fn canvas_labeled_div_with_overlay(
-    label: &str,
-    canvas: &ElRef<HtmlCanvasElement>,
-    img: &ElRef<HtmlImageElement>,
-    // if canvas should not be subsampled, pass None
-    subsampling_pack_option: Option<&SubsamplingPack>,
-    zoom: u32,
-) -> Node<GMsg> {
-    let mut width: u32 = BLOCK_SIZE * zoom;
-    let mut height: u32 = BLOCK_SIZE * zoom;
-
-    let mut is_resizable_canvas: bool = false;
+/// Buttons that lay one of the per-block heatmaps over the compared windows, the active one hides it again
+fn view_heatmap_choice(model: &Model) -> Node<GMsg> {
+    div![
+        C!["heatmap_choice"],
+        span!["Heatmap:"],
+        Heatmap::iter().map(|heatmap| {
+            let is_active = model.heatmap == Some(heatmap);
+            button![
+                heatmap.name(),
+                IF!(is_active => C!["active"]),
+                ev(Ev::Click, move |_| wrap(Msg::HeatmapChanged(
+                    if is_active { None } else { Some(heatmap) }
+                ))),
+            ]
+        }),
+        IF!(model.heatmap.is_some() => span![
+            C!["heatmap_legend"],
+            "dark blocks have the lowest and bright ones the highest value in the window"
+        ]),
+    ]
+}
 
-    match subsampling_pack_option {
-        Some(subsampling_pack) => {
-            is_resizable_canvas = true;
-            width = width * subsampling_pack.a as u32 / subsampling_pack.j as u32;
-            if subsampling_pack.b == 0 {
-                height = height / 2;
-            }
-        }
-        None => {}
+fn format_psnr(psnr: f64) -> String {
+    if psnr.is_finite() {
+        format!("{:.2} dB", psnr)
+    } else {
+        "∞".to_string()
     }
+}
 
-    let label_text = format!("{} [{}x{}]", label, width / zoom, height / zoom);
+/// PSNR of the recovered window with and without the deblocking filter
+fn view_deblocking_metrics(model: &Model) -> Node<GMsg> {
+    let (pack, deblocking_filter) = match (&model.state, model.deblocking_filter) {
+        (State::ImageView(pack), Some(deblocking_filter)) => (pack, deblocking_filter),
+        _ => return empty![],
+    };
 
-    labeled_canvas_wrapper(
-        BLOCK_SIZE * zoom,
-        div![
-            label![C!["canvas_label"], &label_text],
-            style![
-                St::Width => px(BLOCK_SIZE * zoom),
-            ]
-        ],
-        canvas_with_overlay_with_w_h(canvas, img, width, height, is_resizable_canvas),
-    )
+    table![
+        C!["block-content quality_metrics"],
+        caption![format!(
+            "PSNR of the window without and with the {} deblocking filter",
+            deblocking_filter.name().to_lowercase()
+        )],
+        tr![th![""], th!["Unfiltered"], th!["Deblocked"], th!["Gain"]],
+        pack.window_metrics
+            .iter()
+            .zip(pack.deblocked_window_metrics.iter())
+            .map(|((channel, unfiltered), (_, deblocked))| {
+                tr![
+                    th![channel],
+                    td![format_psnr(unfiltered.psnr)],
+                    td![format_psnr(deblocked.psnr)],
+                    td![format!("{:+.2} dB", deblocked.psnr - unfiltered.psnr)],
+                ]
+            })
+    ]
 }
-fn labeled_canvas_wrapper(
-    width: u32,
-    label_element: Node<GMsg>,
-    content_element: Node<GMsg>,
-) -> Node<GMsg> {
-    let padding = 10;
 
-    div![
-        C!["labeled_canvas_wrapper"],
-        label_element,
-        content_element,
-        style![
-            St::MaxWidth => px(width + padding * 2),
-        ]
+/// MSE, PSNR and SSIM of every channel of the recovered image window
+fn view_quality_metrics(model: &Model) -> Node<GMsg> {
+    let pack = match &model.state {
+        State::ImageView(pack) => pack,
+        _ => return empty![],
+    };
+
+    table![
+        C!["block-content quality_metrics"],
+        caption!["Recovered image window compared to the original"],
+        tr![th![""], th!["MSE"], th!["PSNR"], th!["SSIM"]],
+        pack.window_metrics.iter().map(|(channel, metrics)| {
+            tr![
+                th![channel],
+                td![format!("{:.2}", metrics.mse)],
+                td![format_psnr(metrics.psnr)],
+                td![format!("{:.4}", metrics.ssim)],
+            ]
+        })
     ]
 }
 
-fn canvas_with_overlay_with_w_h(
-    canvas: &ElRef<HtmlCanvasElement>,
-    img: &ElRef<HtmlImageElement>,
-    width: u32,
+/// Recovered image and the difference at full size, the canvases are sized when they are drawn
+fn view_full_image(model: &Model) -> Node<GMsg> {
+    let full_image_canvas = |label: &str, canvas_name: FullImageCanvasName| {
+        div![
+            C!["full_image_wrapper"],
+            label![C!["canvas_label"], label],
+            canvas![
+                C!["full_image"],
+                el_ref(model.full_image_canvas_map.get(&canvas_name).unwrap()),
+            ],
+        ]
+    };
+
+    div![
+        C!["image_view"],
+        details![
+            summary!["Whole image"],
+            full_image_canvas("OUTPUT", FullImageCanvasName::Recovered),
+            full_image_canvas("DIFFERENCE", FullImageCanvasName::Difference),
+        ]
+    ]
+}
+
+/// Most bytes shown in the dump of the chosen segment, the entropy-coded data takes most of the file
+const SEGMENT_DUMP_BYTES: usize = 512;
+
+/// Marker segments of the uploaded file as a tree, the bar shows the share of the file every one takes
+fn view_segment_inspector(model: &Model) -> Node<GMsg> {
+    let pack = match &model.state {
+        State::ImageView(pack) if !pack.file_segments.is_empty() => pack,
+        _ => return empty![],
+    };
+    let file_length = pack.file_bytes.len();
+    let entropy_coded_length: usize = pack
+        .file_segments
+        .iter()
+        .filter(|segment| segment.is_entropy_coded())
+        .map(|segment| segment.length)
+        .sum();
+    let chosen_segment = pack
+        .chosen_segment
+        .and_then(|(offset, length)| find_segment(&pack.file_segments, offset, length));
+
+    div![
+        C!["image_view segment_inspector"],
+        details![
+            summary!["File structure"],
+            div![
+                C!["segment_map"],
+                pack.file_segments.iter().map(|segment| {
+                    let (offset, length) = (segment.offset, segment.length);
+                    div![
+                        C![
+                            if segment.is_entropy_coded() {
+                                "entropy_coded"
+                            } else {
+                                "header"
+                            },
+                            IF!(pack.chosen_segment == Some((offset, length)) => "chosen"),
+                        ],
+                        style![St::Width => format!("{:.3}%", 100.0 * length as f64 / file_length as f64)],
+                        attrs! {
+                            At::Title => format!("{} at {}, {} bytes", segment.name(), offset, length),
+                        },
+                        ev(Ev::Click, move |_| wrap(Msg::FileSegmentChosen(offset, length))),
+                    ]
+                }),
+            ],
+            p![format!(
+                "{} bytes, {} of them ({:.1}%) entropy-coded data",
+                file_length,
+                entropy_coded_length,
+                100.0 * entropy_coded_length as f64 / file_length as f64
+            )],
+            div![
+                C!["segment_tree"],
+                pack.file_segments
+                    .iter()
+                    .map(|segment| view_segment_node(segment, pack.chosen_segment)),
+            ],
+            match chosen_segment {
+                Some(segment) => view_segment_bytes(&pack.file_bytes, segment),
+                None => p!["Click a segment to see its bytes"],
+            },
+        ]
+    ]
+}
+
+fn find_segment(segments: &[Segment], offset: usize, length: usize) -> Option<&Segment> {
+    segments.iter().find_map(|segment| {
+        if (segment.offset, segment.length) == (offset, length) {
+            Some(segment)
+        } else {
+            find_segment(&segment.children, offset, length)
+        }
+    })
+}
+
+fn view_segment_node(segment: &Segment, chosen_segment: Option<(usize, usize)>) -> Node<GMsg> {
+    let (offset, length) = (segment.offset, segment.length);
+    let row = div![
+        C![
+            "segment_row",
+            IF!(segment.is_entropy_coded() => "entropy_coded"),
+            IF!(chosen_segment == Some((offset, length)) => "chosen"),
+        ],
+        span![C!["segment_offset"], format!("0x{:06X}", offset)],
+        span![C!["segment_name"], segment.name()],
+        span![segment.description()],
+        span![C!["segment_length"], format!("{} bytes", length)],
+        ev(Ev::Click, move |_| wrap(Msg::FileSegmentChosen(
+            offset, length
+        ))),
+    ];
+    if segment.content == SegmentContent::Empty && segment.children.is_empty() {
+        return div![C!["segment_node"], row];
+    }
+
+    details![
+        C!["segment_node"],
+        summary![row],
+        match &segment.content {
+            SegmentContent::Empty => empty![],
+            SegmentContent::Fields(fields) => table![
+                C!["segment_fields"],
+                fields
+                    .iter()
+                    .map(|(name, value)| tr![th![name], td![value]]),
+            ],
+            SegmentContent::QuantizationTables(tables) => div![tables.iter().map(|table| {
+                div![
+                    C!["segment_table"],
+                    h4![format!("Table {}, {}-bit", table.id, table.precision)],
+                    table![table
+                        .values
+                        .iter()
+                        .map(|row| tr![row.iter().map(|value| td![value.to_string()])])],
+                ]
+            })],
+            SegmentContent::HuffmanTables(tables) => div![tables.iter().map(|table| {
+                let mut values = table.values.iter();
+                div![
+                    C!["segment_table"],
+                    h4![format!(
+                        "{} table {}",
+                        if table.class == 0 { "DC" } else { "AC" },
+                        table.id
+                    )],
+                    table![
+                        tr![th!["Code length"], th!["Codes"], th!["Values"]],
+                        table
+                            .bits
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, count)| **count > 0)
+                            .map(|(i, count)| {
+                                let values = values
+                                    .by_ref()
+                                    .take(*count as usize)
+                                    .map(|value| format!("{:02X}", value))
+                                    .collect::<Vec<String>>();
+                                tr![
+                                    td![(i + 1).to_string()],
+                                    td![count.to_string()],
+                                    td![C!["segment_values"], values.join(" ")],
+                                ]
+                            })
+                            .collect::<Vec<Node<GMsg>>>(),
+                    ],
+                ]
+            })],
+            SegmentContent::Invalid(reason) =>
+                p![C!["segment_invalid"], format!("Invalid: {}", reason)],
+        },
+        segment
+            .children
+            .iter()
+            .map(|child| view_segment_node(child, chosen_segment)),
+    ]
+}
+
+/// Hex dump of the start of the segment, the marker, the length and the stuffed bytes stand out
+fn view_segment_bytes(file_bytes: &[u8], segment: &Segment) -> Node<GMsg> {
+    let end = segment.end().min(segment.offset + SEGMENT_DUMP_BYTES);
+    let bytes = &file_bytes[segment.offset..end];
+    let byte_class = |i: usize| {
+        let previous = if i > 0 { Some(bytes[i - 1]) } else { None };
+        let next = bytes.get(i + 1).copied();
+        if !segment.is_entropy_coded() {
+            match i {
+                0 | 1 => "marker",
+                2 | 3 if segment.length > 2 => "length",
+                _ => "payload",
+            }
+        } else if bytes[i] == 0x00 && previous == Some(0xFF) {
+            "stuffed"
+        } else if (bytes[i] == 0xFF && next.is_some_and(|next| next != 0x00))
+            || (previous == Some(0xFF) && bytes[i] != 0xFF)
+        {
+            "marker"
+        } else {
+            "entropy_coded"
+        }
+    };
+
+    div![
+        C!["segment_bytes"],
+        h4![if end < segment.end() {
+            format!(
+                "{} at 0x{:06X}, first {} of {} bytes",
+                segment.name(),
+                segment.offset,
+                bytes.len(),
+                segment.length
+            )
+        } else {
+            format!("{} at 0x{:06X}", segment.name(), segment.offset)
+        }],
+        bytes.chunks(16).enumerate().map(|(row, chunk)| {
+            div![
+                span![
+                    C!["segment_offset"],
+                    format!("{:06X}", segment.offset + row * 16)
+                ],
+                chunk.iter().enumerate().map(|(column, byte)| {
+                    span![C![byte_class(row * 16 + column)], format!("{:02X}", byte)]
+                }),
+            ]
+        }),
+    ]
+}
+
+/// Source recompressed with the block grid moved against the grid of its previous compression
+fn view_grid_shift(model: &Model) -> Node<GMsg> {
+    let pack = match &model.state {
+        State::ImageView(pack) => pack,
+        _ => return empty![],
+    };
+    // half a block is the worst misalignment, so the experiment starts with it
+    let (offset_x, offset_y) = model.grid_shift.unwrap_or((4, 4));
+    let is_enabled = model.grid_shift.is_some();
+    let is_jpeg_source = pack.file_bytes.starts_with(&[0xFF, 0xD8]);
+    let offset_input = |value: usize, with_value: fn(usize, usize, usize) -> (usize, usize)| {
+        input![
+            attrs! {
+                At::Type => "number",
+                At::Min => 0,
+                At::Max => 7,
+                At::Value => value,
+                At::Disabled => (!is_enabled).as_at_value(),
+            },
+            input_ev("change", move |value| {
+                let value = value.parse::<usize>().ok()?.min(7);
+                Some(wrap(Msg::GridShiftChanged(Some(with_value(
+                    offset_x, offset_y, value,
+                )))))
+            })
+        ]
+    };
+
+    div![
+        C!["image_view grid_shift"],
+        details![
+            summary!["Grid-shift recompression"],
+            div![
+                C!["grid_shift_controls"],
+                label![
+                    input![
+                        attrs! {
+                            At::Type => "checkbox",
+                            At::Checked => model.grid_shift.is_some().as_at_value(),
+                        },
+                        ev(Ev::Change, move |_| wrap(Msg::GridShiftChanged(
+                            if is_enabled {
+                                None
+                            } else {
+                                Some((offset_x, offset_y))
+                            }
+                        ))),
+                    ],
+                    "Recompress the source with a shifted block grid",
+                ],
+                label!["Offset x:", offset_input(offset_x, |_, y, x| (x, y))],
+                label!["Offset y:", offset_input(offset_y, |x, _, y| (x, y))],
+            ],
+            p![if is_jpeg_source {
+                "The source is a JPEG, so the aligned grid matches the blocks it was compressed with"
+            } else {
+                "The source isn't a JPEG, open one to see the grid of its previous compression"
+            }],
+            match &pack.grid_shift_experiment {
+                Some(experiment) => table![
+                    C!["block-content quality_metrics"],
+                    caption!["PSNR of the whole recompressed image against the source"],
+                    tr![th!["Aligned"], th![format!("Shifted by ({}, {})", experiment.offset.0, experiment.offset.1)], th!["Difference"]],
+                    tr![
+                        td![format_psnr(experiment.aligned_psnr)],
+                        td![format_psnr(experiment.shifted_psnr)],
+                        td![format!("{:+.2} dB", experiment.shifted_psnr - experiment.aligned_psnr)],
+                    ],
+                ],
+                None => empty![],
+            },
+            div![
+                style![
+                    St::Display => if is_enabled { "block" } else { "none" },
+                ],
+                canvas_labeled_div_with_overlay(
+                    "ALIGNED GRID",
+                    model.canvas_map.get(&CanvasName::GridAligned).unwrap(),
+                    model.overlay_map.get(&CanvasName::GridAligned).unwrap(),
+                    None,
+                    model.zoom
+                ),
+                canvas_labeled_div_with_overlay(
+                    "SHIFTED GRID",
+                    model.canvas_map.get(&CanvasName::GridShifted).unwrap(),
+                    model.overlay_map.get(&CanvasName::GridShifted).unwrap(),
+                    None,
+                    model.zoom
+                ),
+                canvas_labeled_div_with_overlay(
+                    "ERROR DIFFERENCE",
+                    model.canvas_map.get(&CanvasName::GridErrorDifference).unwrap(),
+                    model.overlay_map.get(&CanvasName::GridErrorDifference).unwrap(),
+                    None,
+                    model.zoom
+                ),
+                p!["Red pixels are further from the source with the shifted grid, blue ones with the aligned grid"],
+            ],
+        ]
+    ]
+}
+
+fn view_generation_loss(model: &Model) -> Node<GMsg> {
+    let pack = match &model.state {
+        State::ImageView(pack) => pack,
+        _ => return empty![],
+    };
+    let thumbnail_height =
+        GENERATION_THUMBNAIL_WIDTH * pack.raw_image.height() / pack.raw_image.width();
+    let thumbnails = match &model.generation_loss {
+        Some(generation_loss) => generation_loss
+            .thumbnails
+            .iter()
+            .map(|(generation, _)| (*generation, generation_loss.psnr[generation - 1]))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    div![
+        C!["image_view generation_loss"],
+        details![
+            summary!["Generation loss"],
+            div![
+                C!["generation_loss_controls"],
+                label![
+                    "Generations:",
+                    input![
+                        attrs! {
+                            At::Type => "number",
+                            At::Min => 1,
+                            At::Max => 100,
+                            At::Value => model.generation_count,
+                        },
+                        input_ev("change", |value| {
+                            let count = value.parse::<usize>().ok()?.clamp(1, 100);
+                            Some(wrap(Msg::GenerationCountChanged(count)))
+                        })
+                    ],
+                ],
+                label![
+                    input![
+                        attrs! {
+                            At::Type => "checkbox",
+                            At::Checked => model.generation_grid_shift.as_at_value(),
+                        },
+                        ev(Ev::Change, |_| wrap(Msg::GenerationGridShiftToggled)),
+                    ],
+                    "Shift the block grid by 1 pixel every generation",
+                ],
+                label![
+                    "Every other generation:",
+                    select![
+                        option![
+                            "Same quality",
+                            attrs! {
+                                At::Value => 0,
+                                At::Selected => model.generation_alternate_quality.is_none().as_at_value(),
+                            }
+                        ],
+                        (5..=100).step_by(5).map(|quality| {
+                            option![
+                                format!("Quality {}", quality),
+                                attrs! {
+                                    At::Value => quality,
+                                    At::Selected => (model.generation_alternate_quality == Some(quality)).as_at_value(),
+                                }
+                            ]
+                        }),
+                        input_ev("change", |value| {
+                            let quality = value.parse::<u8>().unwrap();
+                            wrap(Msg::GenerationAlternateQualityChanged(
+                                Some(quality).filter(|quality| *quality > 0),
+                            ))
+                        })
+                    ],
+                ],
+                button![
+                    "Run",
+                    ev(Ev::Click, |_| wrap(Msg::GenerationLossRun)),
+                ],
+                span!["Every generation compresses and decodes the whole image decoded by the previous one with the current settings"],
+            ],
+            canvas![
+                el_ref(&model.generation_chart_canvas),
+                attrs! {
+                    At::Width => px(800),
+                    At::Height => px(400),
+                },
+            ],
+            div![
+                C!["generation_thumbnails"],
+                model
+                    .generation_thumbnail_canvases
+                    .iter()
+                    .enumerate()
+                    .map(|(i, canvas)| {
+                        let thumbnail = thumbnails.get(i);
+                        div![
+                            IF!(thumbnail.is_none() => style![St::Display => "none"]),
+                            canvas![
+                                el_ref(canvas),
+                                attrs! {
+                                    At::Width => px(GENERATION_THUMBNAIL_WIDTH),
+                                    At::Height => px(thumbnail_height),
+                                },
+                            ],
+                            thumbnail.map(|(generation, psnr)| {
+                                label![format!("Generation {}, {:.2} dB", generation, psnr)]
+                            }),
+                        ]
+                    }),
+            ],
+        ]
+    ]
+}
+
+/// Bits and PSNR of the whole image for every quantization strategy with the current tables
+fn view_quantization_strategies(model: &Model) -> Node<GMsg> {
+    if !matches!(model.state, State::ImageView(_)) {
+        return empty![];
+    }
+
+    div![
+        C!["image_view quantization_strategies"],
+        details![
+            summary!["Quantization strategies"],
+            div![
+                C!["rate_distortion_controls"],
+                button![
+                    "Compare strategies",
+                    ev(Ev::Click, |_| wrap(Msg::QuantizationStrategiesCompared)),
+                ],
+                span![format!(
+                    "Bits are the entropy coded data with the default Huffman tables, the trellis uses lambda {}",
+                    model.trellis_lambda
+                )],
+            ],
+            IF!(!model.strategy_results.is_empty() => table![
+                C!["block-content quality_metrics"],
+                tr![th!["Strategy"], th!["Bits"], th!["Bits per pixel"], th!["PSNR"]],
+                model.strategy_results.iter().map(|result| {
+                    tr![
+                        C![IF!(result.strategy == model.quantization_strategy => "chosen")],
+                        th![result.strategy.name()],
+                        td![result.bits.to_string()],
+                        td![format!("{:.3}", result.bits_per_pixel)],
+                        td![format_psnr(result.psnr)],
+                    ]
+                }),
+            ]),
+        ]
+    ]
+}
+
+/// Residual map of the chosen predictor and the coded size of the window for every predictor,
+/// next to the estimated size of the same window coded with the DCT
+fn view_lossless(model: &Model) -> Node<GMsg> {
+    let pack = match &model.state {
+        State::ImageView(pack) => pack,
+        _ => return empty![],
+    };
+    let pixels = (BLOCK_SIZE * BLOCK_SIZE) as f64;
+    // 24 bits of the RGB source per pixel
+    let ratio = |bits: f64| format!("{:.2}:1", 24.0 * pixels / bits);
+    let chosen = pack
+        .lossless_results
+        .iter()
+        .find(|result| result.predictor == model.lossless_predictor);
+
+    div![
+        C!["image_view lossless"],
+        details![
+            summary!["Lossless JPEG"],
+            p!["The lossless process predicts every sample from its neighbours - Ra on the left, Rb above and Rc above on the left - and Huffman codes the residuals like DC differences"],
+            div![
+                C!["lossless_predictors"],
+                span!["Predictor:"],
+                Predictor::iter().map(|predictor| {
+                    button![
+                        format!("{}: {}", predictor.selection_value(), predictor.name()),
+                        IF!(predictor == model.lossless_predictor => C!["active"]),
+                        ev(Ev::Click, move |_| wrap(Msg::LosslessPredictorChanged(predictor))),
+                    ]
+                }),
+            ],
+            canvas_labeled_div_with_overlay(
+                "RESIDUALS",
+                model.canvas_map.get(&CanvasName::LosslessResiduals).unwrap(),
+                model.overlay_map.get(&CanvasName::LosslessResiduals).unwrap(),
+                None,
+                model.zoom
+            ),
+            p!["Mean absolute residual of the red, green and blue samples, the brightest pixels are off by 32 or more"],
+            table![
+                C!["block-content quality_metrics"],
+                caption!["Entropy coded data of the image window, every lossless channel has a Huffman table built for its residuals, the DCT blocks use the tables of the encoded image"],
+                tr![th!["Coding"], th!["Bits"], th!["Bits per pixel"], th!["Compression ratio"]],
+                pack.lossless_results.iter().map(|result| {
+                    tr![
+                        C![IF!(result.predictor == model.lossless_predictor => "chosen")],
+                        th![format!("Lossless, {}", result.predictor.name())],
+                        td![result.bits.to_string()],
+                        td![format!("{:.3}", result.bits_per_pixel)],
+                        td![ratio(result.bits as f64)],
+                    ]
+                }),
+                tr![
+                    th![format!("DCT, quality {}", model.quality)],
+                    td![format!("{:.0}", pack.dct_window_bits)],
+                    td![format!("{:.3}", pack.dct_window_bits / pixels)],
+                    td![ratio(pack.dct_window_bits)],
+                ],
+            ],
+            match chosen {
+                Some(result) => {
+                    let codes = result.codings.each_ref().map(|coding| coding.table.codes());
+                    table![
+                        C!["block-content quality_metrics lossless_categories"],
+                        caption![format!("Residual categories with predictor {}", result.predictor.name())],
+                        tr![
+                            th!["SSSS"],
+                            th!["Residuals"],
+                            ["R", "G", "B"].iter().map(|channel| th![format!("{} count / code", channel)]),
+                        ],
+                        (0..=16_usize)
+                            .filter(|category| {
+                                result.codings.iter().any(|coding| coding.categories[*category] > 0)
+                            })
+                            .map(|category| {
+                                tr![
+                                    td![category.to_string()],
+                                    td![match category {
+                                        0 => "0".to_string(),
+                                        // the differences are taken modulo 2^16, so the last category holds a single one
+                                        16 => "32768".to_string(),
+                                        _ => format!("±{}..{}", 1 << (category - 1), (1 << category) - 1),
+                                    }],
+                                    result.codings.iter().zip(codes.iter()).map(|(coding, codes)| {
+                                        let count = coding.categories[category];
+                                        td![if count > 0 {
+                                            format!("{} / {} bits", count, codes[category].length)
+                                        } else {
+                                            "-".to_string()
+                                        }]
+                                    }),
+                                ]
+                            }),
+                    ]
+                }
+                None => empty![],
+            },
+        ]
+    ]
+}
+
+/// Chart of the quality sweeps, the curves are computed only on demand because every one
+/// quantizes and codes the whole image a hundred times
+fn view_rate_distortion(model: &Model) -> Node<GMsg> {
+    div![
+        C!["image_view rate_distortion"],
+        details![
+            summary!["Rate-distortion curve"],
+            div![
+                C!["rate_distortion_controls"],
+                button![
+                    format!("Add curve for {}", model.subsampling_pack.ratio_name()),
+                    ev(Ev::Click, |_| wrap(Msg::RdCurveAdded)),
+                ],
+                button![
+                    "Clear curves",
+                    attrs! {
+                        At::Disabled => model.rd_curves.is_empty().as_at_value(),
+                    },
+                    ev(Ev::Click, |_| wrap(Msg::RdCurvesCleared)),
+                ],
+                span!["Bits are the entropy coded data with the default Huffman tables, PSNR is estimated from the quantization error of the coefficients"],
+            ],
+            canvas![
+                el_ref(&model.rd_chart_canvas),
+                attrs! {
+                    At::Width => px(800),
+                    At::Height => px(500),
+                },
+            ],
+        ]
+    ]
+}
+
+fn canvas_labeled_div_with_overlay(
+    label: &str,
+    canvas: &ElRef<HtmlCanvasElement>,
+    img: &ElRef<HtmlImageElement>,
+    // if canvas should not be subsampled, pass None, otherwise the pack and the chroma channel
+    subsampling_pack_option: Option<(&SubsamplingPack, PlotName)>,
+    zoom: u32,
+) -> Node<GMsg> {
+    let mut width: u32 = BLOCK_SIZE * zoom;
+    let mut height: u32 = BLOCK_SIZE * zoom;
+
+    let mut subsampled_channel: Option<PlotName> = None;
+
+    match subsampling_pack_option {
+        Some((subsampling_pack, plot_name)) => {
+            subsampled_channel = Some(plot_name);
+            let (horiz_mult, vert_mult) = subsampling_pack.mults(plot_name);
+            width = subsampled_window_size(horiz_mult) as u32 * zoom;
+            height = subsampled_window_size(vert_mult) as u32 * zoom;
+        }
+        None => {}
+    }
+
+    let label_text = format!("{} [{}x{}]", label, width / zoom, height / zoom);
+
+    labeled_canvas_wrapper(
+        BLOCK_SIZE * zoom,
+        div![
+            label![C!["canvas_label"], &label_text],
+            style![
+                St::Width => px(BLOCK_SIZE * zoom),
+            ]
+        ],
+        canvas_with_overlay_with_w_h(canvas, img, width, height, subsampled_channel),
+    )
+}
+/// Canvas with its overlay and a heatmap between them, the heatmap lets the clicks through
+fn canvas_labeled_div_with_heatmap(
+    label: &str,
+    canvas: &ElRef<HtmlCanvasElement>,
+    img: &ElRef<HtmlImageElement>,
+    heatmap_canvas: &ElRef<HtmlCanvasElement>,
+    is_heatmap_shown: bool,
+    zoom: u32,
+) -> Node<GMsg> {
+    let size = BLOCK_SIZE * zoom;
+    labeled_canvas_wrapper(
+        size,
+        div![
+            label![
+                C!["canvas_label"],
+                format!("{} [{}x{}]", label, BLOCK_SIZE, BLOCK_SIZE)
+            ],
+            style![
+                St::Width => px(size),
+            ]
+        ],
+        div![
+            C!["heatmap_container"],
+            canvas_with_overlay_with_w_h(canvas, img, size, size, None),
+            canvas![
+                C!["heatmap_overlay"],
+                el_ref(heatmap_canvas),
+                attrs![
+                    At::Width => px(size),
+                    At::Height => px(size),
+                ],
+                IF!(!is_heatmap_shown => style![St::Display => "none"]),
+            ],
+        ],
+    )
+}
+
+fn labeled_canvas_wrapper(
+    width: u32,
+    label_element: Node<GMsg>,
+    content_element: Node<GMsg>,
+) -> Node<GMsg> {
+    let padding = 10;
+
+    div![
+        C!["labeled_canvas_wrapper"],
+        label_element,
+        content_element,
+        style![
+            St::MaxWidth => px(width + padding * 2),
+        ]
+    ]
+}
+
+fn canvas_with_overlay_with_w_h(
+    canvas: &ElRef<HtmlCanvasElement>,
+    img: &ElRef<HtmlImageElement>,
+    width: u32,
     height: u32,
-    is_resizable_canvas: bool,
+    // chroma channel whose blocks the canvas shows, None for full resolution canvases
+    subsampled_channel: Option<PlotName>,
 ) -> Node<GMsg> {
     let cloned_canvas_ref = canvas.clone();
 
@@ -431,7 +2012,7 @@ fn canvas_with_overlay_with_w_h(
                     mouse_event.y(),
                     canvas_rect.left() as i32,
                     canvas_rect.top() as i32,
-                    is_resizable_canvas,
+                    subsampled_channel,
                 ))
             }),
         ],
@@ -447,34 +2028,33 @@ fn canvas_with_overlay_with_w_h(
 fn canvas_with_overlay(
     canvas: &ElRef<HtmlCanvasElement>,
     img: &ElRef<HtmlImageElement>,
-    // if canvas should not be subsampled, pass None
-    subsampling_pack_option: Option<&SubsamplingPack>,
+    // if canvas should not be subsampled, pass None, otherwise the pack and the chroma channel
+    subsampling_pack_option: Option<(&SubsamplingPack, PlotName)>,
     zoom: u32,
 ) -> Node<GMsg> {
     let mut width: u32 = BLOCK_SIZE * zoom;
     let mut height: u32 = BLOCK_SIZE * zoom;
 
-    let mut is_resizable_canvas: bool = false;
+    let mut subsampled_channel: Option<PlotName> = None;
 
     match subsampling_pack_option {
-        Some(subsampling_pack) => {
-            is_resizable_canvas = true;
-            width = width * subsampling_pack.a as u32 / subsampling_pack.j as u32;
-            if subsampling_pack.b == 0 {
-                height = height / 2;
-            }
+        Some((subsampling_pack, plot_name)) => {
+            subsampled_channel = Some(plot_name);
+            let (horiz_mult, vert_mult) = subsampling_pack.mults(plot_name);
+            width = subsampled_window_size(horiz_mult) as u32 * zoom;
+            height = subsampled_window_size(vert_mult) as u32 * zoom;
         }
         None => {}
     }
 
-    canvas_with_overlay_with_w_h(canvas, img, width, height, is_resizable_canvas)
+    canvas_with_overlay_with_w_h(canvas, img, width, height, subsampled_channel)
 }
 
 fn plot_labeled_div(
     label: &str,
     canvas: &ElRef<HtmlCanvasElement>,
-    // if canvas should not be subsampled, pass None
-    subsampling_pack_option: Option<&SubsamplingPack>,
+    // if canvas should not be subsampled, pass None, otherwise the pack and the chroma channel
+    subsampling_pack_option: Option<(&SubsamplingPack, PlotName)>,
     zoom: u32,
 ) -> Node<GMsg> {
     let padding = 10;
@@ -483,11 +2063,10 @@ fn plot_labeled_div(
     let mut height: u32 = BLOCK_SIZE * zoom;
 
     match subsampling_pack_option {
-        Some(subsampling_pack) => {
-            width = width * subsampling_pack.a as u32 / subsampling_pack.j as u32;
-            if subsampling_pack.b == 0 {
-                height = height / 2;
-            }
+        Some((subsampling_pack, plot_name)) => {
+            let (horiz_mult, vert_mult) = subsampling_pack.mults(plot_name);
+            width = subsampled_window_size(horiz_mult) as u32 * zoom;
+            height = subsampled_window_size(vert_mult) as u32 * zoom;
         }
         None => {}
     }
@@ -510,7 +2089,40 @@ fn plot_labeled_div(
     ]
 }
 
+/// One sampling factor of the current layout, the factors that would make an illegal layout
+/// are disabled
+fn view_sampling_factor_select(
+    model: &Model,
+    factor: u8,
+    with_factor: fn(&SubsamplingPack, u8) -> Option<SubsamplingPack>,
+) -> Node<GMsg> {
+    let subsampling_pack = model.subsampling_pack;
+    select![
+        (1..=4).map(|value| {
+            option![
+                value.to_string(),
+                attrs! {
+                    At::Value => value,
+                    At::Selected => (value == factor).as_at_value(),
+                    At::Disabled => with_factor(&subsampling_pack, value).is_none().as_at_value(),
+                }
+            ]
+        }),
+        attrs! {
+            At::Disabled => model.use_source_coefficients.as_at_value(),
+        },
+        input_ev("change", move |value| {
+            let factor = value.parse::<u8>().unwrap();
+            wrap(Msg::SubsamplingChanged(
+                with_factor(&subsampling_pack, factor).unwrap(),
+            ))
+        })
+    ]
+}
+
 pub fn view_settings_sidebar(model: &Model) -> Node<GMsg> {
+    let subsampling_pack = model.subsampling_pack;
+    let is_preset = SubsamplingPack::PRESETS.contains(&subsampling_pack);
     div![
         C!["setting_sidebar"],
         input![
@@ -555,46 +2167,194 @@ pub fn view_settings_sidebar(model: &Model) -> Node<GMsg> {
                 "Subsampling ratio (J:a:b):"
             ],
             select![
-                option![
-                    "4:4:4",
-                    attrs! {
-                        At::Value => "4:4:4"
-                    }
-                ],
-                option![
-                    "4:2:2",
-                    attrs! {
-                        At::Value => "4:2:2"
-                    }
-                ],
-                option![
-                    "4:1:1",
-                    attrs! {
-                        At::Value => "4:1:1"
-                    }
-                ],
-                option![
-                    "4:4:0",
+                SubsamplingPack::PRESETS.iter().enumerate().map(|(i, preset)| {
+                    option![
+                        preset.name(),
+                        attrs! {
+                            At::Value => i,
+                            At::Selected => (*preset == subsampling_pack).as_at_value(),
+                        }
+                    ]
+                }),
+                IF!(!is_preset => option![
+                    subsampling_pack.name(),
                     attrs! {
-                        At::Value => "4:4:0"
+                        At::Disabled => true.as_at_value(),
+                        At::Selected => true.as_at_value(),
                     }
-                ],
+                ]),
+                attrs! {
+                    At::Id => "subsampling_ratio_select",
+                    At::Disabled => model.use_source_coefficients.as_at_value(),
+                },
+                input_ev("change", |value| {
+                    let index = value.parse::<usize>().unwrap();
+                    wrap(Msg::SubsamplingChanged(SubsamplingPack::PRESETS[index]))
+                })
+            ],
+            label!["Sampling factors (HxV):"],
+            div![
+                C!["sampling_factors"],
+                span!["Y"],
+                view_sampling_factor_select(model, subsampling_pack.luma_horiz, |pack, factor| {
+                    SubsamplingPack::with_factors((factor, pack.luma_vert), (pack.cb_horiz, pack.cb_vert), (pack.cr_horiz, pack.cr_vert))
+                }),
+                view_sampling_factor_select(model, subsampling_pack.luma_vert, |pack, factor| {
+                    SubsamplingPack::with_factors((pack.luma_horiz, factor), (pack.cb_horiz, pack.cb_vert), (pack.cr_horiz, pack.cr_vert))
+                }),
+                span!["Cb"],
+                view_sampling_factor_select(model, subsampling_pack.cb_horiz, |pack, factor| {
+                    SubsamplingPack::with_factors((pack.luma_horiz, pack.luma_vert), (factor, pack.cb_vert), (pack.cr_horiz, pack.cr_vert))
+                }),
+                view_sampling_factor_select(model, subsampling_pack.cb_vert, |pack, factor| {
+                    SubsamplingPack::with_factors((pack.luma_horiz, pack.luma_vert), (pack.cb_horiz, factor), (pack.cr_horiz, pack.cr_vert))
+                }),
+                span!["Cr"],
+                view_sampling_factor_select(model, subsampling_pack.cr_horiz, |pack, factor| {
+                    SubsamplingPack::with_factors((pack.luma_horiz, pack.luma_vert), (pack.cb_horiz, pack.cb_vert), (factor, pack.cr_vert))
+                }),
+                view_sampling_factor_select(model, subsampling_pack.cr_vert, |pack, factor| {
+                    SubsamplingPack::with_factors((pack.luma_horiz, pack.luma_vert), (pack.cb_horiz, pack.cb_vert), (pack.cr_horiz, factor))
+                }),
+            ],
+            label![
+                attrs! {
+                    At::For => "downsampling_filter_select"
+                },
+                "Chroma filter:"
+            ],
+            select![
+                DownsamplingFilter::iter().enumerate().map(|(i, downsampling_filter)| {
+                    option![
+                        downsampling_filter.name(),
+                        attrs! {
+                            At::Value => i,
+                            At::Selected => (downsampling_filter == model.downsampling_filter).as_at_value(),
+                        }
+                    ]
+                }),
+                attrs! {
+                    At::Id => "downsampling_filter_select",
+                },
+                input_ev("change", |value| {
+                    let index = value.parse::<usize>().unwrap();
+                    wrap(Msg::DownsamplingFilterChanged(
+                        DownsamplingFilter::iter().nth(index).unwrap(),
+                    ))
+                })
+            ],
+            label![
+                attrs! {
+                    At::For => "upsampling_filter_select"
+                },
+                "Chroma upsampling:"
+            ],
+            select![
+                UpsamplingFilter::iter().enumerate().map(|(i, upsampling_filter)| {
+                    option![
+                        upsampling_filter.name(),
+                        attrs! {
+                            At::Value => i,
+                            At::Selected => (upsampling_filter == model.upsampling_filter).as_at_value(),
+                        }
+                    ]
+                }),
+                attrs! {
+                    At::Id => "upsampling_filter_select",
+                },
+                input_ev("change", |value| {
+                    let index = value.parse::<usize>().unwrap();
+                    wrap(Msg::UpsamplingFilterChanged(
+                        UpsamplingFilter::iter().nth(index).unwrap(),
+                    ))
+                })
+            ],
+            label![
+                attrs! {
+                    At::For => "chroma_siting_select"
+                },
+                "Chroma siting:"
+            ],
+            select![
+                ChromaSiting::iter().enumerate().map(|(i, chroma_siting)| {
+                    option![
+                        chroma_siting.name(),
+                        attrs! {
+                            At::Value => i,
+                            At::Selected => (chroma_siting == model.chroma_siting).as_at_value(),
+                        }
+                    ]
+                }),
+                attrs! {
+                    At::Id => "chroma_siting_select",
+                    At::Disabled => (model.upsampling_filter == UpsamplingFilter::Fancy).as_at_value(),
+                },
+                input_ev("change", |value| {
+                    let index = value.parse::<usize>().unwrap();
+                    wrap(Msg::ChromaSitingChanged(
+                        ChromaSiting::iter().nth(index).unwrap(),
+                    ))
+                })
+            ],
+            label![
+                attrs! {
+                    At::For => "deblocking_filter_select"
+                },
+                "Deblocking:"
+            ],
+            select![
                 option![
-                    "4:2:0",
+                    "Off",
                     attrs! {
-                        At::Value => "4:2:0"
+                        At::Value => 0,
+                        At::Selected => model.deblocking_filter.is_none().as_at_value(),
                     }
                 ],
+                DeblockingFilter::iter().enumerate().map(|(i, deblocking_filter)| {
+                    option![
+                        deblocking_filter.name(),
+                        attrs! {
+                            At::Value => i + 1,
+                            At::Selected => (Some(deblocking_filter) == model.deblocking_filter).as_at_value(),
+                        }
+                    ]
+                }),
+                attrs! {
+                    At::Id => "deblocking_filter_select",
+                },
+                input_ev("change", |value| {
+                    let index = value.parse::<usize>().unwrap();
+                    wrap(Msg::DeblockingFilterChanged(match index {
+                        0 => None,
+                        _ => DeblockingFilter::iter().nth(index - 1),
+                    }))
+                })
+            ],
+            label![
                 attrs! {
-                    At::Id => "subsampling_ratio_select"
+                    At::For => "quality_scaling_select"
+                },
+                "Quality scaling:"
+            ],
+            select![
+                QualityScaling::iter().enumerate().map(|(i, quality_scaling)| {
+                    option![
+                        quality_scaling.name(),
+                        attrs! {
+                            At::Value => i,
+                            At::Selected => (quality_scaling == model.quality_scaling).as_at_value(),
+                        }
+                    ]
+                }),
+                attrs! {
+                    At::Id => "quality_scaling_select",
+                    At::Disabled => model.use_source_coefficients.as_at_value(),
                 },
                 input_ev("change", |value| {
-                    let ratio_iter = value.split(':');
-                    let ratio_vec = ratio_iter.collect::<Vec<&str>>();
-                    let y_ratio = ratio_vec[0].parse::<i8>().unwrap();
-                    let cb_ratio = ratio_vec[1].parse::<i8>().unwrap();
-                    let cr_ratio = ratio_vec[2].parse::<i8>().unwrap();
-                    wrap(Msg::SubsamplingRatioChanged(y_ratio, cb_ratio, cr_ratio))
+                    let index = value.parse::<usize>().unwrap();
+                    wrap(Msg::QualityScalingChanged(
+                        QualityScaling::iter().nth(index).unwrap(),
+                    ))
                 })
             ],
             label![
@@ -610,41 +2370,306 @@ pub fn view_settings_sidebar(model: &Model) -> Node<GMsg> {
                     At::Value => model.quality,
                     At::Min => 0,
                     At::Id => "quality",
+                    At::Disabled => model.use_source_coefficients.as_at_value(),
                 },
                 input_ev("change", |value| {
                     wrap(Msg::QualityUpdated(value.parse::<u8>().unwrap()))
                 })
             ],
-            table![
-                C!["block-content"],
-                caption!["Luminance quantization table"],
-                (0..8).into_iter().map(|row| {
-                    tr![(0..8).into_iter().map(|col| {
-                        td![model.scaled_luminance_quant_table[row][col].to_string()]
-                    })]
+            label![
+                attrs! {
+                    At::For => "color_transform_select"
+                },
+                "Colour transform:"
+            ],
+            select![
+                ColorTransform::iter()
+                    .filter(ColorTransform::is_lossy_safe)
+                    .enumerate()
+                    .map(|(i, color_transform)| {
+                        option![
+                            color_transform.name(),
+                            attrs! {
+                                At::Value => i,
+                                At::Selected => (color_transform == model.color_transform).as_at_value(),
+                            }
+                        ]
+                    }),
+                attrs! {
+                    At::Id => "color_transform_select",
+                    At::Title => "Viewers decode every JFIF file as BT.601 full range, other transforms will show wrong colours there",
+                    At::Disabled => model.use_source_coefficients.as_at_value(),
+                },
+                input_ev("change", |value| {
+                    let index = value.parse::<usize>().unwrap();
+                    wrap(Msg::ColorTransformChanged(
+                        ColorTransform::iter()
+                            .filter(ColorTransform::is_lossy_safe)
+                            .nth(index)
+                            .unwrap(),
+                    ))
                 })
             ],
-            table![
-                C!["block-content"],
-                caption!["Chrominance quantization table"],
-                (0..8).into_iter().map(|row| {
-                    tr![(0..8).into_iter().map(|col| {
-                        td![model.scaled_chrominance_quant_table[row][col].to_string()]
-                    })]
+            label![
+                attrs! {
+                    At::For => "dct_algorithm_select"
+                },
+                "DCT:"
+            ],
+            select![
+                DctAlgorithm::iter().enumerate().map(|(i, dct_algorithm)| {
+                    option![
+                        dct_algorithm.name(),
+                        attrs! {
+                            At::Value => i,
+                            At::Selected => (dct_algorithm == model.dct_algorithm).as_at_value(),
+                        }
+                    ]
+                }),
+                attrs! {
+                    At::Id => "dct_algorithm_select",
+                },
+                input_ev("change", |value| {
+                    let index = value.parse::<usize>().unwrap();
+                    wrap(Msg::DctAlgorithmChanged(
+                        DctAlgorithm::iter().nth(index).unwrap(),
+                    ))
                 })
-            ]
+            ],
+            label![
+                attrs! {
+                    At::For => "quantization_strategy_select"
+                },
+                "Quantization:"
+            ],
+            select![
+                QuantizationStrategy::iter().enumerate().map(|(i, strategy)| {
+                    option![
+                        strategy.name(),
+                        attrs! {
+                            At::Value => i,
+                            At::Selected => (strategy == model.quantization_strategy).as_at_value(),
+                        }
+                    ]
+                }),
+                attrs! {
+                    At::Id => "quantization_strategy_select",
+                    At::Disabled => model.use_source_coefficients.as_at_value(),
+                },
+                input_ev("change", |value| {
+                    let index = value.parse::<usize>().unwrap();
+                    wrap(Msg::QuantizationStrategyChanged(
+                        QuantizationStrategy::iter().nth(index).unwrap(),
+                    ))
+                })
+            ],
+            IF!(model.quantization_strategy == QuantizationStrategy::Trellis => div![
+                C!["trellis_lambda"],
+                label![
+                    attrs! {
+                        At::For => "trellis_lambda"
+                    },
+                    "Lambda:"
+                ],
+                input![
+                    attrs! {
+                        At::Type => "number",
+                        At::Min => 0,
+                        At::Max => 10000,
+                        At::Value => model.trellis_lambda,
+                        At::Id => "trellis_lambda",
+                        At::Title => "Squared error of the coefficients a single bit is worth",
+                        At::Disabled => model.use_source_coefficients.as_at_value(),
+                    },
+                    input_ev("change", |value| {
+                        let lambda = value.parse::<u32>().ok()?.min(10000);
+                        Some(wrap(Msg::TrellisLambdaChanged(lambda)))
+                    })
+                ],
+            ]),
+            match &model.state {
+                State::ImageView(pack) if pack.source_jpeg.is_some() => div![
+                    C!["source_coefficients"],
+                    input![
+                        attrs! {
+                            At::Type => "checkbox",
+                            At::Id => "source_coefficients",
+                            At::Checked => model.use_source_coefficients.as_at_value(),
+                        },
+                        ev(Ev::Change, |_| wrap(Msg::SourceCoefficientsToggled)),
+                    ],
+                    label![
+                        attrs! {
+                            At::For => "source_coefficients"
+                        },
+                        "Use coefficients from the file"
+                    ],
+                ],
+                _ => empty![],
+            },
+            view_source_quality(model),
+            match &model.state {
+                State::ImageView(pack) => div![
+                    C!["encoded_size"],
+                    label![format!("Encoded size: {} B", pack.encoded_jpeg.len())],
+                    button![
+                        "Download .jpg",
+                        ev(Ev::Click, |_| wrap(Msg::EncodedJpegDownloadClicked)),
+                    ],
+                    label![
+                        input![
+                            attrs! {
+                                At::Type => "checkbox",
+                                At::Checked => model.optimize_coding.as_at_value(),
+                            },
+                            ev(Ev::Change, |_| wrap(Msg::OptimizeCodingToggled)),
+                        ],
+                        "Optimize Huffman tables",
+                    ],
+                ],
+                _ => empty![],
+            },
+            label![
+                attrs! {
+                    At::For => "quantization_preset_select"
+                },
+                "Load tables:"
+            ],
+            select![
+                option![
+                    "Choose a preset",
+                    attrs! {
+                        At::Value => "",
+                        At::Selected => true.as_at_value(),
+                    }
+                ],
+                QuantizationPreset::iter().enumerate().map(|(i, preset)| {
+                    option![
+                        preset.name(),
+                        attrs! {
+                            At::Value => i,
+                        }
+                    ]
+                }),
+                attrs! {
+                    At::Id => "quantization_preset_select",
+                    At::Disabled => model.use_source_coefficients.as_at_value(),
+                },
+                input_ev("change", |value| {
+                    let preset = QuantizationPreset::iter().nth(value.parse::<usize>().ok()?)?;
+                    Some(wrap(Msg::QuantizationPresetLoaded(preset)))
+                })
+            ],
+            view_quantization_table_editor(
+                model,
+                "Luminance quantization table",
+                QuantizationTableName::Luminance,
+                &model.scaled_luminance_quant_table
+            ),
+            view_quantization_table_editor(
+                model,
+                "Chrominance quantization table",
+                QuantizationTableName::Chrominance,
+                &model.scaled_chrominance_quant_table
+            ),
         ]
     ]
 }
 
+/// Quality the uploaded file was most likely saved with, judged by its quantization tables
+fn view_source_quality(model: &Model) -> Node<GMsg> {
+    let pack = match &model.state {
+        State::ImageView(pack) if !pack.source_quality.is_empty() => pack,
+        _ => return empty![],
+    };
+    let is_exact = pack
+        .source_quality
+        .iter()
+        .all(|(_, estimate)| estimate.is_exact());
+
+    div![
+        C!["source_quality"],
+        table![
+            C!["block-content quality_metrics"],
+            caption!["Estimated quality of the file"],
+            tr![
+                th![],
+                th!["IJG quality"],
+                th!["Matching values"],
+                th!["Max difference"]
+            ],
+            pack.source_quality.iter().map(|(channel, estimate)| {
+                tr![
+                    th![channel],
+                    td![estimate.quality.to_string()],
+                    td![format!("{}/64", estimate.matching_values)],
+                    td![estimate.max_difference.to_string()],
+                ]
+            }),
+        ],
+        p![if is_exact {
+            "The tables are the Annex K tables scaled by libjpeg"
+        } else {
+            "The tables aren't scaled Annex K tables, the quality of the closest ones is shown"
+        }],
+    ]
+}
+
+/// Quantization table whose values can be typed in, the tables of the file can't be edited
+fn view_quantization_table_editor(
+    model: &Model,
+    caption: &str,
+    table_name: QuantizationTableName,
+    table: &[[u8; 8]; 8],
+) -> Node<GMsg> {
+    table![
+        C!["block-content quantization_table_editor"],
+        caption![caption],
+        (0..8).map(|row| {
+            tr![(0..8).map(|col| {
+                td![input![
+                    attrs! {
+                        At::Type => "number",
+                        At::Min => 1,
+                        At::Max => 255,
+                        At::Value => table[row][col],
+                        At::Disabled => model.use_source_coefficients.as_at_value(),
+                    },
+                    input_ev("change", move |value| {
+                        let value = value.parse::<u8>().ok()?;
+                        Some(wrap(Msg::QuantizationTableEdited(
+                            table_name, row, col, value,
+                        )))
+                    })
+                ]]
+            })]
+        })
+    ]
+}
+
 pub fn view_jpeg_visualization(model: &Model) -> Node<GMsg> {
     div![
         view_settings_sidebar(&model),
         view_image_preview(&model),
         view_ycbcr(&model),
+        view_color_round_trip(model),
+        view_segment_inspector(model),
+        view_scan_stepper(model),
         view_dct_quantized(&model),
+        view_coefficient_editor(model),
+        view_dct_accuracy(model),
+        view_basis_explorer(model),
+        view_mcu_interleaving(model),
+        view_entropy_coding(model),
+        view_huffman_optimization(model),
         view_ycbcr_recovered(&model),
-        view_image_recovered(&model)
+        view_image_recovered(&model),
+        view_full_image(model),
+        view_grid_shift(model),
+        view_rate_distortion(model),
+        view_generation_loss(model),
+        view_quantization_strategies(model),
+        view_lossless(model)
     ]
 }
 
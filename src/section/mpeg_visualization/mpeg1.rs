@@ -1465,7 +1465,7 @@ fn copy_block_from_source(src: &[u8], mut index: usize, scan: usize) -> [u8; 64]
 
 #[rustfmt::skip]
 #[allow(non_snake_case, clippy::identity_op, clippy::erasing_op)]
-fn IDCT(block: &mut [i32; 64]) {
+pub(crate) fn IDCT(block: &mut [i32; 64]) {
     let (mut b1, mut b3, mut b4, mut b6, mut b7, mut tmp1, mut tmp2, mut m0,
     mut x0, mut x1, mut x2, mut x3, mut x4, mut y3, mut y4, mut y5, mut y6, mut y7);
 